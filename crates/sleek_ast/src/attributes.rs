@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use crate::{AttributeQuoteType, HtmlAttribute, Span};
+
+#[derive(Debug, Clone)]
+pub struct AttributeData {
+    pub data: Option<String>,
+    pub _quote_type: AttributeQuoteType,
+    /// The span of the attribute name in the source.
+    /// Empty for attributes that were not parsed, like those added with [`set_attribute`](crate::ElementRef::set_attribute).
+    pub name_span: Span,
+    /// The span of the value as written, including its quotes. `None` if the attribute has no value in the source.
+    pub value_span: Option<Span>,
+}
+
+impl AttributeData {
+    /// Create an attribute that was not parsed from a source.
+    pub fn new(data: Option<String>) -> Self {
+        Self {
+            data,
+            _quote_type: AttributeQuoteType::Double,
+            name_span: Span::over([0, 0], [0, 0]),
+            value_span: None,
+        }
+    }
+}
+
+impl From<HtmlAttribute> for AttributeData {
+    fn from(attribute: HtmlAttribute) -> Self {
+        Self {
+            data: attribute.value,
+            _quote_type: attribute.quote_type,
+            name_span: attribute.name_span,
+            value_span: attribute.value_span,
+        }
+    }
+}
+
+/// The attributes of an element, in the order they were written or added, with lookup by name.
+#[derive(Default, Clone)]
+pub struct AttributeMap {
+    entries: Vec<(String, AttributeData)>,
+    indices: HashMap<String, usize>,
+}
+
+impl std::fmt::Debug for AttributeMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl AttributeMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn get(&self, name: &str) -> Option<&AttributeData> {
+        self.indices.get(name).map(|&index| &self.entries[index].1)
+    }
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut AttributeData> {
+        self.indices
+            .get(name)
+            .map(|&index| &mut self.entries[index].1)
+    }
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.indices.contains_key(name)
+    }
+    /// Set an attribute, returning its previous data.
+    /// A new attribute is added last, an existing one keeps its position.
+    pub fn insert(&mut self, name: String, data: AttributeData) -> Option<AttributeData> {
+        match self.indices.get(&name) {
+            Some(&index) => Some(std::mem::replace(&mut self.entries[index].1, data)),
+            None => {
+                self.indices.insert(name.clone(), self.entries.len());
+                self.entries.push((name, data));
+                None
+            }
+        }
+    }
+    /// Remove an attribute, returning its data. The attributes after it keep their order.
+    pub fn remove(&mut self, name: &str) -> Option<AttributeData> {
+        let index = self.indices.remove(name)?;
+        let (_, data) = self.entries.remove(index);
+        for later in self.indices.values_mut() {
+            if *later > index {
+                *later -= 1;
+            }
+        }
+        Some(data)
+    }
+    /// Iterate over the attributes in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AttributeData)> {
+        self.entries.iter().map(|(name, data)| (name, data))
+    }
+    /// Iterate over the attribute names in order.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(name, _)| name)
+    }
+    /// Iterate over the data of the attributes in order, to change it.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut AttributeData> {
+        self.entries.iter_mut().map(|(_, data)| data)
+    }
+}
+
+impl<'a> IntoIterator for &'a AttributeMap {
+    type Item = (&'a String, &'a AttributeData);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, AttributeData)>,
+        fn(&'a (String, AttributeData)) -> (&'a String, &'a AttributeData),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter().map(|(name, data)| (name, data))
+    }
+}
+
+impl FromIterator<(String, AttributeData)> for AttributeMap {
+    fn from_iter<T: IntoIterator<Item = (String, AttributeData)>>(iter: T) -> Self {
+        let mut attributes = Self::new();
+        for (name, data) in iter {
+            attributes.insert(name, data);
+        }
+        attributes
+    }
+}
@@ -1,33 +1,27 @@
-use std::collections::HashMap;
-
 use sleek_utils::Node;
 
-use crate::{parse_selector, AttributeQuoteType, HtmlAttribute, HtmlTextNode, Span};
+use crate::{parse_selector, AttributeData, AttributeMap, HtmlAttribute, HtmlTextNode, Span};
 
-use super::{ElementSpan, HtmlEventListener, HtmlNode, HtmlTag, Query};
+use super::{ElementSpan, HtmlEventListener, HtmlNode, HtmlTag, Namespace, Query};
 
 pub struct Element {
     pub name: HtmlTag,
+    pub namespace: Namespace,
     pub class_list: Vec<String>,
     pub refs: usize,
-    pub attributes: HashMap<String, AttributeData>,
+    pub attributes: AttributeMap,
     _listeners: Vec<HtmlEventListener>,
     pub location: ElementSpan,
     pub child_nodes: Vec<HtmlNode>,
     pub __parent: Option<*mut Self>,
 }
 
-#[derive(Debug)]
-pub struct AttributeData {
-    pub data: Option<String>,
-    pub _quote_type: AttributeQuoteType,
-}
-
 impl Element {
     pub fn new(name: HtmlTag) -> Self {
         Element {
             name,
-            attributes: HashMap::new(),
+            namespace: Namespace::Html,
+            attributes: AttributeMap::new(),
             refs: 0,
             class_list: vec![],
             _listeners: vec![],
@@ -39,13 +33,8 @@ impl Element {
     /// Manually Initialize the element to some set values.
     fn init(&mut self, attributes: Vec<HtmlAttribute>, start_tag_span: Span) {
         for attribute in attributes {
-            self.attributes.insert(
-                attribute.key,
-                AttributeData {
-                    data: attribute.value,
-                    _quote_type: attribute.quote_type,
-                },
-            );
+            self.attributes
+                .insert(attribute.key.clone(), AttributeData::from(attribute));
         }
 
         self.location.open_tag = start_tag_span;
@@ -120,6 +109,10 @@ impl ElementRef {
     pub fn element(&self) -> &mut Element {
         unsafe { &mut *self.__element }
     }
+    /// The address of the element, which identifies it while a reference to it is held.
+    pub(crate) fn address(&self) -> usize {
+        self.__element as usize
+    }
 }
 
 impl ElementRef {
@@ -127,6 +120,10 @@ impl ElementRef {
     pub fn tag_name(&self) -> &HtmlTag {
         &self.element().name
     }
+    /// Returns the namespace of the element.
+    pub fn namespace(&self) -> Namespace {
+        self.element().namespace
+    }
     /// Return the ending of the element in its original document.
     pub fn get_end(&self) -> [usize; 2] {
         if let Some(span) = &self.element().location.close_tag {
@@ -173,10 +170,7 @@ impl ElementRef {
     pub fn set_attribute(&mut self, name: &str, value: &str) {
         self.element().attributes.insert(
             name.to_string(),
-            AttributeData {
-                data: Some(value.to_string()),
-                _quote_type: AttributeQuoteType::Double,
-            },
+            AttributeData::new(Some(value.to_string())),
         );
 
         if name == "class" {
@@ -213,10 +207,7 @@ impl ElementRef {
 
         element.attributes.insert(
             "class".to_string(),
-            AttributeData {
-                data: Some(self.class_name() + " " + class_name),
-                _quote_type: AttributeQuoteType::Double,
-            },
+            AttributeData::new(Some(self.class_name() + " " + class_name)),
         );
     }
     /// Removes a class from the element class list if it exists.
@@ -239,6 +230,23 @@ impl ElementRef {
                 .for_each(|token| list.push(token.to_owned()));
         }
     }
+    /// Adds the attributes from a list that are not already set on the element.
+    /// Used when a tag like `<html>` or `<body>` appears more than once in a document.
+    pub fn merge_attributes(&mut self, attributes: Vec<HtmlAttribute>) {
+        let element = self.element();
+        let mut class_changed = false;
+        for attribute in attributes {
+            if !element.attributes.contains_key(&attribute.key) {
+                class_changed |= attribute.key == "class";
+                element
+                    .attributes
+                    .insert(attribute.key.clone(), AttributeData::from(attribute));
+            }
+        }
+        if class_changed {
+            self.update_class_list();
+        }
+    }
     /// Appends a text node to the element.
     pub fn append_text(&mut self, text_node: HtmlTextNode) {
         self.element().child_nodes.push(HtmlNode::Text(text_node));
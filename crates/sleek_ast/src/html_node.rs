@@ -1,6 +1,6 @@
 #![allow(unused)]
 
-use std::fmt::Debug;
+use std::{fmt::Debug, ops::Range};
 
 use sleek_utils::MutableCountRef;
 
@@ -8,22 +8,45 @@ use crate::DocTypeIdentifier;
 
 use super::ElementRef;
 
+/// Where something is in the source, as `[line, column]` positions with columns counted in characters,
+/// and as byte offsets for slicing the source. The end offset is exclusive.
+/// Use a [`LineIndex`](sleek_utils::LineIndex) of the source to find the UTF-16 columns of the positions.
 #[derive(Clone, PartialEq)]
 pub struct Span {
     pub start: [usize; 2],
     pub end: [usize; 2],
+    pub start_offset: usize,
+    pub end_offset: usize,
 }
 
 impl Debug for Span {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("[{:?}, {:?}]", self.start, self.end))
+        f.write_fmt(format_args!(
+            "[{:?}, {:?}] ({}..{})",
+            self.start, self.end, self.start_offset, self.end_offset
+        ))
     }
 }
 
 impl Span {
     /// Create a span with the given range.
     pub fn over(start: [usize; 2], end: [usize; 2]) -> Self {
-        Self { start, end }
+        Self {
+            start,
+            end,
+            start_offset: 0,
+            end_offset: 0,
+        }
+    }
+    /// Set the byte offsets of the span.
+    pub fn with_offsets(mut self, start: usize, end: usize) -> Self {
+        self.start_offset = start;
+        self.end_offset = end;
+        self
+    }
+    /// The byte range of the span, for slicing the source.
+    pub fn range(&self) -> Range<usize> {
+        self.start_offset..self.end_offset
     }
 }
 
@@ -36,10 +59,7 @@ pub struct ElementSpan {
 impl ElementSpan {
     pub fn empty() -> Self {
         ElementSpan {
-            open_tag: Span {
-                start: [0, 0],
-                end: [0, 0],
-            },
+            open_tag: Span::over([0, 0], [0, 0]),
             close_tag: None,
         }
     }
@@ -49,9 +69,32 @@ impl ElementSpan {
 pub struct HtmlDocType {
     pub name: String,
     pub r#type: Option<DocTypeIdentifier>,
+    pub public_identifier: Option<String>,
+    pub system_identifier: Option<String>,
     pub force_quirks: bool,
 }
 
+/// How closely a document follows the standard, decided by its doctype.
+/// Old doctypes, or no doctype at all, put a document in quirks mode, where styling emulates old browsers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentMode {
+    #[default]
+    NoQuirks,
+    /// Only the quirks of how line heights are calculated in table cells.
+    LimitedQuirks,
+    Quirks,
+}
+
+/// The namespace of an element. SVG and MathML elements are parsed with different rules from HTML ones,
+/// and can share names with them, like the `<title>` of an SVG image.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Namespace {
+    #[default]
+    Html,
+    Svg,
+    MathMl,
+}
+
 #[derive(Debug)]
 pub struct HtmlTextNode {
     pub content: String,
@@ -2,21 +2,25 @@
 #![allow(incomplete_features)]
 #![feature(return_position_impl_trait_in_trait)]
 
+mod attributes;
 mod element;
 mod event;
 mod html_node;
 mod query;
 mod selector;
+mod source;
 mod tag;
 mod tests;
 mod token;
 mod tree;
 
-pub use element::{AttributeData, ElementRef};
+pub use attributes::{AttributeData, AttributeMap};
+pub use element::ElementRef;
 pub use event::*;
 pub use html_node::*;
 pub use query::Query;
 pub use selector::*;
+pub use source::DocumentSource;
 pub use tag::HtmlTag;
 pub use token::{AttributeQuoteType, DocTypeIdentifier, HtmlAttribute, HtmlToken};
 pub use tree::HtmlDocument;
@@ -0,0 +1,598 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    iter::Peekable,
+    ops::Range,
+    str::CharIndices,
+};
+
+use crate::{element::Element, ElementRef, HtmlNode, HtmlTag, Namespace, Span};
+
+/// The source of a document parsed in lossless mode, with a copy of its nodes as they were parsed.
+///
+/// Writing the document back compares its nodes to the copy, and only rewrites the parts of the source that were edited.
+/// Everything else, like quote styles, attribute order, tag casing, whitespace and tokens the parser dropped, is kept as written.
+pub struct DocumentSource {
+    text: String,
+    /// The nodes at the root of the document, as parsed.
+    nodes: Vec<Original>,
+    /// The elements of the document as parsed, by their address.
+    elements: HashMap<usize, OriginalElement>,
+}
+
+/// A node as it was parsed.
+enum Original {
+    /// The element is kept alive by the copy, so that its address is not reused by a new element.
+    Element(ElementRef),
+    Text {
+        content: String,
+        range: Range<usize>,
+    },
+    Comment {
+        content: String,
+        range: Range<usize>,
+    },
+    DocType,
+}
+
+struct OriginalElement {
+    name: HtmlTag,
+    /// The attributes, sorted by name.
+    attributes: Vec<(String, Option<String>)>,
+    children: Vec<Original>,
+    /// The start tag, which is empty if the element was implied.
+    open_tag: Range<usize>,
+    close_tag: Option<Range<usize>>,
+    /// The source of the element and its descendants, if any of it was written.
+    extent: Option<Range<usize>>,
+}
+
+/// Identifies a node of the document, to find it in the copy.
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Element(usize),
+    Text(usize, usize),
+    Comment(usize, usize),
+    DocType,
+}
+
+/// A change to the source.
+struct Edit {
+    range: Range<usize>,
+    text: String,
+}
+
+/// An attribute in the source of a start tag, with ranges relative to the tag.
+struct RawAttribute<'a> {
+    name: &'a str,
+    /// The attribute from the start of its name to the end of its value.
+    range: Range<usize>,
+    /// The value with its quotes, and the quote.
+    value: Option<(Range<usize>, Option<char>)>,
+}
+
+impl Debug for DocumentSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DocumentSource")
+            .field("text", &self.text)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DocumentSource {
+    /// Keep the source of a document, with a copy of its nodes as they are now.
+    pub fn new(text: String, nodes: &[HtmlNode]) -> Self {
+        let mut source = DocumentSource {
+            text,
+            nodes: vec![],
+            elements: HashMap::new(),
+        };
+        source.nodes = source.copy(nodes).0;
+        source
+    }
+    /// The source text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    /// Write nodes back to HTML, keeping the source of what did not change since they were parsed.
+    pub fn write(&self, nodes: &[HtmlNode]) -> String {
+        let mut edits = vec![];
+        self.diff(nodes, &self.nodes, 0, false, &mut edits);
+        // Insertions come before the removals that start at the same place.
+        edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+
+        let mut output = String::with_capacity(self.text.len());
+        let mut cursor = 0;
+        for edit in edits {
+            // The edit is inside a part that was already replaced, e.g. an element that was parsed twice from one tag.
+            if edit.range.start < cursor {
+                continue;
+            }
+            output.push_str(&self.text[cursor..edit.range.start]);
+            output.push_str(&edit.text);
+            cursor = edit.range.end;
+        }
+        output.push_str(&self.text[cursor..]);
+        output
+    }
+
+    /// Copy nodes and their descendants, returning the copies with the extent of their source.
+    fn copy(&mut self, nodes: &[HtmlNode]) -> (Vec<Original>, Option<Range<usize>>) {
+        let mut originals = vec![];
+        let mut extent = None;
+        for node in nodes {
+            let (original, range) = match node {
+                HtmlNode::Element(element_ref) => {
+                    let element = element_ref.element();
+                    let (children, children_extent) = self.copy(&element.child_nodes);
+                    let open_tag = element.location.open_tag.range();
+                    let close_tag = element.location.close_tag.as_ref().map(Span::range);
+                    let range = [Some(open_tag.clone()), close_tag.clone(), children_extent]
+                        .into_iter()
+                        .flatten()
+                        .fold(None, union);
+                    let original = OriginalElement {
+                        name: element.name.clone(),
+                        attributes: attributes(element),
+                        children,
+                        open_tag,
+                        close_tag,
+                        extent: range.clone(),
+                    };
+                    self.elements.insert(element_ref.address(), original);
+                    (Original::Element(element_ref.clone()), range)
+                }
+                HtmlNode::Text(text) => {
+                    let range = text.span.range();
+                    let content = text.content.clone();
+                    (Original::Text { content, range }, Some(text.span.range()))
+                }
+                HtmlNode::Comment(comment) => {
+                    let range = comment.span.range();
+                    let content = comment.content.clone();
+                    (
+                        Original::Comment { content, range },
+                        Some(comment.span.range()),
+                    )
+                }
+                HtmlNode::DocType(_) => (Original::DocType, None),
+            };
+            extent = range.into_iter().fold(extent, union);
+            originals.push(original);
+        }
+        (originals, extent)
+    }
+
+    /// The part of the source that a node as parsed was written in.
+    fn extent(&self, node: &Original) -> Option<Range<usize>> {
+        match node {
+            Original::Element(element) => self.elements[&element.address()].extent.clone(),
+            Original::Text { range, .. } | Original::Comment { range, .. } => Some(range.clone()),
+            Original::DocType => None,
+        }
+    }
+
+    /// Find the edits that turn the nodes as parsed into the nodes as they are now.
+    /// `position` is where the nodes start in the source, where new nodes are written if there are none before them.
+    fn diff(
+        &self,
+        nodes: &[HtmlNode],
+        originals: &[Original],
+        mut position: usize,
+        raw: bool,
+        edits: &mut Vec<Edit>,
+    ) {
+        let keys: HashSet<Key> = nodes.iter().filter_map(Key::of).collect();
+        let mut kept = HashMap::new();
+        for original in originals {
+            let key = original.key();
+            if keys.contains(&key) {
+                kept.insert(key, original);
+            } else if let Some(range) = self.extent(original) {
+                edits.push(Edit::new(range, String::new()));
+            }
+        }
+        for node in nodes {
+            let Some(original) = Key::of(node).and_then(|key| kept.get(&key)) else {
+                let mut text = String::new();
+                write_node(node, raw, &mut text);
+                edits.push(Edit::new(position..position, text));
+                continue;
+            };
+            self.diff_node(node, original, position, raw, edits);
+            if let Some(range) = self.extent(original) {
+                position = range.end;
+            }
+        }
+    }
+
+    /// Find the edits to a node that was parsed, and to its descendants.
+    fn diff_node(
+        &self,
+        node: &HtmlNode,
+        original: &Original,
+        position: usize,
+        raw: bool,
+        edits: &mut Vec<Edit>,
+    ) {
+        match (node, original) {
+            (HtmlNode::Text(text), Original::Text { content, range })
+                if &text.content != content =>
+            {
+                let mut escaped = String::new();
+                write_text(&text.content, raw, &mut escaped);
+                edits.push(Edit::new(range.clone(), escaped));
+            }
+            (HtmlNode::Comment(comment), Original::Comment { content, range })
+                if &comment.content != content =>
+            {
+                edits.push(Edit::new(
+                    range.clone(),
+                    format!("<!--{}-->", comment.content),
+                ));
+            }
+            (HtmlNode::Element(element_ref), Original::Element(_)) => {
+                let element = element_ref.element();
+                let original = &self.elements[&element_ref.address()];
+                let changed =
+                    element.name != original.name || attributes(element) != original.attributes;
+                let start = match original.open_tag.is_empty() {
+                    // An implied element only gets a start tag if it is given a name or attributes.
+                    true => {
+                        let start = original
+                            .extent
+                            .as_ref()
+                            .map_or(position, |range| range.start);
+                        if changed {
+                            let mut tag = String::new();
+                            write_start_tag(element, &mut tag);
+                            edits.push(Edit::new(start..start, tag));
+                        }
+                        start
+                    }
+                    false => {
+                        if changed {
+                            self.diff_tags(element, original, edits);
+                        }
+                        original.open_tag.end
+                    }
+                };
+                let raw = is_raw_text(&element.name);
+                self.diff(&element.child_nodes, &original.children, start, raw, edits);
+            }
+            _ => {}
+        }
+    }
+
+    /// Find the edits to the tags of an element that was renamed, or whose attributes changed.
+    fn diff_tags(&self, element: &Element, original: &OriginalElement, edits: &mut Vec<Edit>) {
+        let open_tag = &self.text[original.open_tag.clone()];
+        let offset = original.open_tag.start;
+        let name_end = 1 + tag_name_length(&open_tag[1..]);
+        if element.name != original.name {
+            let name = element.name.to_string();
+            edits.push(Edit::new(offset + 1..offset + name_end, name.clone()));
+            if let Some(close_tag) = &original.close_tag {
+                let length = tag_name_length(&self.text[close_tag.start + 2..close_tag.end]);
+                edits.push(Edit::new(
+                    close_tag.start + 2..close_tag.start + 2 + length,
+                    name,
+                ));
+            }
+        }
+
+        let raw_attributes = raw_attributes(open_tag, name_end);
+        for attribute in &raw_attributes {
+            let current = element
+                .attributes
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(attribute.name));
+            let original = original
+                .attributes
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(attribute.name));
+            match (current, original) {
+                // Removed, with the whitespace before it.
+                (None, _) => {
+                    let start = open_tag[..attribute.range.start].trim_end().len();
+                    edits.push(Edit::new(
+                        offset + start..offset + attribute.range.end,
+                        String::new(),
+                    ));
+                }
+                (Some((_, current)), Some((_, original))) if &current.data == original => {}
+                (Some((_, current)), _) => {
+                    let (range, text) = match (&attribute.value, &current.data) {
+                        (Some((range, quote)), Some(value)) => {
+                            (range.clone(), quoted(value, *quote))
+                        }
+                        (Some((range, _)), None) => (
+                            attribute.range.start + attribute.name.len()..range.end,
+                            String::new(),
+                        ),
+                        (None, Some(value)) => (
+                            attribute.range.end..attribute.range.end,
+                            format!("={}", quoted(value, Some('"'))),
+                        ),
+                        (None, None) => continue,
+                    };
+                    edits.push(Edit::new(offset + range.start..offset + range.end, text));
+                }
+            }
+        }
+
+        // Attributes that are not in the tag are written after the last one, in the order they were added.
+        let end = raw_attributes
+            .last()
+            .map_or(name_end, |attribute| attribute.range.end);
+        let added: Vec<_> = element
+            .attributes
+            .iter()
+            .filter(|(name, attribute)| {
+                !raw_attributes
+                    .iter()
+                    .any(|raw| raw.name.eq_ignore_ascii_case(name))
+                    && !original
+                        .attributes
+                        .iter()
+                        .any(|(key, value)| key == *name && value == &attribute.data)
+            })
+            .collect();
+        let mut text = String::new();
+        for (name, attribute) in added {
+            write_attribute(name, &attribute.data, &mut text);
+        }
+        if !text.is_empty() {
+            edits.push(Edit::new(offset + end..offset + end, text));
+        }
+    }
+}
+
+impl Edit {
+    fn new(range: Range<usize>, text: String) -> Self {
+        Edit { range, text }
+    }
+}
+
+impl Key {
+    fn of(node: &HtmlNode) -> Option<Self> {
+        match node {
+            HtmlNode::Element(element) => Some(Key::Element(element.address())),
+            // Nodes that were not parsed have no source, so they cannot be confused with one.
+            HtmlNode::Text(text) if !text.span.range().is_empty() => {
+                Some(Key::Text(text.span.start_offset, text.span.end_offset))
+            }
+            HtmlNode::Comment(comment) if !comment.span.range().is_empty() => Some(Key::Comment(
+                comment.span.start_offset,
+                comment.span.end_offset,
+            )),
+            HtmlNode::DocType(_) => Some(Key::DocType),
+            _ => None,
+        }
+    }
+}
+
+impl Original {
+    fn key(&self) -> Key {
+        match self {
+            Original::Element(element) => Key::Element(element.address()),
+            Original::Text { range, .. } => Key::Text(range.start, range.end),
+            Original::Comment { range, .. } => Key::Comment(range.start, range.end),
+            Original::DocType => Key::DocType,
+        }
+    }
+}
+
+/// The smallest range that covers two ranges. Empty ranges, like the tags of implied elements, are not in the source.
+fn union(range: Option<Range<usize>>, other: Range<usize>) -> Option<Range<usize>> {
+    if other.is_empty() {
+        return range;
+    }
+    Some(match range {
+        Some(range) => range.start.min(other.start)..range.end.max(other.end),
+        None => other,
+    })
+}
+
+/// The attributes of an element, sorted by name.
+fn attributes(element: &Element) -> Vec<(String, Option<String>)> {
+    let mut attributes: Vec<_> = element
+        .attributes
+        .iter()
+        .map(|(name, attribute)| (name.clone(), attribute.data.clone()))
+        .collect();
+    attributes.sort();
+    attributes
+}
+
+/// The length of the tag name at the start of the source of a tag, after its `<` or `</`.
+fn tag_name_length(tag: &str) -> usize {
+    tag.find(|ch: char| ch.is_whitespace() || ch == '/' || ch == '>')
+        .unwrap_or(tag.len())
+}
+
+/// Find the attributes in the source of a start tag, after its name.
+fn raw_attributes(tag: &str, name_end: usize) -> Vec<RawAttribute<'_>> {
+    fn position(chars: &mut Peekable<CharIndices>, tag: &str) -> usize {
+        chars.peek().map_or(tag.len(), |(index, _)| *index)
+    }
+    let mut chars = tag.char_indices().peekable();
+    while chars.next_if(|(index, _)| *index < name_end).is_some() {}
+
+    let mut attributes = vec![];
+    loop {
+        while chars
+            .next_if(|(_, ch)| ch.is_whitespace() || *ch == '/')
+            .is_some()
+        {}
+        let start = match chars.next() {
+            Some((_, '>')) | None => break,
+            Some((start, _)) => start,
+        };
+        while chars
+            .next_if(|(_, ch)| !ch.is_whitespace() && !"/>=".contains(*ch))
+            .is_some()
+        {}
+        let name_end = position(&mut chars, tag);
+
+        let mut lookahead = chars.clone();
+        while lookahead.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+        let mut value = None;
+        if lookahead.next_if(|(_, ch)| *ch == '=').is_some() {
+            while lookahead.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+            chars = lookahead;
+            let value_start = position(&mut chars, tag);
+            let quote = chars
+                .next_if(|(_, ch)| *ch == '"' || *ch == '\'')
+                .map(|(_, ch)| ch);
+            match quote {
+                Some(quote) => {
+                    while chars.next_if(|(_, ch)| *ch != quote).is_some() {}
+                    chars.next();
+                }
+                None => {
+                    while chars
+                        .next_if(|(_, ch)| !ch.is_whitespace() && *ch != '>')
+                        .is_some()
+                    {}
+                }
+            }
+            value = Some((value_start..position(&mut chars, tag), quote));
+        }
+        attributes.push(RawAttribute {
+            name: &tag[start..name_end],
+            range: start..position(&mut chars, tag),
+            value,
+        });
+    }
+    attributes
+}
+
+/// Write a node that was not parsed, and its descendants.
+/// `raw` is whether the node is inside an element like `<script>`, whose text is not escaped.
+fn write_node(node: &HtmlNode, raw: bool, output: &mut String) {
+    match node {
+        HtmlNode::DocType(doctype) => {
+            output.push_str("<!DOCTYPE ");
+            output.push_str(&doctype.name);
+            let keyword = match &doctype.public_identifier {
+                Some(_) => " PUBLIC",
+                None => " SYSTEM",
+            };
+            if doctype.public_identifier.is_some() || doctype.system_identifier.is_some() {
+                output.push_str(keyword);
+            }
+            // Identifiers are not decoded, so they can only be quoted by the quote they don't contain.
+            for identifier in [&doctype.public_identifier, &doctype.system_identifier]
+                .into_iter()
+                .flatten()
+            {
+                let quote = match identifier.contains('"') {
+                    true => '\'',
+                    false => '"',
+                };
+                output.push(' ');
+                output.push(quote);
+                output.push_str(identifier);
+                output.push(quote);
+            }
+            output.push('>');
+        }
+        HtmlNode::Text(text) => write_text(&text.content, raw, output),
+        HtmlNode::Comment(comment) => {
+            output.push_str("<!--");
+            output.push_str(&comment.content);
+            output.push_str("-->");
+        }
+        HtmlNode::Element(element) => {
+            let element = element.element();
+            write_start_tag(element, output);
+            // Foreign elements without content are written self-closing, like `<circle/>`.
+            if element.namespace != Namespace::Html && element.child_nodes.is_empty() {
+                output.insert(output.len() - 1, '/');
+                return;
+            }
+            if element.name.is_void() {
+                return;
+            }
+            let raw = is_raw_text(&element.name);
+            for child in &element.child_nodes {
+                write_node(child, raw, output);
+            }
+            output.push_str("</");
+            output.push_str(&element.name.to_string());
+            output.push('>');
+        }
+    }
+}
+
+fn write_start_tag(element: &Element, output: &mut String) {
+    output.push('<');
+    output.push_str(&element.name.to_string());
+    for (name, attribute) in &element.attributes {
+        write_attribute(name, &attribute.data, output);
+    }
+    output.push('>');
+}
+
+fn write_attribute(name: &str, value: &Option<String>, output: &mut String) {
+    output.push(' ');
+    output.push_str(name);
+    if let Some(value) = value {
+        output.push('=');
+        output.push_str(&quoted(value, Some('"')));
+    }
+}
+
+/// Write text, escaping the characters that would be read as markup.
+fn write_text(text: &str, raw: bool, output: &mut String) {
+    if raw {
+        output.push_str(text);
+        return;
+    }
+    for ch in text.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '\u{a0}' => output.push_str("&nbsp;"),
+            ch => output.push(ch),
+        }
+    }
+}
+
+/// An attribute value in a quote, or unquoted if it was written without one and does not need it.
+fn quoted(value: &str, quote: Option<char>) -> String {
+    let needs_quote = value.is_empty()
+        || value.contains(|ch: char| ch.is_whitespace() || "\"'=<>`&".contains(ch));
+    let quote = match quote {
+        None if !needs_quote => return value.to_string(),
+        None => '"',
+        Some(quote) => quote,
+    };
+    let mut output = String::from(quote);
+    for ch in value.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '"' if quote == '"' => output.push_str("&quot;"),
+            '\'' if quote == '\'' => output.push_str("&#39;"),
+            ch => output.push(ch),
+        }
+    }
+    output.push(quote);
+    output
+}
+
+/// Check if the text of an element is written as is, without character references.
+fn is_raw_text(name: &HtmlTag) -> bool {
+    matches!(
+        name,
+        HtmlTag::Script
+            | HtmlTag::Style
+            | HtmlTag::Xmp
+            | HtmlTag::Iframe
+            | HtmlTag::Noembed
+            | HtmlTag::Noframes
+            | HtmlTag::Plaintext
+    )
+}
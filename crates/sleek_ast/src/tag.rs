@@ -308,6 +308,8 @@ impl HtmlTag {
             "abbr" => Self::Abbr,
             "acronym" => Self::Acronym,
             "address" => Self::Address,
+            "altGlyph" => Self::AltGlyph,
+            "altGlyphItem" => Self::AltGlyphItem,
             "applet" => Self::Applet,
             "area" => Self::Area,
             "article" => Self::Article,
@@ -16,6 +16,29 @@ mod tests {
         assert!(aside.matches(".sidebar.blue-bg.scrollable"))
     }
 
+    #[test]
+    fn it_keeps_attributes_in_insertion_order() {
+        let mut anchor = element!("a");
+        anchor.set_attribute("id", "a");
+        anchor.set_attribute("class", "b");
+        anchor.set_attribute("href", "c");
+        anchor.set_attribute("id", "d");
+        let names = |anchor: &ElementRef| {
+            anchor
+                .element()
+                .attributes
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&anchor), vec!["id", "class", "href"]);
+        assert_eq!(anchor.get_attribute("id").as_deref(), Some("d"));
+
+        anchor.remove_attribute("class");
+        assert_eq!(names(&anchor), vec!["id", "href"]);
+        assert_eq!(anchor.get_attribute("href").as_deref(), Some("c"));
+    }
+
     #[test]
     fn it_parses_id_and_class_selector() {
         let mut para = element!("p");
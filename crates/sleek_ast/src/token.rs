@@ -2,12 +2,15 @@ use crate::Span;
 
 use super::HtmlTag;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum HtmlToken {
     DocType {
         name: String,
         r#type: Option<DocTypeIdentifier>,
+        public_identifier: Option<String>,
+        system_identifier: Option<String>,
         force_quirks: bool,
+        span: Span,
     },
     OpeningTag {
         name: HtmlTag,
@@ -23,10 +26,6 @@ pub enum HtmlToken {
         content: String,
         span: Span,
     },
-    CharacterRef {
-        r#type: CharacterRef,
-        location: Span,
-    },
     Comment {
         content: String,
         span: Span,
@@ -46,26 +45,24 @@ impl HtmlToken {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HtmlAttribute {
     pub key: String,
     pub value: Option<String>,
     pub quote_type: AttributeQuoteType,
+    pub name_span: Span,
+    /// The span of the value, including its quotes.
+    pub value_span: Option<Span>,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum CharacterRef {
-    Amp,
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AttributeQuoteType {
     Single,
     Double,
     None,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DocTypeIdentifier {
     System,
     Public,
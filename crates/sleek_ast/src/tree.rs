@@ -1,15 +1,30 @@
 use sleek_utils::Node;
 
-use crate::{ElementRef, HtmlNode, Query};
+use crate::{DocumentMode, DocumentSource, ElementRef, HtmlNode, Query};
 
 #[derive(Debug, Default)]
 pub struct HtmlDocument {
     pub nodes: Vec<HtmlNode>,
+    /// The mode of the document, decided by its doctype.
+    pub mode: DocumentMode,
+    /// The source of the document, if it was parsed in lossless mode.
+    pub source: Option<DocumentSource>,
 }
 
 impl HtmlDocument {
     pub fn new() -> Self {
-        HtmlDocument { nodes: vec![] }
+        HtmlDocument {
+            nodes: vec![],
+            mode: DocumentMode::NoQuirks,
+            source: None,
+        }
+    }
+    /// Write the document back to HTML, changing only the parts of its source that were edited since it was parsed.
+    /// An unchanged document is written exactly as its source.
+    /// Returns `None` if the document was not parsed in lossless mode.
+    pub fn to_source(&self) -> Option<String> {
+        let source = self.source.as_ref()?;
+        Some(source.write(&self.nodes))
     }
 }
 
@@ -0,0 +1,198 @@
+use std::{iter::Peekable, str::Chars};
+
+/// A JSON value, read from the tokenizer fixtures.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// The members of an object, in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Returns the value of a member, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/// Read a JSON document. Returns `None` if it is not valid.
+pub fn parse(input: &str) -> Option<Json> {
+    let mut chars = input.chars().peekable();
+    let value = value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    chars.next().is_none().then_some(value)
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|ch| ch.is_ascii_whitespace()).is_some() {}
+}
+
+fn value(chars: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(chars);
+    match chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut members = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars);
+                if chars.next()? != '"' {
+                    return None;
+                }
+                let key = string(chars)?;
+                skip_whitespace(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                members.push((key, value(chars)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    '}' => return Some(Json::Object(members)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut values = vec![];
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Json::Array(values));
+            }
+            loop {
+                values.push(value(chars)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => continue,
+                    ']' => return Some(Json::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            string(chars).map(Json::String)
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(ch) =
+                chars.next_if(|ch| ch.is_ascii_alphanumeric() || "+-.".contains(*ch))
+            {
+                word.push(ch);
+            }
+            match word.as_str() {
+                "null" => Some(Json::Null),
+                "true" => Some(Json::Bool(true)),
+                "false" => Some(Json::Bool(false)),
+                number => number.parse().ok().map(Json::Number),
+            }
+        }
+    }
+}
+
+/// Read the rest of a string, after its opening quote.
+fn string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' => {
+                    let unit = code_unit(chars)?;
+                    // Characters outside the basic plane are written as a surrogate pair.
+                    let pair = match (0xD800..0xDC00).contains(&unit) && chars.peek() == Some(&'\\')
+                    {
+                        true => {
+                            let mut lookahead = chars.clone();
+                            lookahead.next();
+                            match lookahead.next() {
+                                Some('u') => {
+                                    let low = code_unit(&mut lookahead)?;
+                                    let joined = char::decode_utf16([unit, low]).next()?.ok();
+                                    if joined.is_some() {
+                                        *chars = lookahead;
+                                    }
+                                    joined
+                                }
+                                _ => None,
+                            }
+                        }
+                        false => None,
+                    };
+                    value.push(pair.unwrap_or_else(|| {
+                        char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+                    }));
+                }
+                ch => value.push(ch),
+            },
+            ch => value.push(ch),
+        }
+    }
+}
+
+/// Read the four hex digits of a `\u` escape.
+fn code_unit(chars: &mut Peekable<Chars>) -> Option<u16> {
+    let digits: String = (0..4).filter_map(|_| chars.next()).collect();
+    u16::from_str_radix(&digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Json};
+
+    #[test]
+    fn it_reads_nested_values() {
+        let json = parse(r#"{"a": [1, true, null], "b": "x\"é𝄞"}"#).unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![
+                Json::Number(1.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(json.get("b").and_then(Json::as_str), Some("x\"é𝄞"));
+        assert_eq!(
+            parse(r#""\ud834\udd1e""#),
+            Some(Json::String("𝄞".to_string()))
+        );
+        assert!(parse("[1, 2").is_none());
+    }
+}
@@ -0,0 +1,107 @@
+//! Runs the fixtures of the [html5lib-tests](https://github.com/html5lib/html5lib-tests) suite against the parser.
+//!
+//! Fixtures are in `fixtures/html5lib`: tokenizer cases in `tokenizer/*.test`, and tree construction cases in `tree-construction/*.dat`.
+//! Cases that are known to fail are listed in `expected-failures.txt`, so that the tests only fail when a case regresses,
+//! or when a case starts passing and should be removed from the list.
+//! Run with `--nocapture` to see the result of every case.
+//!
+//! The number of errors of a case is checked apart from its tokens or tree, as the case id followed by `:errors`,
+//! like `tokenizer/tags.test:3:errors`. The errors themselves are not compared, since their names differ from the fixtures.
+
+mod json;
+mod tokenizer;
+mod tree;
+
+use std::{collections::HashSet, fs, path::Path};
+
+const FIXTURES: &str = "fixtures/html5lib";
+
+/// The result of a case, named by its file and index, like `tokenizer/tags.test:3`.
+pub struct Outcome {
+    id: String,
+    failure: Option<String>,
+}
+
+impl Outcome {
+    fn pass(id: String) -> Self {
+        Outcome { id, failure: None }
+    }
+    fn fail(id: String, reason: &str) -> Self {
+        Outcome {
+            id,
+            failure: Some(reason.to_string()),
+        }
+    }
+    /// The id without the description that tokenizer cases have after it.
+    fn key(&self) -> &str {
+        self.id.split(' ').next().unwrap_or_default()
+    }
+}
+
+/// Run every fixture with an extension in a directory of the fixtures.
+fn run(directory: &str, extension: &str, run: fn(&str, &str) -> Vec<Outcome>) -> Vec<Outcome> {
+    let mut paths: Vec<_> = fs::read_dir(Path::new(FIXTURES).join(directory))
+        .unwrap()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .flat_map(|path| {
+            let source = fs::read_to_string(path).unwrap();
+            let name = path.file_name().unwrap().to_string_lossy();
+            run(&format!("{directory}/{name}"), &source)
+        })
+        .collect()
+}
+
+/// Report the outcomes, and panic if they differ from the expected failures.
+fn check(outcomes: Vec<Outcome>) {
+    let expected = fs::read_to_string(Path::new(FIXTURES).join("expected-failures.txt")).unwrap();
+    let expected: HashSet<&str> = expected
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut regressions = vec![];
+    let mut fixed = vec![];
+    for outcome in &outcomes {
+        let known = expected.contains(outcome.key());
+        match &outcome.failure {
+            None => {
+                println!("PASS {}", outcome.id);
+                if known {
+                    fixed.push(outcome.key());
+                }
+            }
+            Some(failure) => {
+                println!("FAIL {}\n  {failure}", outcome.id);
+                if !known {
+                    regressions.push(outcome.key());
+                }
+            }
+        }
+    }
+    let passed = outcomes
+        .iter()
+        .filter(|outcome| outcome.failure.is_none())
+        .count();
+    println!("{passed}/{} cases passed", outcomes.len());
+    assert!(regressions.is_empty(), "Cases failed: {regressions:?}");
+    assert!(
+        fixed.is_empty(),
+        "Cases passed, remove them from expected-failures.txt: {fixed:?}"
+    );
+}
+
+#[test]
+fn it_passes_html5lib_tokenizer_tests() {
+    check(run("tokenizer", "test", tokenizer::run));
+}
+
+#[test]
+fn it_passes_html5lib_tree_construction_tests() {
+    check(run("tree-construction", "dat", tree::run));
+}
@@ -0,0 +1,242 @@
+use std::mem::take;
+
+use sleek_ast::{HtmlTag, HtmlToken};
+use sleek_utils::{MatrixIterator, QueueIterator};
+
+use super::{json::Json, Outcome};
+use crate::{
+    html::tokenizer::{tokenize, TokenStore},
+    ParseOptions, Whitespace,
+};
+
+/// Run the cases of a tokenizer fixture, which is a JSON object with a list of `tests`.
+/// Every case is run once for each of its initial states.
+pub fn run(file: &str, source: &str) -> Vec<Outcome> {
+    let Some(fixture) = super::json::parse(source) else {
+        return vec![Outcome::fail(file.to_string(), "fixture is not valid JSON")];
+    };
+    let cases = fixture
+        .get("tests")
+        .and_then(Json::as_array)
+        .unwrap_or_default();
+    let mut outcomes = vec![];
+    for (index, case) in cases.iter().enumerate() {
+        let id = format!("{file}:{index}");
+        let description = case.get("description").and_then(Json::as_str);
+        let double_escaped = case.get("doubleEscaped").and_then(Json::as_bool) == Some(true);
+        let unescape = |text: &str| match double_escaped {
+            true => unescape(text),
+            false => text.to_string(),
+        };
+        let (Some(input), Some(output)) = (
+            case.get("input").and_then(Json::as_str),
+            case.get("output").and_then(Json::as_array),
+        ) else {
+            outcomes.push(Outcome::fail(id, "case has no input or output"));
+            continue;
+        };
+        let input = unescape(input);
+        let expected = coalesce(output.iter().map(|token| unescape_token(token, &unescape)));
+
+        let states: Vec<&str> = match case.get("initialStates").and_then(Json::as_array) {
+            Some(states) => states.iter().filter_map(Json::as_str).collect(),
+            None => vec!["Data state"],
+        };
+        let last_start_tag = case.get("lastStartTag").and_then(Json::as_str);
+        let expected_errors = case
+            .get("errors")
+            .and_then(Json::as_array)
+            .map_or(0, |errors| errors.len());
+        let mut failures = vec![];
+        let mut error_failures = vec![];
+        for state in states {
+            let Some((tokens, errors)) = tokenize_in(&input, state, last_start_tag) else {
+                failures.push(format!("{state} is not supported"));
+                continue;
+            };
+            if errors != expected_errors {
+                error_failures.push(format!(
+                    "{state}: expected {expected_errors} errors, found {errors}"
+                ));
+            }
+            let actual = coalesce(tokens.iter().filter_map(to_json));
+            if actual != expected {
+                failures.push(format!(
+                    "{state}\n    expected: {expected:?}\n    actual:   {actual:?}"
+                ));
+            }
+        }
+        let describe = |id: String| match description {
+            Some(description) => format!("{id} {description}"),
+            None => id,
+        };
+        for (id, failures) in [
+            (id.clone(), failures),
+            (format!("{id}:errors"), error_failures),
+        ] {
+            let id = describe(id);
+            outcomes.push(match failures.is_empty() {
+                true => Outcome::pass(id),
+                false => Outcome::fail(id, &failures.join("\n")),
+            });
+        }
+    }
+    outcomes
+}
+
+/// Tokenize the input, starting in one of the states of the standard, into its tokens and the number of errors.
+/// The tokenizer is put into the state the way the tree builder would, by starting it inside an element.
+fn tokenize_in(
+    input: &str,
+    state: &str,
+    last_start_tag: Option<&str>,
+) -> Option<(Vec<HtmlToken>, usize)> {
+    let context = match state {
+        "Data state" => None,
+        "RCDATA state" => Some(last_start_tag.unwrap_or("title")),
+        "RAWTEXT state" => Some(last_start_tag.unwrap_or("style")),
+        "Script data state" => Some(last_start_tag.unwrap_or("script")),
+        _ => return None,
+    };
+    let mut options = ParseOptions::new().whitespace(Whitespace::Preserve);
+    if let Some(context) = context {
+        options = options.fragment_context(HtmlTag::new(context.to_string()));
+    }
+    let mut token_store = TokenStore::new();
+    let mut reader = token_store.reader();
+    let chars = input.chars().inspect(move |&ch| reader.read(ch));
+    let mut iterator = QueueIterator::new(MatrixIterator::new(chars, '\n'));
+    token_store.configure(&options);
+    tokenize(&mut token_store, &mut iterator);
+    Some((take(&mut token_store.tokens), token_store.errors.len()))
+}
+
+/// Convert a token into the format of the fixtures, e.g. `["StartTag", "a", {"href": "/"}]`.
+fn to_json(token: &HtmlToken) -> Option<Json> {
+    let string = |value: &str| Json::String(value.to_string());
+    let token = match token {
+        HtmlToken::DocType {
+            name,
+            public_identifier,
+            system_identifier,
+            force_quirks,
+            ..
+        } => {
+            let identifier = |value: &Option<String>| value.as_deref().map_or(Json::Null, string);
+            vec![
+                string("DOCTYPE"),
+                match name.is_empty() {
+                    true => Json::Null,
+                    false => string(name),
+                },
+                identifier(public_identifier),
+                identifier(system_identifier),
+                Json::Bool(!force_quirks),
+            ]
+        }
+        HtmlToken::OpeningTag {
+            name,
+            attributes,
+            self_closing,
+            ..
+        } => {
+            let attributes = attributes
+                .iter()
+                .map(|attribute| {
+                    let value = attribute.value.as_deref().unwrap_or_default();
+                    (attribute.key.clone(), string(value))
+                })
+                .collect();
+            let mut token = vec![
+                string("StartTag"),
+                string(&tag_name(name)),
+                Json::Object(attributes),
+            ];
+            if *self_closing {
+                token.push(Json::Bool(true));
+            }
+            token
+        }
+        HtmlToken::ClosingTag { name, .. } => vec![string("EndTag"), string(&tag_name(name))],
+        HtmlToken::Text { content, .. } => vec![string("Character"), string(content)],
+        HtmlToken::Comment { content, .. } => vec![string("Comment"), string(content)],
+        HtmlToken::EOF { .. } => return None,
+    };
+    Some(Json::Array(token))
+}
+
+/// Tag names are always lowercase in tokens, even for elements like `foreignObject` that are written in camel case.
+fn tag_name(name: &HtmlTag) -> String {
+    name.to_string().to_ascii_lowercase()
+}
+
+/// Join neighbouring character tokens, since the fixtures do not say how text is split.
+/// Attributes are sorted, since their order in the fixtures' JSON objects has no meaning.
+fn coalesce(tokens: impl Iterator<Item = Json>) -> Vec<Json> {
+    let mut coalesced: Vec<Json> = vec![];
+    for mut token in tokens {
+        let Json::Array(parts) = &mut token else {
+            continue;
+        };
+        if let Some(Json::Object(attributes)) = parts.get_mut(2) {
+            attributes.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+        if let [Json::String(kind), Json::String(text)] = parts.as_slice() {
+            if kind == "Character" {
+                if let Some(Json::Array(last)) = coalesced.last_mut() {
+                    if let [Json::String(last_kind), Json::String(last_text)] = last.as_mut_slice()
+                    {
+                        if last_kind == "Character" {
+                            last_text.push_str(text);
+                            continue;
+                        }
+                    }
+                }
+            }
+        }
+        coalesced.push(token);
+    }
+    coalesced
+}
+
+/// Unescape the strings of an expected token.
+fn unescape_token(token: &Json, unescape: &impl Fn(&str) -> String) -> Json {
+    match token {
+        Json::String(value) => Json::String(unescape(value)),
+        Json::Array(values) => Json::Array(
+            values
+                .iter()
+                .map(|value| unescape_token(value, unescape))
+                .collect(),
+        ),
+        Json::Object(members) => Json::Object(
+            members
+                .iter()
+                .map(|(key, value)| (unescape(key), unescape_token(value, unescape)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// Decode the `\uXXXX` escapes of a double escaped string. Lone surrogates cannot be in a Rust string, so they become U+FFFD.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\\u") {
+        unescaped.push_str(&rest[..start]);
+        let digits = rest.get(start + 2..start + 6).unwrap_or_default();
+        match u32::from_str_radix(digits, 16) {
+            Ok(code) => {
+                unescaped.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                rest = &rest[start + 6..];
+            }
+            Err(_) => {
+                unescaped.push_str("\\u");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
@@ -0,0 +1,196 @@
+use std::fmt::Write;
+
+use sleek_ast::{HtmlDocument, HtmlNode, HtmlTag, Namespace};
+
+use super::Outcome;
+use crate::{parse_html_fragment, parse_html_input, ParseMode, ParseOptions, Whitespace};
+
+/// A case of a tree construction fixture.
+#[derive(Default)]
+struct Case {
+    data: String,
+    fragment: Option<String>,
+    scripting: bool,
+    /// The number of errors expected, from the `#errors` and `#new-errors` sections.
+    errors: usize,
+    /// The expected tree, as lines starting with `| `.
+    document: String,
+}
+
+/// Run the cases of a tree construction fixture, in every parse mode.
+pub fn run(file: &str, source: &str) -> Vec<Outcome> {
+    cases(source)
+        .enumerate()
+        .flat_map(|(index, case)| {
+            let id = format!("{file}:{index}");
+            let mut failures = vec![];
+            let mut error_failures = vec![];
+            for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+                let options = ParseOptions::new()
+                    .mode(mode)
+                    .scripting(case.scripting)
+                    .whitespace(Whitespace::Preserve);
+                let res = match &case.fragment {
+                    // Fragments in foreign content, like `svg path`, are not supported.
+                    Some(context) if context.contains(' ') => {
+                        failures.push(format!("context {context} is not supported"));
+                        error_failures.push(format!("context {context} is not supported"));
+                        break;
+                    }
+                    Some(context) => {
+                        let context = HtmlTag::new(context.to_string());
+                        parse_html_fragment(&case.data, context, options)
+                    }
+                    None => parse_html_input(&case.data, options),
+                };
+                if res.errors.len() != case.errors {
+                    error_failures.push(format!(
+                        "{mode:?} mode: expected {} errors, found {}",
+                        case.errors,
+                        res.errors.len()
+                    ));
+                }
+                let actual = dump(&res.tree);
+                if actual != case.document {
+                    failures.push(format!(
+                        "{mode:?} mode\n{}\n  expected:\n{}\n  actual:\n{actual}",
+                        case.data, case.document
+                    ));
+                }
+            }
+            [
+                (id.clone(), failures),
+                (format!("{id}:errors"), error_failures),
+            ]
+        })
+        .map(|(id, failures)| match failures.is_empty() {
+            true => Outcome::pass(id),
+            false => Outcome::fail(id, &failures.join("\n")),
+        })
+        .collect()
+}
+
+/// Split a fixture into its cases. Each starts with a `#data` line, followed by sections like `#errors` and `#document`.
+fn cases(source: &str) -> impl Iterator<Item = Case> + '_ {
+    source
+        .split("#data\n")
+        .filter(|case| !case.is_empty())
+        .map(|case| {
+            let mut parsed = Case::default();
+            let mut section = "data";
+            let mut lines = vec![];
+            for line in case.split('\n') {
+                match line.strip_prefix('#') {
+                    Some(name)
+                        if matches!(
+                            name,
+                            "errors"
+                                | "new-errors"
+                                | "document"
+                                | "document-fragment"
+                                | "script-on"
+                                | "script-off"
+                        ) =>
+                    {
+                        parsed.finish(section, &mut lines);
+                        section = name;
+                        parsed.scripting |= name == "script-on";
+                    }
+                    _ => lines.push(line),
+                }
+            }
+            parsed.finish(section, &mut lines);
+            parsed
+        })
+}
+
+impl Case {
+    /// Store the lines of a section that has ended.
+    fn finish(&mut self, section: &str, lines: &mut Vec<&str>) {
+        match section {
+            // The newline before the next section is not part of the input.
+            "data" => self.data = lines.join("\n"),
+            "errors" | "new-errors" => {
+                self.errors += lines.iter().filter(|line| !line.is_empty()).count()
+            }
+            "document-fragment" => self.fragment = lines.first().map(|line| line.to_string()),
+            "document" => {
+                // Cases are separated by a blank line.
+                while lines.last() == Some(&"") {
+                    lines.pop();
+                }
+                self.document = lines.join("\n");
+            }
+            _ => {}
+        }
+        lines.clear();
+    }
+}
+
+/// Write a tree in the format of the fixtures, with a line for every node, indented by its depth.
+pub fn dump(tree: &HtmlDocument) -> String {
+    let mut output = String::new();
+    dump_nodes(&tree.nodes, 0, &mut output);
+    output.pop();
+    output
+}
+
+fn dump_nodes(nodes: &[HtmlNode], depth: usize, output: &mut String) {
+    let indent = "  ".repeat(depth);
+    for node in nodes {
+        let _ = match node {
+            HtmlNode::DocType(doctype) => {
+                match (&doctype.public_identifier, &doctype.system_identifier) {
+                    (None, None) => writeln!(output, "| {indent}<!DOCTYPE {}>", doctype.name),
+                    (public, system) => writeln!(
+                        output,
+                        "| {indent}<!DOCTYPE {} \"{}\" \"{}\">",
+                        doctype.name,
+                        public.as_deref().unwrap_or_default(),
+                        system.as_deref().unwrap_or_default()
+                    ),
+                }
+            }
+            HtmlNode::Text(text) => writeln!(output, "| {indent}\"{}\"", text.content),
+            HtmlNode::Comment(comment) => {
+                writeln!(output, "| {indent}<!-- {} -->", comment.content)
+            }
+            HtmlNode::Element(element) => {
+                let element = element.element();
+                let prefix = match element.namespace {
+                    Namespace::Html => "",
+                    Namespace::Svg => "svg ",
+                    Namespace::MathMl => "math ",
+                };
+                let _ = writeln!(output, "| {indent}<{prefix}{}>", element.name);
+                let foreign = element.namespace != Namespace::Html;
+                let mut attributes: Vec<_> = element
+                    .attributes
+                    .iter()
+                    .map(|(name, attribute)| match foreign {
+                        true => (attribute_name(name), attribute),
+                        false => (name.clone(), attribute),
+                    })
+                    .collect();
+                attributes.sort_by(|a, b| a.0.cmp(&b.0));
+                for (name, attribute) in attributes {
+                    let value = attribute.data.as_deref().unwrap_or_default();
+                    let _ = writeln!(output, "| {indent}  {name}=\"{value}\"");
+                }
+                dump_nodes(&element.child_nodes, depth + 1, output);
+                Ok(())
+            }
+        };
+    }
+}
+
+/// The name of an attribute of a foreign element, with the namespace of attributes like `xlink:href` written as `xlink href`.
+fn attribute_name(name: &str) -> String {
+    match name {
+        "xlink:actuate" | "xlink:arcrole" | "xlink:href" | "xlink:role" | "xlink:show"
+        | "xlink:title" | "xlink:type" | "xml:lang" | "xml:space" | "xmlns:xlink" => {
+            name.replacen(':', " ", 1)
+        }
+        name => name.to_string(),
+    }
+}
@@ -0,0 +1,424 @@
+use std::fmt::{self, Display, Write};
+
+use super::{HtmlParseError, HtmlParseErrorType as ErrorType};
+
+/// How serious an error is. Warnings are errors that browsers recover from without changing the meaning of the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// How a diagnostic is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticStyle {
+    Plain,
+    /// Colored with ANSI escape codes, for terminals.
+    Ansi,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+
+impl ErrorType {
+    /// A code for the error that does not change between versions, e.g. `E0003` for [`ErrorType::UnexpectedCharacter`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorType::InvalidCharacter => "E0001",
+            ErrorType::UnexpectedEndOfInput => "E0002",
+            ErrorType::UnexpectedCharacter(_) => "E0003",
+            ErrorType::ExpectedTagName => "E0004",
+            ErrorType::UnclosedComment => "E0005",
+            ErrorType::IndecipherableDocType => "E0006",
+            ErrorType::SelfClosingNonVoidTag => "E0007",
+            ErrorType::VoidElementEndTag(_) => "E0008",
+            ErrorType::UnclosedTag(_) => "E0009",
+            ErrorType::UnexpectedCloseTag(_) => "E0010",
+            ErrorType::UnexpectedOpenTag(_) => "E0011",
+            ErrorType::UnexpectedDocType => "E0012",
+            ErrorType::MissingSemicolonAfterCharacterReference => "E0013",
+            ErrorType::UnknownNamedCharacterReference => "E0014",
+            ErrorType::AbsenceOfDigitsInNumericCharacterReference => "E0015",
+            ErrorType::NullCharacterReference => "E0016",
+            ErrorType::CharacterReferenceOutsideUnicodeRange => "E0017",
+            ErrorType::SurrogateCharacterReference => "E0018",
+            ErrorType::NoncharacterCharacterReference => "E0019",
+            ErrorType::ControlCharacterReference => "E0020",
+            ErrorType::AbruptClosingOfEmptyComment => "E0021",
+            ErrorType::AbruptDoctypePublicIdentifier => "E0022",
+            ErrorType::AbruptDoctypeSystemIdentifier => "E0023",
+            ErrorType::CdataInHtmlContent => "E0024",
+            ErrorType::ControlCharacterInInputStream => "E0025",
+            ErrorType::DuplicateAttribute => "E0026",
+            ErrorType::EndTagWithAttributes => "E0027",
+            ErrorType::EndTagWithTrailingSolidus => "E0028",
+            ErrorType::EofBeforeTagName => "E0029",
+            ErrorType::EofInCdata => "E0030",
+            ErrorType::EofInDoctype => "E0031",
+            ErrorType::EofInScriptHtmlCommentLikeText => "E0032",
+            ErrorType::EofInTag => "E0033",
+            ErrorType::IncorrectlyClosedComment => "E0034",
+            ErrorType::IncorrectlyOpenedComment => "E0035",
+            ErrorType::InvalidFirstCharacterOfTagName => "E0036",
+            ErrorType::MissingAttributeValue => "E0037",
+            ErrorType::MissingDoctypeName => "E0038",
+            ErrorType::MissingDoctypePublicIdentifier => "E0039",
+            ErrorType::MissingDoctypeSystemIdentifier => "E0040",
+            ErrorType::MissingQuoteBeforeDoctypePublicIdentifier => "E0041",
+            ErrorType::MissingQuoteBeforeDoctypeSystemIdentifier => "E0042",
+            ErrorType::MissingWhitespaceAfterDoctypePublicKeyword => "E0043",
+            ErrorType::MissingWhitespaceAfterDoctypeSystemKeyword => "E0044",
+            ErrorType::MissingWhitespaceBeforeDoctypeName => "E0045",
+            ErrorType::MissingWhitespaceBetweenAttributes => "E0046",
+            ErrorType::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => "E0047",
+            ErrorType::NestedComment => "E0048",
+            ErrorType::NoncharacterInInputStream => "E0049",
+            ErrorType::UnexpectedCharacterAfterDoctypeSystemIdentifier => "E0051",
+            ErrorType::UnexpectedCharacterInAttributeName => "E0052",
+            ErrorType::UnexpectedCharacterInUnquotedAttributeValue => "E0053",
+            ErrorType::UnexpectedEqualsSignBeforeAttributeName => "E0054",
+            ErrorType::UnexpectedQuestionMarkInsteadOfTagName => "E0055",
+            ErrorType::UnexpectedSolidusInTag => "E0056",
+        }
+    }
+
+    /// A short description of the error.
+    pub fn message(&self) -> String {
+        match self {
+            ErrorType::InvalidCharacter => "unexpected null character".to_string(),
+            ErrorType::UnexpectedEndOfInput => "unexpected end of input".to_string(),
+            ErrorType::UnexpectedCharacter(ch) => {
+                format!("unexpected character `{}`", ch.escape_debug())
+            }
+            ErrorType::ExpectedTagName => "expected a tag name".to_string(),
+            ErrorType::UnclosedComment => "comment is not closed".to_string(),
+            ErrorType::IndecipherableDocType => "doctype could not be read".to_string(),
+            ErrorType::SelfClosingNonVoidTag => "non-void element used as self-closing".to_string(),
+            ErrorType::VoidElementEndTag(tag) => format!("end tag for void element `{tag}`"),
+            ErrorType::UnclosedTag(tag) => format!("`<{tag}>` is not closed"),
+            ErrorType::UnexpectedCloseTag(tag) => format!("unexpected end tag `</{tag}>`"),
+            ErrorType::UnexpectedOpenTag(tag) => format!("unexpected start tag `<{tag}>`"),
+            ErrorType::UnexpectedDocType => "unexpected doctype".to_string(),
+            ErrorType::MissingSemicolonAfterCharacterReference => {
+                "missing semicolon after character reference".to_string()
+            }
+            ErrorType::UnknownNamedCharacterReference => {
+                "unknown named character reference".to_string()
+            }
+            ErrorType::AbsenceOfDigitsInNumericCharacterReference => {
+                "numeric character reference has no digits".to_string()
+            }
+            ErrorType::NullCharacterReference => "character reference to null".to_string(),
+            ErrorType::CharacterReferenceOutsideUnicodeRange => {
+                "character reference outside the unicode range".to_string()
+            }
+            ErrorType::SurrogateCharacterReference => {
+                "character reference to a surrogate".to_string()
+            }
+            ErrorType::NoncharacterCharacterReference => {
+                "character reference to a noncharacter".to_string()
+            }
+            ErrorType::ControlCharacterReference => {
+                "character reference to a control character".to_string()
+            }
+            ErrorType::AbruptClosingOfEmptyComment => "empty comment closed with `>`".to_string(),
+            ErrorType::AbruptDoctypePublicIdentifier => {
+                "doctype public identifier ended with `>`".to_string()
+            }
+            ErrorType::AbruptDoctypeSystemIdentifier => {
+                "doctype system identifier ended with `>`".to_string()
+            }
+            ErrorType::CdataInHtmlContent => "CDATA section outside of foreign content".to_string(),
+            ErrorType::ControlCharacterInInputStream => {
+                "control character in the input".to_string()
+            }
+            ErrorType::DuplicateAttribute => "attribute is repeated".to_string(),
+            ErrorType::EndTagWithAttributes => "end tag has attributes".to_string(),
+            ErrorType::EndTagWithTrailingSolidus => "end tag ends with `/>`".to_string(),
+            ErrorType::EofBeforeTagName => "input ended before the tag name".to_string(),
+            ErrorType::EofInCdata => "input ended in a CDATA section".to_string(),
+            ErrorType::EofInDoctype => "input ended in a doctype".to_string(),
+            ErrorType::EofInScriptHtmlCommentLikeText => {
+                "input ended in a comment in a script".to_string()
+            }
+            ErrorType::EofInTag => "input ended in a tag".to_string(),
+            ErrorType::IncorrectlyClosedComment => "comment closed with `--!>`".to_string(),
+            ErrorType::IncorrectlyOpenedComment => "comment not opened with `<!--`".to_string(),
+            ErrorType::InvalidFirstCharacterOfTagName => {
+                "tag name does not start with a letter".to_string()
+            }
+            ErrorType::MissingAttributeValue => "attribute has `=` but no value".to_string(),
+            ErrorType::MissingDoctypeName => "doctype has no name".to_string(),
+            ErrorType::MissingDoctypePublicIdentifier => {
+                "doctype public identifier is missing".to_string()
+            }
+            ErrorType::MissingDoctypeSystemIdentifier => {
+                "doctype system identifier is missing".to_string()
+            }
+            ErrorType::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "doctype public identifier is not quoted".to_string()
+            }
+            ErrorType::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "doctype system identifier is not quoted".to_string()
+            }
+            ErrorType::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing whitespace after `PUBLIC`".to_string()
+            }
+            ErrorType::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing whitespace after `SYSTEM`".to_string()
+            }
+            ErrorType::MissingWhitespaceBeforeDoctypeName => {
+                "missing whitespace before the doctype name".to_string()
+            }
+            ErrorType::MissingWhitespaceBetweenAttributes => {
+                "missing whitespace between attributes".to_string()
+            }
+            ErrorType::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing whitespace between the doctype identifiers".to_string()
+            }
+            ErrorType::NestedComment => "comment opened inside a comment".to_string(),
+            ErrorType::NoncharacterInInputStream => "noncharacter in the input".to_string(),
+            ErrorType::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected character after the doctype system identifier".to_string()
+            }
+            ErrorType::UnexpectedCharacterInAttributeName => {
+                "unexpected character in an attribute name".to_string()
+            }
+            ErrorType::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected character in an unquoted attribute value".to_string()
+            }
+            ErrorType::UnexpectedEqualsSignBeforeAttributeName => {
+                "attribute name starts with `=`".to_string()
+            }
+            ErrorType::UnexpectedQuestionMarkInsteadOfTagName => {
+                "processing instructions are not supported in html".to_string()
+            }
+            ErrorType::UnexpectedSolidusInTag => "unexpected `/` in a tag".to_string(),
+        }
+    }
+
+    /// Whether the error changes the meaning of the document, or is only a warning.
+    pub fn severity(&self) -> Severity {
+        match self {
+            ErrorType::SelfClosingNonVoidTag
+            | ErrorType::VoidElementEndTag(_)
+            | ErrorType::MissingSemicolonAfterCharacterReference
+            | ErrorType::NoncharacterCharacterReference
+            | ErrorType::ControlCharacterReference
+            | ErrorType::EndTagWithTrailingSolidus
+            | ErrorType::MissingWhitespaceAfterDoctypePublicKeyword
+            | ErrorType::MissingWhitespaceAfterDoctypeSystemKeyword
+            | ErrorType::MissingWhitespaceBeforeDoctypeName
+            | ErrorType::MissingWhitespaceBetweenAttributes
+            | ErrorType::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers
+            | ErrorType::NestedComment
+            | ErrorType::ControlCharacterInInputStream
+            | ErrorType::NoncharacterInInputStream => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    /// A suggestion for fixing the error, if there is a common one.
+    pub fn help(&self) -> Option<&'static str> {
+        match self {
+            ErrorType::InvalidCharacter => {
+                Some("null characters are not allowed in html, remove it")
+            }
+            ErrorType::SelfClosingNonVoidTag => {
+                Some("the `/` is ignored, so the element stays open. Add an end tag instead")
+            }
+            ErrorType::VoidElementEndTag(_) => {
+                Some("void elements cannot have content, remove the end tag")
+            }
+            ErrorType::UnclosedTag(_) => Some("add an end tag where the element should end"),
+            ErrorType::UnclosedComment => Some("end the comment with `-->`"),
+            ErrorType::MissingSemicolonAfterCharacterReference => {
+                Some("add a `;` after the reference")
+            }
+            ErrorType::UnknownNamedCharacterReference => {
+                Some("to write a literal `&`, use `&amp;`")
+            }
+            ErrorType::UnexpectedCharacter('<') => Some("to write a literal `<`, use `&lt;`"),
+            ErrorType::UnexpectedDocType => {
+                Some("the doctype must be the first thing in the document")
+            }
+            ErrorType::DuplicateAttribute => {
+                Some("only the first value is used, remove the others")
+            }
+            ErrorType::IncorrectlyOpenedComment => Some("start the comment with `<!--`"),
+            ErrorType::IncorrectlyClosedComment => Some("end the comment with `-->`"),
+            ErrorType::InvalidFirstCharacterOfTagName
+            | ErrorType::EofBeforeTagName
+            | ErrorType::UnexpectedCharacterInUnquotedAttributeValue => {
+                Some("to write a literal `<`, use `&lt;`")
+            }
+            ErrorType::UnexpectedQuestionMarkInsteadOfTagName => {
+                Some("the instruction is parsed as a comment, remove it")
+            }
+            ErrorType::EndTagWithAttributes => {
+                Some("attributes of end tags are ignored, remove them")
+            }
+            ErrorType::MissingAttributeValue => Some("quote the value, or remove the `=`"),
+            _ => None,
+        }
+    }
+}
+
+impl Display for HtmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [line, column] = self.location;
+        write!(
+            f,
+            "{}[{}]: {} at {line}:{column}",
+            self.error_type.severity(),
+            self.error_type.code(),
+            self.error_type.message()
+        )
+    }
+}
+
+impl HtmlParseError {
+    /// Render the error with the line of the source it is on, pointing at where it happened.
+    /// # Examples
+    /// ```
+    /// use sleek_parser::{parse_html_input, DiagnosticStyle, ParseOptions};
+    ///
+    /// let source = "<p>\n<div/></p>";
+    /// let result = parse_html_input(source, ParseOptions::new());
+    /// let rendered = result.errors[0].render(source, DiagnosticStyle::Plain);
+    /// assert!(rendered.starts_with("warning[E0007]: non-void element used as self-closing"));
+    /// ```
+    pub fn render(&self, source: &str, style: DiagnosticStyle) -> String {
+        let paint = |color: &'static str| match style {
+            DiagnosticStyle::Plain => ("", ""),
+            DiagnosticStyle::Ansi => (color, RESET),
+        };
+        let severity = self.error_type.severity();
+        let (level, reset) = paint(match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        });
+        let (bold, _) = paint(BOLD);
+        let (gutter, _) = paint(BLUE);
+
+        let [line_number, column] = self.location;
+        let line = source
+            .lines()
+            .nth(line_number.saturating_sub(1))
+            .unwrap_or_default();
+        let number = line_number.to_string();
+        let padding = " ".repeat(number.len());
+
+        let mut output = String::new();
+        let code = self.error_type.code();
+        let message = self.error_type.message();
+        let _ = writeln!(
+            output,
+            "{level}{severity}[{code}]{reset}{bold}: {message}{reset}"
+        );
+        let _ = writeln!(output, "{padding}{gutter}-->{reset} {line_number}:{column}");
+        let _ = writeln!(output, "{padding} {gutter}|{reset}");
+        let _ = writeln!(output, "{gutter}{number} |{reset} {line}");
+
+        // Underline a whole tag or character reference when the error is at its start, or point at a single character.
+        let start = column.saturating_sub(1).min(line.chars().count());
+        let rest: String = line.chars().skip(start).collect();
+        let width = match rest.chars().next() {
+            Some('<') => rest.find('>').map_or(1, |end| rest[..=end].chars().count()),
+            Some('&') => {
+                let name = rest[1..]
+                    .chars()
+                    .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '#')
+                    .count();
+                1 + name + usize::from(rest[1 + name..].starts_with(';'))
+            }
+            _ => 1,
+        };
+        let indent: String = line
+            .chars()
+            .take(start)
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let _ = write!(
+            output,
+            "{padding} {gutter}|{reset} {indent}{level}{}{reset}",
+            "^".repeat(width)
+        );
+        if let Some(help) = self.error_type.help() {
+            let _ = write!(
+                output,
+                "\n{padding} {gutter}={reset} {bold}help{reset}: {help}"
+            );
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sleek_ast::HtmlTag;
+
+    use super::{DiagnosticStyle, HtmlParseError};
+    use crate::{html::HtmlParseErrorType, parse_html_input, ParseOptions};
+
+    #[test]
+    fn it_renders_errors_with_the_source_line() {
+        let error = HtmlParseError {
+            error_type: HtmlParseErrorType::UnexpectedCloseTag(HtmlTag::Span),
+            location: [2, 7],
+            offset: 12,
+        };
+        let source = "<div>\n  <p>a</span></p>\n</div>";
+        assert_eq!(
+            error.render(source, DiagnosticStyle::Plain),
+            "error[E0010]: unexpected end tag `</span>`\n \
+             --> 2:7\n  \
+             |\n\
+             2 |   <p>a</span></p>\n  \
+             |       ^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn it_renders_help_and_colors() {
+        let error = HtmlParseError {
+            error_type: HtmlParseErrorType::MissingSemicolonAfterCharacterReference,
+            location: [1, 5],
+            offset: 4,
+        };
+        let rendered = error.render("a &amp b", DiagnosticStyle::Ansi);
+        assert!(rendered.starts_with("\x1b[1;33mwarning[E0013]\x1b[0m"));
+        assert!(rendered.ends_with("add a `;` after the reference"));
+        assert!(!error
+            .render("a &amp b", DiagnosticStyle::Plain)
+            .contains('\x1b'));
+    }
+
+    #[test]
+    fn it_points_at_the_source_of_errors_found_while_parsing() {
+        let cases = [
+            ("a &amp b", "1 | a &amp b\n  |   ^^^^"),
+            ("x &#0; y", "1 | x &#0; y\n  |   ^^^^"),
+            ("<!DOCTYPE >", "1 | <!DOCTYPE >\n  |           ^"),
+            ("</>", "1 | </>\n  |   ^"),
+            ("<p>\n<div/></p>", "2 | <div/></p>\n  | ^^^^^^"),
+        ];
+        for (source, expected) in cases {
+            let res = parse_html_input(source, ParseOptions::new());
+            let rendered = res.errors[0].render(source, DiagnosticStyle::Plain);
+            assert!(rendered.contains(expected), "{source:?}:\n{rendered}");
+        }
+    }
+}
@@ -0,0 +1,127 @@
+mod sniff;
+mod tables;
+
+use std::collections::VecDeque;
+
+use sleek_utils::Decode;
+
+pub use sniff::sniff_encoding;
+
+/// The character encodings that HTML bytes can be decoded from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1250,
+    Windows1251,
+    /// Also used for `ISO-8859-1` and `US-ASCII`, which it is a superset of.
+    Windows1252,
+    Iso8859_2,
+    Iso8859_15,
+    Koi8R,
+}
+
+impl Encoding {
+    /// Find the encoding for a label, like the `charset` of a `<meta>` tag.
+    /// Labels are matched without case and surrounding whitespace, e.g. `" Latin1"` is windows-1252.
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        let label = label
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .to_ascii_lowercase();
+        let encoding =
+            match label.as_str() {
+                "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+                | "x-unicode20utf8" => Encoding::Utf8,
+                "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff"
+                | "utf-16" | "utf-16le" => Encoding::Utf16Le,
+                "unicodefffe" | "utf-16be" => Encoding::Utf16Be,
+                "cp1250" | "windows-1250" | "x-cp1250" => Encoding::Windows1250,
+                "cp1251" | "windows-1251" | "x-cp1251" => Encoding::Windows1251,
+                "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+                | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+                | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252"
+                | "x-cp1252" => Encoding::Windows1252,
+                "csisolatin2" | "iso-8859-2" | "iso-ir-101" | "iso8859-2" | "iso88592"
+                | "iso_8859-2" | "iso_8859-2:1987" | "l2" | "latin2" => Encoding::Iso8859_2,
+                "csisolatin9" | "iso-8859-15" | "iso8859-15" | "iso885915" | "iso_8859-15"
+                | "l9" => Encoding::Iso8859_15,
+                "cskoi8r" | "koi" | "koi8" | "koi8-r" | "koi8_r" => Encoding::Koi8R,
+                _ => return None,
+            };
+        Some(encoding)
+    }
+
+    /// The name of the encoding in the Encoding Standard.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Windows1250 => "windows-1250",
+            Encoding::Windows1251 => "windows-1251",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::Iso8859_2 => "ISO-8859-2",
+            Encoding::Iso8859_15 => "ISO-8859-15",
+            Encoding::Koi8R => "KOI8-R",
+        }
+    }
+
+    /// The decoder for bytes in the encoding, or `None` for UTF-8, which is the default of [`sleek_utils::CharReader`].
+    pub(crate) fn decoder(&self) -> Option<Decode> {
+        let table = match self {
+            Encoding::Utf8 => return None,
+            Encoding::Utf16Le => {
+                return Some(Box::new(|bytes: &[u8], decoded: &mut VecDeque<char>| {
+                    decode_utf16(bytes, decoded, u16::from_le_bytes)
+                }))
+            }
+            Encoding::Utf16Be => {
+                return Some(Box::new(|bytes: &[u8], decoded: &mut VecDeque<char>| {
+                    decode_utf16(bytes, decoded, u16::from_be_bytes)
+                }))
+            }
+            Encoding::Windows1250 => &tables::WINDOWS_1250,
+            Encoding::Windows1251 => &tables::WINDOWS_1251,
+            Encoding::Windows1252 => &tables::WINDOWS_1252,
+            Encoding::Iso8859_2 => &tables::ISO_8859_2,
+            Encoding::Iso8859_15 => &tables::ISO_8859_15,
+            Encoding::Koi8R => &tables::KOI8_R,
+        };
+        Some(Box::new(
+            move |bytes: &[u8], decoded: &mut VecDeque<char>| {
+                decode_single_byte(bytes, decoded, table)
+            },
+        ))
+    }
+}
+
+/// Decode bytes where every byte is one character.
+fn decode_single_byte(bytes: &[u8], decoded: &mut VecDeque<char>, table: &[char; 128]) -> usize {
+    decoded.extend(bytes.iter().map(|&byte| match byte {
+        0..=0x7F => byte as char,
+        _ => table[byte as usize - 0x80],
+    }));
+    bytes.len()
+}
+
+/// Decode UTF-16 code units, joining surrogate pairs and replacing unpaired surrogates with U+FFFD.
+fn decode_utf16(bytes: &[u8], decoded: &mut VecDeque<char>, unit: fn([u8; 2]) -> u16) -> usize {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    let mut used = units.len();
+    // A surrogate at the end may be completed by the next chunk.
+    if units
+        .last()
+        .is_some_and(|last| (0xD800..0xDC00).contains(last))
+    {
+        used -= 1;
+    }
+    decoded.extend(
+        char::decode_utf16(units[..used].iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+    );
+    used * 2
+}
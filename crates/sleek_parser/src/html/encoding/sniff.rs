@@ -0,0 +1,235 @@
+use super::Encoding;
+
+/// The number of bytes that are searched for a `<meta>` tag declaring the encoding.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Find the encoding of some HTML bytes, using the start of the input:
+/// 1. A byte order mark, which is the length returned with the encoding, so it can be skipped.
+/// 2. A `<meta charset>` or `<meta http-equiv="Content-Type">` tag in the first 1024 bytes.
+/// 3. The fallback encoding.
+/// # Examples
+/// ```
+/// use sleek_parser::{sniff_encoding, Encoding};
+///
+/// let (encoding, _) = sniff_encoding(b"<meta charset=latin2><p>", Encoding::Windows1252);
+/// assert_eq!(encoding, Encoding::Iso8859_2);
+/// ```
+pub fn sniff_encoding(bytes: &[u8], fallback: Encoding) -> (Encoding, usize) {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return (Encoding::Utf8, 3);
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return (Encoding::Utf16Be, 2);
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return (Encoding::Utf16Le, 2);
+    }
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    (prescan(bytes).unwrap_or(fallback), 0)
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// Search for a `<meta>` tag declaring the encoding, skipping comments and other tags.
+fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let mut position = 0;
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+        if rest.starts_with(b"<!--") {
+            // The dashes of `<!--` can also end the comment, as in `<!-->`.
+            let end = rest[2..].windows(3).position(|window| window == b"-->")?;
+            position += end + 4;
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && rest
+                .get(5)
+                .is_some_and(|&byte| is_space(byte) || byte == b'/')
+        {
+            position += 6;
+            if let Some(encoding) = meta_encoding(bytes, &mut position) {
+                return Some(encoding);
+            }
+        } else if rest.len() > 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || (rest[1] == b'/' && rest[2].is_ascii_alphabetic()))
+        {
+            position += rest
+                .iter()
+                .position(|&byte| is_space(byte) || byte == b'>')?;
+            while attribute(bytes, &mut position).is_some() {}
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            position += rest.iter().position(|&byte| byte == b'>')?;
+        }
+        position += 1;
+    }
+    None
+}
+
+/// Read the attributes of a `<meta>` tag, and find the encoding it declares, if any.
+fn meta_encoding(bytes: &[u8], position: &mut usize) -> Option<Encoding> {
+    let mut names = vec![];
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = attribute(bytes, position) {
+        if names.contains(&name) {
+            continue;
+        }
+        match name.as_str() {
+            "http-equiv" => got_pragma |= value == "content-type",
+            "content" if charset.is_none() => {
+                if let Some(encoding) = content_charset(&value) {
+                    charset = Encoding::for_label(&encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Encoding::for_label(&value);
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        names.push(name);
+    }
+    if need_pragma? && !got_pragma {
+        return None;
+    }
+    // The bytes were read as ASCII, so they cannot be UTF-16.
+    match charset? {
+        Encoding::Utf16Le | Encoding::Utf16Be => Some(Encoding::Utf8),
+        encoding => Some(encoding),
+    }
+}
+
+/// Read the next attribute of a tag, with its name and value in lowercase.
+/// Returns `None` at the end of the tag or the input.
+fn attribute(bytes: &[u8], position: &mut usize) -> Option<(String, String)> {
+    let byte_at = |position: usize| bytes.get(position).copied();
+    let lower = |byte: u8| byte.to_ascii_lowercase() as char;
+
+    while byte_at(*position).is_some_and(|byte| is_space(byte) || byte == b'/') {
+        *position += 1;
+    }
+    if byte_at(*position)? == b'>' {
+        return None;
+    }
+    let mut name = String::new();
+    loop {
+        match byte_at(*position)? {
+            b'=' if !name.is_empty() => break,
+            byte if is_space(byte) => {
+                while byte_at(*position).is_some_and(is_space) {
+                    *position += 1;
+                }
+                if byte_at(*position)? != b'=' {
+                    return Some((name, String::new()));
+                }
+                break;
+            }
+            b'/' | b'>' => return Some((name, String::new())),
+            byte => name.push(lower(byte)),
+        }
+        *position += 1;
+    }
+    // Skip the `=`.
+    *position += 1;
+    while byte_at(*position).is_some_and(is_space) {
+        *position += 1;
+    }
+    let mut value = String::new();
+    match byte_at(*position)? {
+        quote @ (b'"' | b'\'') => loop {
+            *position += 1;
+            match byte_at(*position)? {
+                byte if byte == quote => {
+                    *position += 1;
+                    return Some((name, value));
+                }
+                byte => value.push(lower(byte)),
+            }
+        },
+        b'>' => Some((name, value)),
+        _ => loop {
+            match byte_at(*position)? {
+                byte if is_space(byte) || byte == b'>' => return Some((name, value)),
+                byte => value.push(lower(byte)),
+            }
+            *position += 1;
+        },
+    }
+}
+
+/// Find the charset in the value of a `Content-Type`, like `text/html; charset=utf-8`.
+fn content_charset(content: &str) -> Option<String> {
+    let mut rest = content;
+    loop {
+        let start = rest.find("charset")?;
+        rest = rest[start + 7..].trim_start_matches(|c: char| c.is_ascii_whitespace());
+        if let Some(value) = rest.strip_prefix('=') {
+            rest = value.trim_start_matches(|c: char| c.is_ascii_whitespace());
+            break;
+        }
+    }
+    match rest.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let value = &rest[1..];
+            value.find(quote).map(|end| value[..end].to_string())
+        }
+        _ => {
+            let end = rest
+                .find(|c: char| c.is_ascii_whitespace() || c == ';')
+                .unwrap_or(rest.len());
+            Some(rest[..end].to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sniff_encoding, Encoding};
+
+    #[test]
+    fn it_prefers_the_byte_order_mark() {
+        let bytes = b"\xFE\xFF<meta charset=latin1>";
+        assert_eq!(
+            sniff_encoding(bytes, Encoding::Windows1252),
+            (Encoding::Utf16Be, 2)
+        );
+    }
+
+    #[test]
+    fn it_finds_the_charset_of_meta_tags() {
+        let cases: [(&[u8], Encoding); 5] = [
+            (b"<META CHARSET='KOI8-R'>", Encoding::Koi8R),
+            (
+                b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-15\">",
+                Encoding::Iso8859_15,
+            ),
+            (
+                b"<!-- <meta charset=latin2> --><meta charset=cp1251>",
+                Encoding::Windows1251,
+            ),
+            (
+                b"<title x='<meta charset=latin2>'><meta charset=utf-16>",
+                Encoding::Utf8,
+            ),
+            // The content attribute is only used with `http-equiv`.
+            (b"<meta content='charset=latin2'>", Encoding::Windows1252),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(sniff_encoding(bytes, Encoding::Windows1252), (expected, 0));
+        }
+    }
+
+    #[test]
+    fn it_only_searches_the_start_of_the_input() {
+        let mut bytes = vec![b' '; 1024];
+        bytes.extend_from_slice(b"<meta charset=latin2>");
+        assert_eq!(sniff_encoding(&bytes, Encoding::Utf8), (Encoding::Utf8, 0));
+    }
+}
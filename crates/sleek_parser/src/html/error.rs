@@ -1,5 +1,13 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+};
+
 use sleek_ast::HtmlTag;
 
+/// The kinds of errors found while parsing.
+/// Errors of the tokenizer have the names they have in the HTML standard, like `eof-in-tag`, which is returned by [`HtmlParseErrorType::name`].
 #[derive(Debug, Default)]
 pub enum HtmlParseErrorType {
     #[default]
@@ -13,10 +21,203 @@ pub enum HtmlParseErrorType {
     VoidElementEndTag(HtmlTag),
     UnclosedTag(HtmlTag),
     UnexpectedCloseTag(HtmlTag),
+    UnexpectedOpenTag(HtmlTag),
+    UnexpectedDocType,
+    MissingSemicolonAfterCharacterReference,
+    UnknownNamedCharacterReference,
+    AbsenceOfDigitsInNumericCharacterReference,
+    NullCharacterReference,
+    CharacterReferenceOutsideUnicodeRange,
+    SurrogateCharacterReference,
+    NoncharacterCharacterReference,
+    ControlCharacterReference,
+    AbruptClosingOfEmptyComment,
+    AbruptDoctypePublicIdentifier,
+    AbruptDoctypeSystemIdentifier,
+    CdataInHtmlContent,
+    ControlCharacterInInputStream,
+    DuplicateAttribute,
+    EndTagWithAttributes,
+    EndTagWithTrailingSolidus,
+    EofBeforeTagName,
+    EofInCdata,
+    EofInDoctype,
+    EofInScriptHtmlCommentLikeText,
+    EofInTag,
+    IncorrectlyClosedComment,
+    IncorrectlyOpenedComment,
+    InvalidFirstCharacterOfTagName,
+    MissingAttributeValue,
+    MissingDoctypeName,
+    MissingDoctypePublicIdentifier,
+    MissingDoctypeSystemIdentifier,
+    MissingQuoteBeforeDoctypePublicIdentifier,
+    MissingQuoteBeforeDoctypeSystemIdentifier,
+    MissingWhitespaceAfterDoctypePublicKeyword,
+    MissingWhitespaceAfterDoctypeSystemKeyword,
+    MissingWhitespaceBeforeDoctypeName,
+    MissingWhitespaceBetweenAttributes,
+    MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    NestedComment,
+    NoncharacterInInputStream,
+    UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    UnexpectedCharacterInAttributeName,
+    UnexpectedCharacterInUnquotedAttributeValue,
+    UnexpectedEqualsSignBeforeAttributeName,
+    UnexpectedQuestionMarkInsteadOfTagName,
+    UnexpectedSolidusInTag,
+}
+
+impl HtmlParseErrorType {
+    /// The name of the error in the HTML standard, for comparing with other parsers and filtering errors.
+    /// The standard does not name tree construction errors, so they have names in the style of the html5lib tests, like `unexpected-end-tag`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HtmlParseErrorType::InvalidCharacter => "unexpected-null-character",
+            HtmlParseErrorType::UnexpectedEndOfInput => "unexpected-eof",
+            HtmlParseErrorType::UnexpectedCharacter(_) => "unexpected-character",
+            HtmlParseErrorType::ExpectedTagName => "missing-end-tag-name",
+            HtmlParseErrorType::UnclosedComment => "eof-in-comment",
+            HtmlParseErrorType::IndecipherableDocType => {
+                "invalid-character-sequence-after-doctype-name"
+            }
+            HtmlParseErrorType::SelfClosingNonVoidTag => {
+                "non-void-html-element-start-tag-with-trailing-solidus"
+            }
+            HtmlParseErrorType::VoidElementEndTag(_) => "unexpected-end-tag-for-void-element",
+            HtmlParseErrorType::UnclosedTag(_) => "unclosed-element",
+            HtmlParseErrorType::UnexpectedCloseTag(_) => "unexpected-end-tag",
+            HtmlParseErrorType::UnexpectedOpenTag(_) => "unexpected-start-tag",
+            HtmlParseErrorType::UnexpectedDocType => "unexpected-doctype",
+            HtmlParseErrorType::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            HtmlParseErrorType::UnknownNamedCharacterReference => {
+                "unknown-named-character-reference"
+            }
+            HtmlParseErrorType::AbsenceOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            HtmlParseErrorType::NullCharacterReference => "null-character-reference",
+            HtmlParseErrorType::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            HtmlParseErrorType::SurrogateCharacterReference => "surrogate-character-reference",
+            HtmlParseErrorType::NoncharacterCharacterReference => {
+                "noncharacter-character-reference"
+            }
+            HtmlParseErrorType::ControlCharacterReference => "control-character-reference",
+            HtmlParseErrorType::AbruptClosingOfEmptyComment => "abrupt-closing-of-empty-comment",
+            HtmlParseErrorType::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
+            HtmlParseErrorType::AbruptDoctypeSystemIdentifier => "abrupt-doctype-system-identifier",
+            HtmlParseErrorType::CdataInHtmlContent => "cdata-in-html-content",
+            HtmlParseErrorType::ControlCharacterInInputStream => {
+                "control-character-in-input-stream"
+            }
+            HtmlParseErrorType::DuplicateAttribute => "duplicate-attribute",
+            HtmlParseErrorType::EndTagWithAttributes => "end-tag-with-attributes",
+            HtmlParseErrorType::EndTagWithTrailingSolidus => "end-tag-with-trailing-solidus",
+            HtmlParseErrorType::EofBeforeTagName => "eof-before-tag-name",
+            HtmlParseErrorType::EofInCdata => "eof-in-cdata",
+            HtmlParseErrorType::EofInDoctype => "eof-in-doctype",
+            HtmlParseErrorType::EofInScriptHtmlCommentLikeText => {
+                "eof-in-script-html-comment-like-text"
+            }
+            HtmlParseErrorType::EofInTag => "eof-in-tag",
+            HtmlParseErrorType::IncorrectlyClosedComment => "incorrectly-closed-comment",
+            HtmlParseErrorType::IncorrectlyOpenedComment => "incorrectly-opened-comment",
+            HtmlParseErrorType::InvalidFirstCharacterOfTagName => {
+                "invalid-first-character-of-tag-name"
+            }
+            HtmlParseErrorType::MissingAttributeValue => "missing-attribute-value",
+            HtmlParseErrorType::MissingDoctypeName => "missing-doctype-name",
+            HtmlParseErrorType::MissingDoctypePublicIdentifier => {
+                "missing-doctype-public-identifier"
+            }
+            HtmlParseErrorType::MissingDoctypeSystemIdentifier => {
+                "missing-doctype-system-identifier"
+            }
+            HtmlParseErrorType::MissingQuoteBeforeDoctypePublicIdentifier => {
+                "missing-quote-before-doctype-public-identifier"
+            }
+            HtmlParseErrorType::MissingQuoteBeforeDoctypeSystemIdentifier => {
+                "missing-quote-before-doctype-system-identifier"
+            }
+            HtmlParseErrorType::MissingWhitespaceAfterDoctypePublicKeyword => {
+                "missing-whitespace-after-doctype-public-keyword"
+            }
+            HtmlParseErrorType::MissingWhitespaceAfterDoctypeSystemKeyword => {
+                "missing-whitespace-after-doctype-system-keyword"
+            }
+            HtmlParseErrorType::MissingWhitespaceBeforeDoctypeName => {
+                "missing-whitespace-before-doctype-name"
+            }
+            HtmlParseErrorType::MissingWhitespaceBetweenAttributes => {
+                "missing-whitespace-between-attributes"
+            }
+            HtmlParseErrorType::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers => {
+                "missing-whitespace-between-doctype-public-and-system-identifiers"
+            }
+            HtmlParseErrorType::NestedComment => "nested-comment",
+            HtmlParseErrorType::NoncharacterInInputStream => "noncharacter-in-input-stream",
+            HtmlParseErrorType::UnexpectedCharacterAfterDoctypeSystemIdentifier => {
+                "unexpected-character-after-doctype-system-identifier"
+            }
+            HtmlParseErrorType::UnexpectedCharacterInAttributeName => {
+                "unexpected-character-in-attribute-name"
+            }
+            HtmlParseErrorType::UnexpectedCharacterInUnquotedAttributeValue => {
+                "unexpected-character-in-unquoted-attribute-value"
+            }
+            HtmlParseErrorType::UnexpectedEqualsSignBeforeAttributeName => {
+                "unexpected-equals-sign-before-attribute-name"
+            }
+            HtmlParseErrorType::UnexpectedQuestionMarkInsteadOfTagName => {
+                "unexpected-question-mark-instead-of-tag-name"
+            }
+            HtmlParseErrorType::UnexpectedSolidusInTag => "unexpected-solidus-in-tag",
+        }
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct HtmlParseError {
     pub error_type: HtmlParseErrorType,
+    /// The `[line, column]` of the error, with the column counted in characters.
     pub location: [usize; 2],
+    /// The byte offset of the location in the source, which is usually 0 when positions are not kept.
+    pub offset: usize,
+}
+
+/// An error that stops an HTML file from being parsed at all.
+#[derive(Debug)]
+pub enum HtmlFileError {
+    /// The file could not be opened or read.
+    Io(io::Error),
+    /// The file is not text, e.g. an image. Only checked when [content sniffing](crate::ParseOptions::sniff_content) is enabled.
+    UnsupportedContent,
+}
+
+impl Display for HtmlFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HtmlFileError::Io(error) => write!(f, "could not read file: {error}"),
+            HtmlFileError::UnsupportedContent => write!(f, "file content is not html"),
+        }
+    }
+}
+
+impl Error for HtmlFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HtmlFileError::Io(error) => Some(error),
+            HtmlFileError::UnsupportedContent => None,
+        }
+    }
+}
+
+impl From<io::Error> for HtmlFileError {
+    fn from(error: io::Error) -> Self {
+        HtmlFileError::Io(error)
+    }
 }
@@ -0,0 +1,360 @@
+use std::{mem::take, ops::Range};
+
+use sleek_ast::{DocumentSource, ElementRef, HtmlDocument, HtmlNode, HtmlTag, Namespace, Span};
+use sleek_utils::{LineIndex, Node};
+
+use super::{parse_html_fragment, parse_html_input, HtmlParseError, ParseOptions};
+
+/// A change to the source of a document: the bytes in a range are replaced with a text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Range<usize>, text: &str) -> Self {
+        TextEdit {
+            range,
+            text: text.to_string(),
+        }
+    }
+    /// Apply the edit to a source.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = String::with_capacity(source.len() + self.text.len());
+        edited.push_str(&source[..self.range.start]);
+        edited.push_str(&self.text);
+        edited.push_str(&source[self.range.end..]);
+        edited
+    }
+    /// Where an offset of the source is after the edit, for offsets that are not inside the edited range.
+    /// A span that ends where text is inserted stays before the text, and a span that starts there moves after it.
+    fn map(&self, offset: usize, end: bool) -> usize {
+        let after = match end {
+            true => offset > self.range.start && offset >= self.range.end,
+            false => offset >= self.range.end,
+        };
+        match after {
+            true => offset - self.range.len() + self.text.len(),
+            false => offset,
+        }
+    }
+}
+
+/// The result of parsing a document again after an edit.
+#[derive(Debug)]
+pub struct HtmlReparseResult {
+    pub tree: HtmlDocument,
+    /// The errors in the part of the source that was parsed again.
+    pub errors: Vec<HtmlParseError>,
+    /// The element whose content was parsed again, or `None` if the whole document was.
+    pub parent: Option<ElementRef>,
+    /// The elements created by parsing again, in the order of the document. Every other element is reused from the old tree.
+    pub changed: Vec<ElementRef>,
+}
+
+/// Parse a document again after an edit to its source, reusing the parts of the tree that the edit does not affect.
+///
+/// The content of the innermost element around the edit is parsed again, as a fragment in the context of the element,
+/// and replaces the old content. The spans of the nodes after the edit are moved to their new place in the source.
+/// If the content does not parse cleanly on its own, e.g. because the edit added an end tag for an element outside of it,
+/// or a start tag that closes an element around it like `<div>` in a `<p>`, the parent element is tried instead, up to the whole document.
+/// The tree must have been parsed from the source with positions kept.
+/// # Examples
+/// ```
+/// use sleek_parser::{parse_html_input, reparse_html, ParseOptions, TextEdit};
+///
+/// let source = "<ul><li>One</li><li>Two</li></ul>";
+/// let tree = parse_html_input(source, ParseOptions::new()).tree;
+/// let edit = TextEdit::new(11..11, "<b>!</b>");
+/// let result = reparse_html(tree, source, &edit, ParseOptions::new());
+///
+/// assert_eq!(result.parent.unwrap().get_text_content(), "One!");
+/// assert_eq!(result.changed.len(), 1);
+/// ```
+pub fn reparse_html(
+    mut tree: HtmlDocument,
+    source: &str,
+    edit: &TextEdit,
+    options: ParseOptions,
+) -> HtmlReparseResult {
+    let edited = edit.apply(source);
+    let mut candidate = enclosing(&tree.nodes, &edit.range);
+    while let Some(element) = candidate {
+        candidate = element.parent();
+        if !contains(&element, &edit.range) || !can_reparse(&element) {
+            continue;
+        }
+        let location = &element.element().location;
+        let start = location.open_tag.end_offset;
+        let end = location
+            .close_tag
+            .as_ref()
+            .map_or(start, |tag| tag.start_offset);
+        let content = &edited[start..edit.map(end, false)];
+        let fragment_options = ParseOptions {
+            lossless: false,
+            ..options.clone()
+        };
+        let mut fragment =
+            parse_html_fragment(content, element.tag_name().clone(), fragment_options);
+        let mut changed = vec![];
+        for node in &fragment.tree.nodes {
+            collect_elements(node, &mut changed);
+        }
+        if !fragment.errors.is_empty() || !fits(&element, &changed) {
+            continue;
+        }
+
+        let index = LineIndex::new(&edited);
+        relocate(
+            &mut tree.nodes,
+            &|offset, end| edit.map(offset, end),
+            &index,
+        );
+        relocate(
+            &mut fragment.tree.nodes,
+            &|offset, _| offset + start,
+            &index,
+        );
+        for node in &fragment.tree.nodes {
+            if let HtmlNode::Element(child) = node {
+                child.element().__parent = Some(element.element());
+            }
+        }
+        element.element().child_nodes = take(&mut fragment.tree.nodes);
+        if tree.source.is_some() {
+            tree.source = Some(DocumentSource::new(edited.clone(), &tree.nodes));
+        }
+        return HtmlReparseResult {
+            tree,
+            errors: fragment.errors,
+            parent: Some(element),
+            changed,
+        };
+    }
+
+    let result = parse_html_input(&edited, options);
+    let mut changed = vec![];
+    for node in &result.tree.nodes {
+        collect_elements(node, &mut changed);
+    }
+    HtmlReparseResult {
+        tree: result.tree,
+        errors: result.errors,
+        parent: None,
+        changed,
+    }
+}
+
+/// Find the innermost element whose content contains a range of the source, between its start and end tags.
+fn enclosing(nodes: &[HtmlNode], range: &Range<usize>) -> Option<ElementRef> {
+    nodes
+        .iter()
+        .filter_map(HtmlNode::as_element_ref)
+        .find_map(|element| {
+            enclosing(&element.element().child_nodes, range)
+                .or_else(|| contains(&element, range).then_some(element))
+        })
+}
+
+/// Check if a range of the source is in the content of an element, between its start and end tags.
+fn contains(element: &ElementRef, range: &Range<usize>) -> bool {
+    let location = &element.element().location;
+    !location.open_tag.range().is_empty()
+        && location.close_tag.as_ref().is_some_and(|close_tag| {
+            location.open_tag.end_offset <= range.start && range.end <= close_tag.start_offset
+        })
+}
+
+/// Check if the content of an element parses the same as a fragment as it does in the document.
+/// The content of `<head>` is parsed like body content in a fragment,
+/// a line break at the start of a `<pre>` is only dropped right after its start tag,
+/// and fragments are always parsed as HTML, not SVG or MathML.
+fn can_reparse(element: &ElementRef) -> bool {
+    if element.namespace() != Namespace::Html {
+        return false;
+    }
+    match element.tag_name() {
+        HtmlTag::Html
+        | HtmlTag::Head
+        | HtmlTag::Noscript
+        | HtmlTag::Frameset
+        | HtmlTag::Pre
+        | HtmlTag::Textarea => false,
+        HtmlTag::Unknown(name) => name != "listing",
+        _ => true,
+    }
+}
+
+/// Check if the elements of a fragment are created the same way in the document, where the element and its ancestors are open.
+/// Some start tags close an open element without an error, like `<div>` closing a `<p>` or `<li>` closing an `<li>`,
+/// which a fragment parsed on its own cannot do.
+fn fits(context: &ElementRef, elements: &[ElementRef]) -> bool {
+    let mut open = vec![];
+    let mut ancestor = Some(context.clone());
+    while let Some(element) = ancestor {
+        ancestor = element.parent();
+        if element.namespace() == Namespace::Html {
+            open.push(element);
+        }
+    }
+    elements.iter().all(|element| {
+        element.namespace() != Namespace::Html
+            || !open
+                .iter()
+                .any(|open| depends_on(element.tag_name(), open.tag_name()))
+    })
+}
+
+/// Check if a start tag is handled differently when an element is open, following the rules for tags in `<body>`.
+fn depends_on(tag: &HtmlTag, open: &HtmlTag) -> bool {
+    match open {
+        HtmlTag::P => closes_p(tag),
+        HtmlTag::Li => tag == &HtmlTag::Li,
+        HtmlTag::Dd | HtmlTag::Dt => matches!(tag, HtmlTag::Dd | HtmlTag::Dt),
+        open if is_heading(open) => is_heading(tag),
+        HtmlTag::Button => tag == &HtmlTag::Button,
+        HtmlTag::Form => tag == &HtmlTag::Form,
+        HtmlTag::Option => matches!(tag, HtmlTag::Option | HtmlTag::Optgroup),
+        HtmlTag::Ruby | HtmlTag::Rtc => {
+            matches!(tag, HtmlTag::Rb | HtmlTag::Rp | HtmlTag::Rt | HtmlTag::Rtc)
+        }
+        open if is_table_part(open) => is_table_part(tag),
+        // Formatting elements of the same name are closed or counted, like an `<a>` in an `<a>`.
+        open if is_formatting(open) => tag == open,
+        _ => false,
+    }
+}
+
+/// Checks if a start tag closes a `<p>` in button scope.
+fn closes_p(tag: &HtmlTag) -> bool {
+    match tag {
+        HtmlTag::Address
+        | HtmlTag::Article
+        | HtmlTag::Aside
+        | HtmlTag::Blockquote
+        | HtmlTag::Center
+        | HtmlTag::Details
+        | HtmlTag::Dialog
+        | HtmlTag::Dir
+        | HtmlTag::Div
+        | HtmlTag::Dl
+        | HtmlTag::Fieldset
+        | HtmlTag::Figcaption
+        | HtmlTag::Figure
+        | HtmlTag::Footer
+        | HtmlTag::Header
+        | HtmlTag::Hgroup
+        | HtmlTag::Main
+        | HtmlTag::Menu
+        | HtmlTag::Nav
+        | HtmlTag::Ol
+        | HtmlTag::P
+        | HtmlTag::Section
+        | HtmlTag::Summary
+        | HtmlTag::Ul
+        | HtmlTag::Pre
+        | HtmlTag::Form
+        | HtmlTag::Li
+        | HtmlTag::Dd
+        | HtmlTag::Dt
+        | HtmlTag::Plaintext
+        | HtmlTag::Xmp
+        | HtmlTag::Hr
+        | HtmlTag::Table => true,
+        HtmlTag::Unknown(name) => name == "listing",
+        tag => is_heading(tag),
+    }
+}
+
+fn is_heading(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::H1 | HtmlTag::H2 | HtmlTag::H3 | HtmlTag::H4 | HtmlTag::H5 | HtmlTag::H6
+    )
+}
+
+fn is_table_part(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::Table
+            | HtmlTag::Caption
+            | HtmlTag::Colgroup
+            | HtmlTag::Col
+            | HtmlTag::Tbody
+            | HtmlTag::Thead
+            | HtmlTag::Tfoot
+            | HtmlTag::Tr
+            | HtmlTag::Td
+            | HtmlTag::Th
+    )
+}
+
+fn is_formatting(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::A
+            | HtmlTag::B
+            | HtmlTag::Big
+            | HtmlTag::Code
+            | HtmlTag::Em
+            | HtmlTag::Font
+            | HtmlTag::I
+            | HtmlTag::Nobr
+            | HtmlTag::S
+            | HtmlTag::Small
+            | HtmlTag::Strike
+            | HtmlTag::Strong
+            | HtmlTag::Tt
+            | HtmlTag::U
+    )
+}
+
+/// Move the spans of nodes and their descendants, and find their positions in the new source.
+/// The offsets are moved by a function of the offset, and whether it is the end of a span.
+/// Nodes without a span, like implied elements, are left as they are.
+fn relocate(nodes: &mut [HtmlNode], map: &impl Fn(usize, bool) -> usize, index: &LineIndex) {
+    for node in nodes {
+        match node {
+            HtmlNode::Element(element) => {
+                let element = element.element();
+                let location = &mut element.location;
+                relocate_span(&mut location.open_tag, map, index);
+                if let Some(close_tag) = &mut location.close_tag {
+                    relocate_span(close_tag, map, index);
+                }
+                for attribute in element.attributes.values_mut() {
+                    relocate_span(&mut attribute.name_span, map, index);
+                    if let Some(value_span) = &mut attribute.value_span {
+                        relocate_span(value_span, map, index);
+                    }
+                }
+                relocate(&mut element.child_nodes, map, index);
+            }
+            HtmlNode::Text(text) => relocate_span(&mut text.span, map, index),
+            HtmlNode::Comment(comment) => relocate_span(&mut comment.span, map, index),
+            HtmlNode::DocType(_) => {}
+        }
+    }
+}
+
+fn relocate_span(span: &mut Span, map: &impl Fn(usize, bool) -> usize, index: &LineIndex) {
+    if span.range().is_empty() {
+        return;
+    }
+    let (start, end) = (map(span.start_offset, false), map(span.end_offset, true));
+    if let (Some(start_position), Some(end_position)) = (index.position(start), index.position(end))
+    {
+        *span = Span::over(start_position, end_position).with_offsets(start, end);
+    }
+}
+
+fn collect_elements(node: &HtmlNode, elements: &mut Vec<ElementRef>) {
+    if let HtmlNode::Element(element) = node {
+        elements.push(element.clone());
+        for child in &element.element().child_nodes {
+            collect_elements(child, elements);
+        }
+    }
+}
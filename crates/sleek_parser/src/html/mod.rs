@@ -1,62 +1,183 @@
+#[cfg(test)]
+mod conformance;
+mod diagnostics;
+mod encoding;
 mod error;
+mod incremental;
+mod options;
 mod parser;
 mod test;
 mod tokenizer;
 
-use std::path::Path;
+use std::{
+    fs::File,
+    io::{self, Cursor, Read},
+    path::Path,
+};
 
+pub use diagnostics::{DiagnosticStyle, Severity};
+pub use encoding::{sniff_encoding, Encoding};
 pub use error::*;
+pub use incremental::{reparse_html, HtmlReparseResult, TextEdit};
+pub use options::{ParseOptions, Whitespace};
 
 pub use self::parser::HtmlParseResult;
+pub use self::tokenizer::HtmlTokenizer;
 use self::{
-    parser::{SpeculativeHtmlParser, SyncHtmlParser},
+    parser::{SpeculativeHtmlParser, SyncHtmlParser, TreeBuilder},
     tokenizer::TokenStore,
 };
-use sleek_utils::{MatrixIterator, QueueIterator};
+use sleek_ast::{DocumentSource, HtmlDocument, HtmlTag};
+use sleek_utils::{CharReader, MatrixIterator, MutableCountRef, QueueIterator};
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
 pub enum ParseMode {
     /// Build the tree as each token is parsed. Allows easier handling of internal styles and scripts.
     Speculative,
     /// Tokenize all at once before sending to the parser.
+    /// The tokenizer cannot tell when it is inside SVG or MathML, so CDATA sections are read as comments.
     Synchronous,
 }
 
 /// Parse an HTML string into a valid DOM tree.
-pub fn parse_html_input(input: &str, mode: ParseMode) -> HtmlParseResult {
-    let iterator = QueueIterator::new(MatrixIterator::new(input.chars(), '\n'));
-    let token_store = TokenStore::new();
+pub fn parse_html_input(input: &str, options: ParseOptions) -> HtmlParseResult {
+    parse(input.chars(), &options)
+}
+
+/// Parse an HTML string, failing if it has errors instead of building a tree from broken input.
+/// Parsing stops as soon as the [error threshold](ParseOptions::error_threshold) is reached, without reading the rest of the input.
+/// # Errors
+/// The function will return the error that reached the threshold, in the order of the source.
+pub fn parse_html_strict(
+    input: &str,
+    options: ParseOptions,
+) -> Result<HtmlDocument, HtmlParseError> {
+    let threshold = options.error_threshold;
+    let options = ParseOptions {
+        strict: true,
+        max_errors: None,
+        ..options
+    };
+    let HtmlParseResult {
+        tree, mut errors, ..
+    } = parse_html_input(input, options);
+    if errors.len() < threshold {
+        return Ok(tree);
+    }
+    errors.sort_by_key(|error| error.location);
+    Err(errors.swap_remove(threshold - 1))
+}
+
+/// Parse an HTML string as the content of an element, like setting `innerHTML` on it.
+/// The nodes of the fragment are the nodes of the resulting tree, e.g. a `<tr>` fragment in a `<tbody>` context has `<tr>` nodes at its root.
+pub fn parse_html_fragment(
+    input: &str,
+    context: HtmlTag,
+    options: ParseOptions,
+) -> HtmlParseResult {
+    parse_html_input(input, options.fragment_context(context))
+}
+
+/// Parse HTML from a reader into a valid DOM tree.
+/// The input is read and decoded as UTF-8 in chunks while the tree is built, so it is never fully held in memory.
+/// # Errors
+/// The function will return an error if reading from the reader fails.
+pub fn parse_html_reader<R: Read>(reader: R, options: ParseOptions) -> io::Result<HtmlParseResult> {
+    parse_chars(CharReader::new(reader), &options)
+}
+
+/// Parse HTML bytes in an unknown encoding into a valid DOM tree.
+/// The encoding is found with [`sniff_encoding`], using the [fallback](ParseOptions::fallback_encoding) if the input does not declare it, and is stored on the result.
+pub fn parse_html_bytes(input: &[u8], options: ParseOptions) -> HtmlParseResult {
+    let options = options.sniff_content(false);
+    parse_html_encoded(input, &options).expect("reading from a slice cannot fail")
+}
 
-    match mode {
-        ParseMode::Speculative => SpeculativeHtmlParser::parse(token_store, iterator),
-        ParseMode::Synchronous => SyncHtmlParser::parse(token_store, iterator),
+/// Find the encoding of the input from its first bytes, and parse it while decoding the rest.
+fn parse_html_encoded<R: Read>(
+    mut reader: R,
+    options: &ParseOptions,
+) -> Result<HtmlParseResult, HtmlFileError> {
+    let mut start = Vec::with_capacity(1024);
+    (&mut reader).take(1024).read_to_end(&mut start)?;
+    let (encoding, bom_length) = sniff_encoding(&start, options.fallback_encoding);
+    let is_utf16 = matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be);
+    if options.sniff_content && !is_utf16 && start[bom_length..].iter().any(is_binary) {
+        return Err(HtmlFileError::UnsupportedContent);
+    }
+    let source = Cursor::new(start.split_off(bom_length)).chain(reader);
+
+    let chars = match encoding.decoder() {
+        Some(decoder) => CharReader::with_decoder(source, decoder),
+        None => CharReader::new(source),
+    };
+    let mut result = parse_chars(chars, options)?;
+    result.encoding = encoding;
+    Ok(result)
+}
+
+/// Checks if a byte is a control character that does not appear in text, which means the input is binary.
+fn is_binary(byte: &u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)
+}
+
+/// Parse characters with the parser for the mode in the options.
+fn parse(chars: impl Iterator<Item = char>, options: &ParseOptions) -> HtmlParseResult {
+    let mut token_store = TokenStore::new();
+    token_store.configure(options);
+    let mut reader = token_store.reader();
+    // The source is only copied for lossless parsing.
+    let source: Option<MutableCountRef<String>> = options.lossless.then(Default::default);
+    let text = source.clone();
+    let chars = chars.inspect(move |&ch| {
+        reader.read(ch);
+        if let Some(text) = &text {
+            text.borrow_mut().push(ch);
+        }
+    });
+    let iterator = QueueIterator::new(MatrixIterator::new(chars, '\n'));
+    let builder = TreeBuilder::new(
+        options,
+        token_store.error_count(),
+        token_store.line_index(),
+        token_store.foreign_content(),
+    );
+
+    let mut result = match options.mode {
+        ParseMode::Speculative => SpeculativeHtmlParser::parse(token_store, iterator, builder),
+        ParseMode::Synchronous => SyncHtmlParser::parse(token_store, iterator, builder),
+    };
+    if let Some(source) = source {
+        let source = DocumentSource::new(source.take(), &result.tree.nodes);
+        result.tree.source = Some(source);
+    }
+    result
+}
+
+fn parse_chars<R: Read>(
+    chars: CharReader<R>,
+    options: &ParseOptions,
+) -> io::Result<HtmlParseResult> {
+    let error = chars.error();
+    let result = parse(chars, options);
+    let error = error.borrow_mut().take();
+    match error {
+        Some(error) => Err(error),
+        None => Ok(result),
     }
 }
 
 /// Parse an HTML file into a valid DOM tree.
+/// Files are accepted whatever their extension, and their encoding is found like [`parse_html_bytes`].
 /// # Errors
 /// The function will return an error if:
-/// - The file does not exist.
-/// - The file is not a valid HTML file.
+/// - The file does not exist, or there are not adequate permissions.
 /// - There was an error encountered while reading the file.
-/// - There are not adequate permissions.
+/// - [Content sniffing](ParseOptions::sniff_content) is enabled, and the file is not text.
 pub fn parse_html_file<P: AsRef<Path>>(
     path: P,
-    mode: ParseMode,
-) -> std::io::Result<HtmlParseResult> {
-    let address = path.as_ref().to_str().unwrap();
-    match address.split('.').last() {
-        Some("html") | Some("htm") | Some("xhtml") | Some("dhtml") => {
-            let input = std::fs::read_to_string(path)?;
-            Ok(parse_html_input(&input, mode))
-        }
-        None => Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidFilename,
-            "Could not read file. Expected valid html file as input",
-        )),
-        Some(ext) => Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidFilename,
-            format!("\"{ext}\" is not a supported file extension for html"),
-        )),
-    }
+    options: ParseOptions,
+) -> Result<HtmlParseResult, HtmlFileError> {
+    let file = File::open(path)?;
+    parse_html_encoded(file, &options)
 }
@@ -0,0 +1,139 @@
+use sleek_ast::HtmlTag;
+
+use super::{Encoding, ParseMode};
+
+/// How text that is only whitespace is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whitespace {
+    /// Drop text nodes made only of ASCII whitespace, e.g. the indentation between tags.
+    Remove,
+    /// Keep whitespace text, except where the tree construction rules drop it, e.g. before `<html>`.
+    Preserve,
+}
+
+/// Options for parsing HTML, built by chaining setters on the defaults.
+/// # Examples
+/// ```
+/// use sleek_parser::{parse_html_input, ParseMode, ParseOptions};
+///
+/// let options = ParseOptions::new()
+///     .mode(ParseMode::Synchronous)
+///     .keep_comments(false)
+///     .max_errors(10);
+/// let result = parse_html_input("<!-- note --><p>Hello", options);
+/// assert_eq!(result.tree.nodes.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub(crate) mode: ParseMode,
+    pub(crate) scripting: bool,
+    pub(crate) whitespace: Whitespace,
+    pub(crate) max_errors: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) context: Option<HtmlTag>,
+    pub(crate) keep_comments: bool,
+    pub(crate) keep_positions: bool,
+    pub(crate) fallback_encoding: Encoding,
+    pub(crate) sniff_content: bool,
+    pub(crate) error_threshold: usize,
+    pub(crate) lossless: bool,
+    /// Whether parsing stops at the error threshold, set by [`parse_html_strict`](crate::parse_html_strict).
+    pub(crate) strict: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            mode: ParseMode::Speculative,
+            scripting: false,
+            whitespace: Whitespace::Preserve,
+            max_errors: None,
+            max_depth: None,
+            context: None,
+            keep_comments: true,
+            keep_positions: true,
+            fallback_encoding: Encoding::Windows1252,
+            sniff_content: false,
+            error_threshold: 1,
+            lossless: false,
+            strict: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// How tokens are sent to the tree builder. Defaults to [`ParseMode::Speculative`].
+    pub fn mode(mut self, mode: ParseMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    /// Parse as if scripts were enabled, which makes the content of `<noscript>` text. Defaults to false.
+    pub fn scripting(mut self, scripting: bool) -> Self {
+        self.scripting = scripting;
+        self
+    }
+    /// How whitespace-only text is handled. Defaults to [`Whitespace::Preserve`], which follows the standard.
+    pub fn whitespace(mut self, whitespace: Whitespace) -> Self {
+        self.whitespace = whitespace;
+        self
+    }
+    /// The most errors to collect. Parsing goes on after the limit, but later errors are dropped.
+    pub fn max_errors(mut self, max: usize) -> Self {
+        self.max_errors = Some(max);
+        self
+    }
+    /// The deepest an element can be nested and still have children, counting `<html>` as 1.
+    /// Nodes that would be nested deeper are added to the element at the limit instead, like browsers do for very deep documents.
+    /// Limits below 2 are raised to 2, so the content still goes in `<body>`.
+    pub fn max_nesting_depth(mut self, max: usize) -> Self {
+        self.max_depth = Some(max.max(2));
+        self
+    }
+    /// Parse the input as the content of an element, like setting `innerHTML` on it.
+    pub fn fragment_context(mut self, context: HtmlTag) -> Self {
+        self.context = Some(context);
+        self
+    }
+    /// Whether comments are added to the tree. Defaults to true.
+    pub fn keep_comments(mut self, keep: bool) -> Self {
+        self.keep_comments = keep;
+        self
+    }
+    /// Whether nodes keep the spans of their source. Defaults to true.
+    /// Errors are always located, but without positions the lines of the input are not indexed, and most error offsets are 0.
+    pub fn keep_positions(mut self, keep: bool) -> Self {
+        self.keep_positions = keep;
+        self
+    }
+    /// The encoding of byte input that does not declare one. Defaults to windows-1252.
+    pub fn fallback_encoding(mut self, encoding: Encoding) -> Self {
+        self.fallback_encoding = encoding;
+        self
+    }
+    /// Check that files look like text before parsing them, instead of parsing any content. Defaults to false.
+    pub fn sniff_content(mut self, sniff: bool) -> Self {
+        self.sniff_content = sniff;
+        self
+    }
+    /// The number of errors that fails [strict parsing](crate::parse_html_strict). Defaults to 1, failing on the first error.
+    pub fn error_threshold(mut self, threshold: usize) -> Self {
+        self.error_threshold = threshold.max(1);
+        self
+    }
+    /// Keep the source of the document, so that it can be [written back](sleek_ast::HtmlDocument::to_source) with only its edited parts changed.
+    /// Whitespace text is preserved and nodes keep their spans, whatever the other options. Defaults to false.
+    pub fn lossless(mut self, lossless: bool) -> Self {
+        self.lossless = lossless;
+        if lossless {
+            self.whitespace = Whitespace::Preserve;
+        }
+        self
+    }
+    /// The number of errors to stop at, if parsing is strict.
+    pub(crate) fn stop_after(&self) -> Option<usize> {
+        self.strict.then_some(self.error_threshold)
+    }
+}
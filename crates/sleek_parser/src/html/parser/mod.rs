@@ -1,12 +1,14 @@
 use sleek_ast::HtmlDocument;
 
-use crate::HtmlParseError;
+use crate::{Encoding, HtmlParseError};
 
 mod speculative;
 mod synchronous;
+mod tree_builder;
 
 pub use speculative::{ParserResponse, SpeculativeHtmlParser};
 pub use synchronous::SyncHtmlParser;
+pub use tree_builder::TreeBuilder;
 
 /// The result of the Html parsing process.
 /// The parser tries to produce a valid HTML DOM Tree regardless of how wrangled or broken the input string is.
@@ -15,4 +17,6 @@ pub use synchronous::SyncHtmlParser;
 pub struct HtmlParseResult {
     pub tree: HtmlDocument,
     pub errors: Vec<HtmlParseError>,
+    /// The encoding the input was decoded from. Text input is always UTF-8.
+    pub encoding: Encoding,
 }
@@ -0,0 +1,632 @@
+use sleek_ast::{DocumentMode, ElementRef, HtmlTag, HtmlToken, Namespace, Span};
+use sleek_utils::Node;
+
+use super::{
+    foreign::is_special_foreign, formatting::is_formatting, is_heading, is_html, InsertionMode,
+    Scope, TreeBuilder,
+};
+
+/// Checks if an element has special parsing rules. End tags never close these elements implicitly.
+pub(super) fn is_special(element: &ElementRef) -> bool {
+    if element.namespace() != Namespace::Html {
+        return is_special_foreign(element);
+    }
+    matches!(
+        element.tag_name(),
+        HtmlTag::Address
+            | HtmlTag::Applet
+            | HtmlTag::Area
+            | HtmlTag::Article
+            | HtmlTag::Aside
+            | HtmlTag::Base
+            | HtmlTag::Basefont
+            | HtmlTag::Bgsound
+            | HtmlTag::Blockquote
+            | HtmlTag::Body
+            | HtmlTag::Br
+            | HtmlTag::Button
+            | HtmlTag::Caption
+            | HtmlTag::Center
+            | HtmlTag::Col
+            | HtmlTag::Colgroup
+            | HtmlTag::Dd
+            | HtmlTag::Details
+            | HtmlTag::Dir
+            | HtmlTag::Div
+            | HtmlTag::Dl
+            | HtmlTag::Dt
+            | HtmlTag::Embed
+            | HtmlTag::Fieldset
+            | HtmlTag::Figcaption
+            | HtmlTag::Figure
+            | HtmlTag::Footer
+            | HtmlTag::Form
+            | HtmlTag::Frame
+            | HtmlTag::Frameset
+            | HtmlTag::H1
+            | HtmlTag::H2
+            | HtmlTag::H3
+            | HtmlTag::H4
+            | HtmlTag::H5
+            | HtmlTag::H6
+            | HtmlTag::Head
+            | HtmlTag::Header
+            | HtmlTag::Hgroup
+            | HtmlTag::Hr
+            | HtmlTag::Html
+            | HtmlTag::Iframe
+            | HtmlTag::Img
+            | HtmlTag::Input
+            | HtmlTag::Keygen
+            | HtmlTag::Li
+            | HtmlTag::Link
+            | HtmlTag::Main
+            | HtmlTag::Marquee
+            | HtmlTag::Menu
+            | HtmlTag::Meta
+            | HtmlTag::Nav
+            | HtmlTag::Noembed
+            | HtmlTag::Noframes
+            | HtmlTag::Noscript
+            | HtmlTag::Object
+            | HtmlTag::Ol
+            | HtmlTag::P
+            | HtmlTag::Param
+            | HtmlTag::Plaintext
+            | HtmlTag::Pre
+            | HtmlTag::Script
+            | HtmlTag::Section
+            | HtmlTag::Select
+            | HtmlTag::Source
+            | HtmlTag::Style
+            | HtmlTag::Summary
+            | HtmlTag::Table
+            | HtmlTag::Tbody
+            | HtmlTag::Td
+            | HtmlTag::Template
+            | HtmlTag::Textarea
+            | HtmlTag::Tfoot
+            | HtmlTag::Th
+            | HtmlTag::Thead
+            | HtmlTag::Title
+            | HtmlTag::Tr
+            | HtmlTag::Track
+            | HtmlTag::Ul
+            | HtmlTag::Wbr
+            | HtmlTag::Xmp
+    )
+}
+
+/// Checks if an element may be left open at the end of the body without an error.
+fn may_stay_open(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::Dd
+            | HtmlTag::Dt
+            | HtmlTag::Li
+            | HtmlTag::Optgroup
+            | HtmlTag::Option
+            | HtmlTag::P
+            | HtmlTag::Rb
+            | HtmlTag::Rp
+            | HtmlTag::Rt
+            | HtmlTag::Rtc
+            | HtmlTag::Tbody
+            | HtmlTag::Td
+            | HtmlTag::Tfoot
+            | HtmlTag::Th
+            | HtmlTag::Thead
+            | HtmlTag::Tr
+            | HtmlTag::Body
+            | HtmlTag::Html
+    )
+}
+
+impl TreeBuilder {
+    pub(super) fn in_body(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Text { content, span } => {
+                self.reconstruct_formatting();
+                if !content.chars().all(|ch| ch.is_ascii_whitespace()) {
+                    self.frameset_ok = false;
+                }
+                self.insert_text(content, span);
+            }
+            HtmlToken::Comment { content, span } => self.insert_comment(content, span),
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag { .. } => self.in_body_start_tag(token),
+            HtmlToken::ClosingTag { .. } => self.in_body_end_tag(token),
+            HtmlToken::EOF { .. } => {
+                if !self.template_modes.is_empty() {
+                    return self.in_template(token);
+                }
+                self.check_unclosed();
+                self.stop();
+            }
+        }
+    }
+
+    fn in_body_start_tag(&mut self, token: HtmlToken) {
+        let HtmlToken::OpeningTag { name, .. } = &token else {
+            return;
+        };
+        match name {
+            HtmlTag::Html => {
+                self.unexpected(&token);
+                if !self.is_open(&HtmlTag::Template) {
+                    if let (Some(mut html), HtmlToken::OpeningTag { attributes, .. }) =
+                        (self.open_elements.first().cloned(), token)
+                    {
+                        html.merge_attributes(attributes);
+                    }
+                }
+            }
+            HtmlTag::Base
+            | HtmlTag::Basefont
+            | HtmlTag::Bgsound
+            | HtmlTag::Link
+            | HtmlTag::Meta
+            | HtmlTag::Noframes
+            | HtmlTag::Script
+            | HtmlTag::Style
+            | HtmlTag::Template
+            | HtmlTag::Title => self.in_head(token),
+            HtmlTag::Body => {
+                self.unexpected(&token);
+                let body = self
+                    .open_elements
+                    .get(1)
+                    .filter(|body| body.tag_name() == &HtmlTag::Body)
+                    .cloned();
+                if let Some(mut body) = body.filter(|_| !self.is_open(&HtmlTag::Template)) {
+                    self.frameset_ok = false;
+                    if let HtmlToken::OpeningTag { attributes, .. } = token {
+                        body.merge_attributes(attributes);
+                    }
+                }
+            }
+            HtmlTag::Frameset => {
+                self.unexpected(&token);
+                let body = self
+                    .open_elements
+                    .get(1)
+                    .filter(|body| body.tag_name() == &HtmlTag::Body)
+                    .cloned();
+                if let Some(body) = body.filter(|_| self.frameset_ok) {
+                    self.open_elements[0].clone().remove(&body);
+                    self.open_elements.truncate(1);
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InFrameset;
+                }
+            }
+            HtmlTag::Textarea => {
+                self.insert_text_element(token);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+            }
+            HtmlTag::Address
+            | HtmlTag::Article
+            | HtmlTag::Aside
+            | HtmlTag::Blockquote
+            | HtmlTag::Center
+            | HtmlTag::Details
+            | HtmlTag::Dialog
+            | HtmlTag::Dir
+            | HtmlTag::Div
+            | HtmlTag::Dl
+            | HtmlTag::Fieldset
+            | HtmlTag::Figcaption
+            | HtmlTag::Figure
+            | HtmlTag::Footer
+            | HtmlTag::Header
+            | HtmlTag::Hgroup
+            | HtmlTag::Main
+            | HtmlTag::Menu
+            | HtmlTag::Nav
+            | HtmlTag::Ol
+            | HtmlTag::P
+            | HtmlTag::Section
+            | HtmlTag::Summary
+            | HtmlTag::Ul => {
+                self.close_p_in_button_scope();
+                self.insert_element(token);
+            }
+            name if is_heading(name) => {
+                self.close_p_in_button_scope();
+                // Headings cannot be nested.
+                if self
+                    .current()
+                    .is_some_and(|current| is_heading(current.tag_name()))
+                {
+                    self.unexpected(&token);
+                    self.open_elements.pop();
+                }
+                self.insert_element(token);
+            }
+            HtmlTag::Pre => {
+                self.close_p_in_button_scope();
+                self.insert_element(token);
+                self.ignore_lf = true;
+                self.frameset_ok = false;
+            }
+            HtmlTag::Form => {
+                if self.form.is_some() && !self.is_open(&HtmlTag::Template) {
+                    return self.unexpected(&token);
+                }
+                self.close_p_in_button_scope();
+                let form = self.insert_element(token);
+                if !self.is_open(&HtmlTag::Template) {
+                    self.form = Some(form);
+                }
+            }
+            HtmlTag::Li | HtmlTag::Dd | HtmlTag::Dt => {
+                self.frameset_ok = false;
+                self.close_list_item(&token);
+                self.close_p_in_button_scope();
+                self.insert_element(token);
+            }
+            HtmlTag::Plaintext => {
+                self.close_p_in_button_scope();
+                self.insert_element(token);
+            }
+            HtmlTag::Button => {
+                if self.in_scope(&HtmlTag::Button, Scope::Default) {
+                    self.unexpected(&token);
+                    self.generate_implied_end_tags(None);
+                    self.pop_until(&HtmlTag::Button);
+                }
+                self.reconstruct_formatting();
+                self.insert_element(token);
+                self.frameset_ok = false;
+            }
+            HtmlTag::A => {
+                if let Some(index) = self.formatting_element(&HtmlTag::A) {
+                    self.unexpected(&token);
+                    let end_tag = HtmlToken::ClosingTag {
+                        name: HtmlTag::A,
+                        span: Span::over(self.location, self.location),
+                    };
+                    let element = self.formatting_at(index);
+                    self.adoption_agency(&end_tag);
+                    if let Some(index) = self.formatting_index_of(&element) {
+                        self.forget_formatting(index);
+                    }
+                    self.open_elements.retain(|open| open != &element);
+                }
+                self.reconstruct_formatting();
+                self.insert_formatting(token);
+            }
+            HtmlTag::Nobr => {
+                self.reconstruct_formatting();
+                if self.in_scope(&HtmlTag::Nobr, Scope::Default) {
+                    self.unexpected(&token);
+                    let end_tag = HtmlToken::ClosingTag {
+                        name: HtmlTag::Nobr,
+                        span: Span::over(self.location, self.location),
+                    };
+                    self.adoption_agency(&end_tag);
+                    self.reconstruct_formatting();
+                }
+                self.insert_formatting(token);
+            }
+            name if is_formatting(name) => {
+                self.reconstruct_formatting();
+                self.insert_formatting(token);
+            }
+            HtmlTag::Applet | HtmlTag::Marquee | HtmlTag::Object => {
+                self.reconstruct_formatting();
+                self.insert_element(token);
+                self.push_formatting_marker();
+                self.frameset_ok = false;
+            }
+            HtmlTag::Xmp => {
+                self.close_p_in_button_scope();
+                self.reconstruct_formatting();
+                self.frameset_ok = false;
+                self.insert_text_element(token);
+            }
+            HtmlTag::Iframe => {
+                self.frameset_ok = false;
+                self.insert_text_element(token);
+            }
+            HtmlTag::Noembed => self.insert_text_element(token),
+            HtmlTag::Noscript if self.options.scripting => self.insert_text_element(token),
+            HtmlTag::Area
+            | HtmlTag::Br
+            | HtmlTag::Embed
+            | HtmlTag::Img
+            | HtmlTag::Keygen
+            | HtmlTag::Wbr => {
+                self.reconstruct_formatting();
+                self.insert_void(token);
+                self.frameset_ok = false;
+            }
+            HtmlTag::Hr => {
+                self.close_p_in_button_scope();
+                self.insert_void(token);
+                self.frameset_ok = false;
+            }
+            HtmlTag::Input => {
+                self.reconstruct_formatting();
+                let hidden = matches!(&token, HtmlToken::OpeningTag { attributes, .. }
+                    if attributes.iter().any(|attribute| attribute.key == "type"
+                        && attribute.value.as_ref().is_some_and(|value| value.eq_ignore_ascii_case("hidden"))));
+                self.insert_void(token);
+                if !hidden {
+                    self.frameset_ok = false;
+                }
+            }
+            HtmlTag::Param | HtmlTag::Source | HtmlTag::Track => self.insert_void(token),
+            HtmlTag::Optgroup | HtmlTag::Option => {
+                if self.current_is(&HtmlTag::Option) {
+                    self.open_elements.pop();
+                }
+                self.reconstruct_formatting();
+                self.insert_element(token);
+            }
+            HtmlTag::Rb | HtmlTag::Rtc => {
+                if self.in_scope(&HtmlTag::Ruby, Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    if !self.current_is(&HtmlTag::Ruby) {
+                        self.unexpected(&token);
+                    }
+                }
+                self.insert_element(token);
+            }
+            HtmlTag::Rp | HtmlTag::Rt => {
+                if self.in_scope(&HtmlTag::Ruby, Scope::Default) {
+                    self.generate_implied_end_tags(Some(&HtmlTag::Rtc));
+                    if !self.current_is(&HtmlTag::Rtc) && !self.current_is(&HtmlTag::Ruby) {
+                        self.unexpected(&token);
+                    }
+                }
+                self.insert_element(token);
+            }
+            HtmlTag::Table => {
+                // Tables can be inside paragraphs in quirks mode.
+                if self.tree.mode != DocumentMode::Quirks {
+                    self.close_p_in_button_scope();
+                }
+                self.insert_element(token);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            HtmlTag::Caption
+            | HtmlTag::Col
+            | HtmlTag::Colgroup
+            | HtmlTag::Frame
+            | HtmlTag::Head
+            | HtmlTag::Tbody
+            | HtmlTag::Td
+            | HtmlTag::Tfoot
+            | HtmlTag::Th
+            | HtmlTag::Thead
+            | HtmlTag::Tr => self.unexpected(&token),
+            HtmlTag::Svg => {
+                self.reconstruct_formatting();
+                self.insert_foreign_element(token, Namespace::Svg);
+            }
+            HtmlTag::Unknown(name) if name == "math" => {
+                self.reconstruct_formatting();
+                self.insert_foreign_element(token, Namespace::MathMl);
+            }
+            name if name.is_void() => {
+                self.reconstruct_formatting();
+                self.insert_void(token);
+            }
+            _ => {
+                self.reconstruct_formatting();
+                self.insert_element(token);
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, token: HtmlToken) {
+        let HtmlToken::ClosingTag { name, span } = &token else {
+            return;
+        };
+        match name {
+            HtmlTag::Template => self.in_head(token),
+            HtmlTag::Body => {
+                if !self.in_scope(&HtmlTag::Body, Scope::Default) {
+                    return self.unexpected(&token);
+                }
+                self.check_unclosed();
+                if let Some(body) = self.open_elements.get(1) {
+                    body.element().location.close_tag = Some(span.clone());
+                }
+                self.mode = InsertionMode::AfterBody;
+            }
+            HtmlTag::Html => {
+                if !self.in_scope(&HtmlTag::Body, Scope::Default) {
+                    return self.unexpected(&token);
+                }
+                self.check_unclosed();
+                self.reprocess(InsertionMode::AfterBody, token);
+            }
+            HtmlTag::Address
+            | HtmlTag::Article
+            | HtmlTag::Aside
+            | HtmlTag::Blockquote
+            | HtmlTag::Button
+            | HtmlTag::Center
+            | HtmlTag::Details
+            | HtmlTag::Dialog
+            | HtmlTag::Dir
+            | HtmlTag::Div
+            | HtmlTag::Dl
+            | HtmlTag::Fieldset
+            | HtmlTag::Figcaption
+            | HtmlTag::Figure
+            | HtmlTag::Footer
+            | HtmlTag::Header
+            | HtmlTag::Hgroup
+            | HtmlTag::Main
+            | HtmlTag::Menu
+            | HtmlTag::Nav
+            | HtmlTag::Ol
+            | HtmlTag::Pre
+            | HtmlTag::Section
+            | HtmlTag::Summary
+            | HtmlTag::Ul => {
+                if !self.in_scope(name, Scope::Default) {
+                    return self.unexpected(&token);
+                }
+                let (name, span) = (name.clone(), span.clone());
+                self.generate_implied_end_tags(None);
+                self.expect_current(&name);
+                self.close_element(&name, span);
+            }
+            HtmlTag::Form => {
+                let form = self.form.take();
+                if self.is_open(&HtmlTag::Template) {
+                    if !self.in_scope(&HtmlTag::Form, Scope::Default) {
+                        return self.unexpected(&token);
+                    }
+                    let span = span.clone();
+                    self.generate_implied_end_tags(None);
+                    self.expect_current(&HtmlTag::Form);
+                    return self.close_element(&HtmlTag::Form, span);
+                }
+                let Some(form) = form
+                    .filter(|form| self.in_scope_where(|element| element == form, Scope::Default))
+                else {
+                    return self.unexpected(&token);
+                };
+                self.generate_implied_end_tags(None);
+                self.expect_current(&HtmlTag::Form);
+                // Only the form is removed, elements opened inside it stay open.
+                self.open_elements.retain(|element| element != &form);
+                form.element().location.close_tag = Some(span.clone());
+            }
+            HtmlTag::P => {
+                let span = span.clone();
+                if !self.in_scope(&HtmlTag::P, Scope::Button) {
+                    self.unexpected(&token);
+                    self.insert_implied(HtmlTag::P);
+                }
+                self.generate_implied_end_tags(Some(&HtmlTag::P));
+                self.expect_current(&HtmlTag::P);
+                self.close_element(&HtmlTag::P, span);
+            }
+            HtmlTag::Li | HtmlTag::Dd | HtmlTag::Dt => {
+                let scope = if name == &HtmlTag::Li {
+                    Scope::ListItem
+                } else {
+                    Scope::Default
+                };
+                if !self.in_scope(name, scope) {
+                    return self.unexpected(&token);
+                }
+                let (name, span) = (name.clone(), span.clone());
+                self.generate_implied_end_tags(Some(&name));
+                self.expect_current(&name);
+                self.close_element(&name, span);
+            }
+            name if is_heading(name) => {
+                if !self.in_scope_where(|element| is_heading(element.tag_name()), Scope::Default) {
+                    return self.unexpected(&token);
+                }
+                let (name, span) = (name.clone(), span.clone());
+                self.generate_implied_end_tags(None);
+                self.expect_current(&name);
+                // Any heading closes any other heading.
+                while let Some(element) = self.open_elements.pop() {
+                    if is_heading(element.tag_name()) {
+                        element.element().location.close_tag = Some(span);
+                        break;
+                    }
+                }
+            }
+            name if is_formatting(name) => {
+                if !self.adoption_agency(&token) {
+                    let (name, span) = (name.clone(), span.clone());
+                    self.close_any(&token, &name, span);
+                }
+            }
+            HtmlTag::Applet | HtmlTag::Marquee | HtmlTag::Object => {
+                if !self.in_scope(name, Scope::Default) {
+                    return self.unexpected(&token);
+                }
+                let (name, span) = (name.clone(), span.clone());
+                self.generate_implied_end_tags(None);
+                self.expect_current(&name);
+                self.close_element(&name, span);
+                self.clear_formatting_to_marker();
+            }
+            // A </br> is treated as a <br>.
+            HtmlTag::Br => {
+                self.unexpected(&token);
+                self.in_body_start_tag(HtmlToken::OpeningTag {
+                    name: HtmlTag::Br,
+                    attributes: vec![],
+                    self_closing: false,
+                    span: span.clone(),
+                });
+            }
+            _ => {
+                let (name, span) = (name.clone(), span.clone());
+                self.close_any(&token, &name, span);
+            }
+        }
+    }
+
+    /// The rules for an end tag without special handling: close the nearest element with the same tag name,
+    /// unless an element with special parsing rules is open after it.
+    fn close_any(&mut self, token: &HtmlToken, name: &HtmlTag, span: Span) {
+        for index in (0..self.open_elements.len()).rev() {
+            let element = &self.open_elements[index];
+            if is_html(element, name) {
+                self.generate_implied_end_tags(Some(name));
+                self.expect_current(name);
+                let element = self.open_elements[index].clone();
+                self.open_elements.truncate(index);
+                element.element().location.close_tag = Some(span);
+                return;
+            }
+            if is_special(element) {
+                return self.unexpected(token);
+            }
+        }
+    }
+
+    /// Close an open list item before a new one starts. A `<li>` closes a `<li>`, and a `<dd>` or `<dt>` closes either.
+    fn close_list_item(&mut self, token: &HtmlToken) {
+        let HtmlToken::OpeningTag { name, .. } = token else {
+            return;
+        };
+        let closes = |tag: &HtmlTag| match name {
+            HtmlTag::Li => tag == &HtmlTag::Li,
+            _ => matches!(tag, HtmlTag::Dd | HtmlTag::Dt),
+        };
+        for index in (0..self.open_elements.len()).rev() {
+            let element = self.open_elements[index].clone();
+            let tag = element.tag_name().clone();
+            let is_html = element.namespace() == Namespace::Html;
+            if is_html && closes(&tag) {
+                self.generate_implied_end_tags(Some(&tag));
+                self.expect_current(&tag);
+                self.pop_until(&tag);
+                return;
+            }
+            if is_special(&element)
+                && !(is_html && matches!(tag, HtmlTag::Address | HtmlTag::Div | HtmlTag::P))
+            {
+                return;
+            }
+        }
+    }
+
+    /// Report an element that is still open at the end of the body, if it cannot be closed implicitly.
+    fn check_unclosed(&mut self) {
+        let unclosed = self
+            .open_elements
+            .iter()
+            .rev()
+            .find(|element| !may_stay_open(element.tag_name()))
+            .cloned();
+        if let Some(element) = unclosed {
+            self.unclosed(&element);
+        }
+    }
+}
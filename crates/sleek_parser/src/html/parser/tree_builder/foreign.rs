@@ -0,0 +1,361 @@
+use sleek_ast::{ElementRef, HtmlAttribute, HtmlTag, HtmlToken, Namespace};
+
+use super::TreeBuilder;
+
+/// SVG element names that have capital letters. The tokenizer lowercases tag names, so they are restored before the element is created.
+const SVG_TAG_NAMES: [&str; 37] = [
+    "altGlyph",
+    "altGlyphDef",
+    "altGlyphItem",
+    "animateColor",
+    "animateMotion",
+    "animateTransform",
+    "clipPath",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feConvolveMatrix",
+    "feDiffuseLighting",
+    "feDisplacementMap",
+    "feDistantLight",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feImage",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "fePointLight",
+    "feSpecularLighting",
+    "feSpotLight",
+    "feTile",
+    "feTurbulence",
+    "foreignObject",
+    "glyphRef",
+    "linearGradient",
+    "radialGradient",
+    "textPath",
+];
+
+/// SVG attribute names that have capital letters.
+const SVG_ATTRIBUTE_NAMES: [&str; 58] = [
+    "attributeName",
+    "attributeType",
+    "baseFrequency",
+    "baseProfile",
+    "calcMode",
+    "clipPathUnits",
+    "diffuseConstant",
+    "edgeMode",
+    "filterUnits",
+    "glyphRef",
+    "gradientTransform",
+    "gradientUnits",
+    "kernelMatrix",
+    "kernelUnitLength",
+    "keyPoints",
+    "keySplines",
+    "keyTimes",
+    "lengthAdjust",
+    "limitingConeAngle",
+    "markerHeight",
+    "markerUnits",
+    "markerWidth",
+    "maskContentUnits",
+    "maskUnits",
+    "numOctaves",
+    "pathLength",
+    "patternContentUnits",
+    "patternTransform",
+    "patternUnits",
+    "pointsAtX",
+    "pointsAtY",
+    "pointsAtZ",
+    "preserveAlpha",
+    "preserveAspectRatio",
+    "primitiveUnits",
+    "refX",
+    "refY",
+    "repeatCount",
+    "repeatDur",
+    "requiredExtensions",
+    "requiredFeatures",
+    "specularConstant",
+    "specularExponent",
+    "spreadMethod",
+    "startOffset",
+    "stdDeviation",
+    "stitchTiles",
+    "surfaceScale",
+    "systemLanguage",
+    "tableValues",
+    "targetX",
+    "targetY",
+    "textLength",
+    "viewBox",
+    "viewTarget",
+    "xChannelSelector",
+    "yChannelSelector",
+    "zoomAndPan",
+];
+
+/// MathML attribute names that have capital letters.
+const MATHML_ATTRIBUTE_NAMES: [&str; 1] = ["definitionURL"];
+
+/// Restore the capital letters of a name from a list of names.
+fn adjust_case(name: &str, names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .find(|adjusted| adjusted.eq_ignore_ascii_case(name))
+        .map(|adjusted| adjusted.to_string())
+}
+
+/// Restore the capital letters of the attribute names of a foreign element, like `viewBox`.
+fn adjust_attributes(attributes: &mut [HtmlAttribute], namespace: Namespace) {
+    let names: &[&str] = match namespace {
+        Namespace::Svg => &SVG_ATTRIBUTE_NAMES,
+        Namespace::MathMl => &MATHML_ATTRIBUTE_NAMES,
+        Namespace::Html => return,
+    };
+    for attribute in attributes {
+        if let Some(name) = adjust_case(&attribute.key, names) {
+            attribute.key = name;
+        }
+    }
+}
+
+/// Checks if a foreign element has one of a list of names.
+fn is_named(element: &ElementRef, names: &[&str]) -> bool {
+    names.contains(&element.tag_name().to_string().as_str())
+}
+
+/// Checks if an element is a MathML element whose text and child elements are HTML, like `<mi>`.
+fn is_mathml_text_integration_point(element: &ElementRef) -> bool {
+    element.namespace() == Namespace::MathMl
+        && is_named(element, &["mi", "mo", "mn", "ms", "mtext"])
+}
+
+/// Checks if an element is a foreign element whose content is HTML, like `<foreignObject>`.
+fn is_html_integration_point(element: &ElementRef) -> bool {
+    match element.namespace() {
+        Namespace::MathMl => {
+            is_named(element, &["annotation-xml"])
+                && element
+                    .element()
+                    .attributes
+                    .get("encoding")
+                    .and_then(|encoding| encoding.data.as_deref())
+                    .is_some_and(|encoding| {
+                        encoding.eq_ignore_ascii_case("text/html")
+                            || encoding.eq_ignore_ascii_case("application/xhtml+xml")
+                    })
+        }
+        Namespace::Svg => is_named(element, &["foreignObject", "desc", "title"]),
+        Namespace::Html => false,
+    }
+}
+
+/// Checks if a foreign element has special parsing rules, because HTML can be nested in it.
+/// Like HTML elements such as `<td>`, these elements limit scopes, and end tags never close them implicitly.
+pub(super) fn is_special_foreign(element: &ElementRef) -> bool {
+    match element.namespace() {
+        Namespace::MathMl => is_named(
+            element,
+            &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"],
+        ),
+        Namespace::Svg => is_named(element, &["foreignObject", "desc", "title"]),
+        Namespace::Html => false,
+    }
+}
+
+/// Checks if a start tag is the root of foreign content, `<svg>` or `<math>`.
+pub(super) fn is_foreign_root(name: &HtmlTag) -> bool {
+    match name {
+        HtmlTag::Svg => true,
+        HtmlTag::Unknown(name) => name == "math",
+        _ => false,
+    }
+}
+
+/// Checks if a token in foreign content is HTML, which closes the foreign elements around it.
+fn breaks_out(token: &HtmlToken) -> bool {
+    match token {
+        HtmlToken::OpeningTag {
+            name: HtmlTag::Font,
+            attributes,
+            ..
+        } => attributes
+            .iter()
+            .any(|attribute| matches!(attribute.key.as_str(), "color" | "face" | "size")),
+        HtmlToken::OpeningTag { name, .. } => match name {
+            HtmlTag::B
+            | HtmlTag::Big
+            | HtmlTag::Blockquote
+            | HtmlTag::Body
+            | HtmlTag::Br
+            | HtmlTag::Center
+            | HtmlTag::Code
+            | HtmlTag::Dd
+            | HtmlTag::Div
+            | HtmlTag::Dl
+            | HtmlTag::Dt
+            | HtmlTag::Em
+            | HtmlTag::Embed
+            | HtmlTag::H1
+            | HtmlTag::H2
+            | HtmlTag::H3
+            | HtmlTag::H4
+            | HtmlTag::H5
+            | HtmlTag::H6
+            | HtmlTag::Head
+            | HtmlTag::Hr
+            | HtmlTag::I
+            | HtmlTag::Img
+            | HtmlTag::Li
+            | HtmlTag::Menu
+            | HtmlTag::Meta
+            | HtmlTag::Nobr
+            | HtmlTag::Ol
+            | HtmlTag::P
+            | HtmlTag::Pre
+            | HtmlTag::Ruby
+            | HtmlTag::S
+            | HtmlTag::Small
+            | HtmlTag::Span
+            | HtmlTag::Strong
+            | HtmlTag::Strike
+            | HtmlTag::Sub
+            | HtmlTag::Sup
+            | HtmlTag::Table
+            | HtmlTag::Tt
+            | HtmlTag::U
+            | HtmlTag::Ul
+            | HtmlTag::Var => true,
+            HtmlTag::Unknown(name) => name == "listing",
+            _ => false,
+        },
+        HtmlToken::ClosingTag { name, .. } => matches!(name, HtmlTag::Br | HtmlTag::P),
+        _ => false,
+    }
+}
+
+impl TreeBuilder {
+    /// Checks if a token is processed by the rules for foreign content, instead of the rules of the insertion mode.
+    /// Content inside elements like `<foreignObject>` is HTML again.
+    pub(super) fn is_foreign(&self, token: &HtmlToken) -> bool {
+        let Some(current) = self.current() else {
+            return false;
+        };
+        if current.namespace() == Namespace::Html {
+            return false;
+        }
+        match token {
+            HtmlToken::OpeningTag { name, .. } if is_mathml_text_integration_point(current) => {
+                matches!(name, HtmlTag::Unknown(name) if name == "mglyph" || name == "malignmark")
+            }
+            HtmlToken::Text { .. } if is_mathml_text_integration_point(current) => false,
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Svg, ..
+            } if is_named(current, &["annotation-xml"]) => false,
+            HtmlToken::OpeningTag { .. } | HtmlToken::Text { .. } => {
+                !is_html_integration_point(current)
+            }
+            HtmlToken::EOF { .. } => false,
+            _ => true,
+        }
+    }
+
+    pub(super) fn foreign_content(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Text { content, span } => {
+                if !content.chars().all(|ch| ch.is_ascii_whitespace()) {
+                    self.frameset_ok = false;
+                }
+                self.insert_text(content, span);
+            }
+            HtmlToken::Comment { content, span } => self.insert_comment(content, span),
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            token if breaks_out(&token) => {
+                self.unexpected(&token);
+                while self.current().is_some_and(|current| {
+                    current.namespace() != Namespace::Html
+                        && !is_mathml_text_integration_point(current)
+                        && !is_html_integration_point(current)
+                }) {
+                    self.open_elements.pop();
+                }
+                self.process_as(self.mode, token);
+            }
+            HtmlToken::OpeningTag { .. } => {
+                let namespace = self
+                    .current()
+                    .map_or(Namespace::Html, |current| current.namespace());
+                self.insert_foreign_element(token, namespace);
+            }
+            HtmlToken::ClosingTag { .. } => self.close_foreign(token),
+            HtmlToken::EOF { .. } => self.process_as(self.mode, token),
+        }
+    }
+
+    /// Insert an element in a foreign namespace for an opening tag, restoring the case of its names.
+    /// Foreign elements can be self-closing, like `<circle/>`.
+    pub(super) fn insert_foreign_element(&mut self, token: HtmlToken, namespace: Namespace) {
+        let HtmlToken::OpeningTag {
+            name,
+            mut attributes,
+            span,
+            self_closing,
+        } = token
+        else {
+            return;
+        };
+        let name = match namespace {
+            Namespace::Svg => {
+                adjust_case(&name.to_string(), &SVG_TAG_NAMES).map_or(name, HtmlTag::new)
+            }
+            _ => name,
+        };
+        adjust_attributes(&mut attributes, namespace);
+        let element = ElementRef::init(name, attributes, span);
+        element.element().namespace = namespace;
+        self.insert(element);
+        if self_closing {
+            self.open_elements.pop();
+        }
+    }
+
+    /// Close the foreign element an end tag matches, without case.
+    /// If an HTML element is open after it, the end tag is processed by the rules of the insertion mode instead.
+    fn close_foreign(&mut self, token: HtmlToken) {
+        let HtmlToken::ClosingTag { name, span } = &token else {
+            return;
+        };
+        let name = name.to_string();
+        let matches =
+            |element: &ElementRef| element.tag_name().to_string().eq_ignore_ascii_case(&name);
+        let Some(mut index) = self.open_elements.len().checked_sub(1) else {
+            return;
+        };
+        if !matches(&self.open_elements[index]) {
+            self.unexpected(&token);
+        }
+        while index > 0 {
+            let element = self.open_elements[index].clone();
+            if matches(&element) {
+                self.open_elements.truncate(index);
+                element.element().location.close_tag = Some(span.clone());
+                return;
+            }
+            index -= 1;
+            if self.open_elements[index].namespace() == Namespace::Html {
+                return self.process_as(self.mode, token);
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use sleek_ast::{ElementRef, HtmlNode, HtmlTag, HtmlToken};
+use sleek_utils::Node;
+
+use super::{body::is_special, is_html, Scope, TreeBuilder};
+
+/// An entry in the list of active formatting elements.
+pub(super) enum Formatting {
+    /// Separates formatting elements inside elements like `<td>` or `<template>` from the ones outside them.
+    Marker,
+    /// A formatting element, and the token that created it, so it can be created again.
+    Element(ElementRef, HtmlToken),
+}
+
+impl Formatting {
+    fn element(&self) -> Option<&ElementRef> {
+        match self {
+            Formatting::Element(element, _) => Some(element),
+            Formatting::Marker => None,
+        }
+    }
+}
+
+/// Checks if an element is a formatting element, like `<b>` or `<a>`.
+pub(super) fn is_formatting(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::A
+            | HtmlTag::B
+            | HtmlTag::Big
+            | HtmlTag::Code
+            | HtmlTag::Em
+            | HtmlTag::Font
+            | HtmlTag::I
+            | HtmlTag::Nobr
+            | HtmlTag::S
+            | HtmlTag::Small
+            | HtmlTag::Strike
+            | HtmlTag::Strong
+            | HtmlTag::Tt
+            | HtmlTag::U
+    )
+}
+
+/// Checks if two opening tags have the same name and attributes, in any order.
+fn same_tag(a: &HtmlToken, b: &HtmlToken) -> bool {
+    match (a, b) {
+        (
+            HtmlToken::OpeningTag {
+                name: a_name,
+                attributes: a_attributes,
+                ..
+            },
+            HtmlToken::OpeningTag {
+                name: b_name,
+                attributes: b_attributes,
+                ..
+            },
+        ) => {
+            a_name == b_name
+                && a_attributes.len() == b_attributes.len()
+                && a_attributes.iter().all(|a| {
+                    b_attributes
+                        .iter()
+                        .any(|b| a.key == b.key && a.value == b.value)
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Remove an element from its parent.
+fn detach(element: &ElementRef) {
+    if let Some(mut parent) = element.parent() {
+        parent.remove(element);
+    }
+}
+
+impl TreeBuilder {
+    /// Insert an element for a formatting tag, and add it to the list of active formatting elements.
+    pub(super) fn insert_formatting(&mut self, token: HtmlToken) {
+        let copy = token.clone();
+        let element = self.insert_element(token);
+        self.push_formatting(element, copy);
+    }
+
+    fn push_formatting(&mut self, element: ElementRef, token: HtmlToken) {
+        // Only three identical elements are kept after the last marker.
+        let identical: Vec<usize> = self
+            .formatting
+            .iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, entry)| !matches!(entry, Formatting::Marker))
+            .filter(|(_, entry)| matches!(entry, Formatting::Element(_, other) if same_tag(other, &token)))
+            .map(|(index, _)| index)
+            .collect();
+        if identical.len() >= 3 {
+            self.formatting.remove(identical[identical.len() - 1]);
+        }
+        self.formatting.push(Formatting::Element(element, token));
+    }
+
+    pub(super) fn push_formatting_marker(&mut self) {
+        self.formatting.push(Formatting::Marker);
+    }
+
+    /// Remove formatting elements up to and including the last marker.
+    pub(super) fn clear_formatting_to_marker(&mut self) {
+        while let Some(entry) = self.formatting.pop() {
+            if let Formatting::Marker = entry {
+                break;
+            }
+        }
+    }
+
+    /// Find the last formatting element with a tag name after the last marker.
+    pub(super) fn formatting_element(&self, tag: &HtmlTag) -> Option<usize> {
+        for (index, entry) in self.formatting.iter().enumerate().rev() {
+            match entry {
+                Formatting::Marker => return None,
+                Formatting::Element(element, _) if element.tag_name() == tag => return Some(index),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// The formatting element at an index in the list of active formatting elements.
+    pub(super) fn formatting_at(&self, index: usize) -> ElementRef {
+        self.formatting[index].element().unwrap().clone()
+    }
+
+    pub(super) fn formatting_index_of(&self, element: &ElementRef) -> Option<usize> {
+        self.formatting
+            .iter()
+            .position(|entry| entry.element() == Some(element))
+    }
+
+    fn open_index_of(&self, element: &ElementRef) -> Option<usize> {
+        self.open_elements.iter().position(|open| open == element)
+    }
+
+    /// Remove an element from the list of active formatting elements and the stack of open elements.
+    pub(super) fn forget_formatting(&mut self, index: usize) {
+        if let Formatting::Element(element, _) = self.formatting.remove(index) {
+            self.open_elements.retain(|open| open != &element);
+        }
+    }
+
+    /// Create an element for a token again, without inserting it.
+    fn recreate(token: &HtmlToken) -> ElementRef {
+        match token.clone() {
+            HtmlToken::OpeningTag {
+                name,
+                attributes,
+                span,
+                ..
+            } => ElementRef::init(name, attributes, span),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Open formatting elements again that were closed implicitly, e.g. the `<b>` in `<p><b>text<p>more`.
+    pub(super) fn reconstruct_formatting(&mut self) {
+        let Some(last) = self.formatting.last() else {
+            return;
+        };
+        if matches!(last, Formatting::Marker) || self.is_formatting_open(last) {
+            return;
+        }
+        let mut index = self.formatting.len() - 1;
+        while index > 0 {
+            let previous = &self.formatting[index - 1];
+            if matches!(previous, Formatting::Marker) || self.is_formatting_open(previous) {
+                break;
+            }
+            index -= 1;
+        }
+        for index in index..self.formatting.len() {
+            if let Formatting::Element(_, token) = &self.formatting[index] {
+                let token = token.clone();
+                let element = Self::recreate(&token);
+                self.insert(element.clone());
+                self.formatting[index] = Formatting::Element(element, token);
+            }
+        }
+    }
+
+    fn is_formatting_open(&self, entry: &Formatting) -> bool {
+        entry
+            .element()
+            .is_some_and(|element| self.open_elements.contains(element))
+    }
+
+    /// The adoption agency algorithm, which fixes misnested formatting elements like `<b><i>text</b></i>`.
+    /// Returns false if there is no formatting element to close, and the end tag should be handled like any other.
+    pub(super) fn adoption_agency(&mut self, token: &HtmlToken) -> bool {
+        let HtmlToken::ClosingTag {
+            name: subject,
+            span,
+        } = token
+        else {
+            return true;
+        };
+
+        if let Some(current) = self.current() {
+            if is_html(current, subject) && self.formatting_index_of(current).is_none() {
+                let current = self.open_elements.pop().unwrap();
+                current.element().location.close_tag = Some(span.clone());
+                return true;
+            }
+        }
+
+        for _ in 0..8 {
+            let Some(formatting_index) = self.formatting_element(subject) else {
+                return false;
+            };
+            let formatting = self.formatting_at(formatting_index);
+
+            let Some(formatting_open_index) = self.open_index_of(&formatting) else {
+                self.unexpected(token);
+                self.formatting.remove(formatting_index);
+                return true;
+            };
+            if !self.in_scope_where(|element| element == &formatting, Scope::Default) {
+                self.unexpected(token);
+                return true;
+            }
+            if self.current() != Some(&formatting) {
+                self.unexpected(token);
+            }
+
+            let furthest_block = self.open_elements[formatting_open_index + 1..]
+                .iter()
+                .position(is_special)
+                .map(|index| index + formatting_open_index + 1);
+            let Some(furthest_block_index) = furthest_block else {
+                self.open_elements.truncate(formatting_open_index);
+                self.formatting.remove(formatting_index);
+                formatting.element().location.close_tag = Some(span.clone());
+                return true;
+            };
+            let furthest_block = self.open_elements[furthest_block_index].clone();
+            let common_ancestor = self.open_elements[formatting_open_index - 1].clone();
+            let mut bookmark = formatting_index;
+
+            let mut last_node = furthest_block.clone();
+            let mut node_index = furthest_block_index;
+            let mut inner_loop = 0;
+            loop {
+                inner_loop += 1;
+                node_index -= 1;
+                let node = self.open_elements[node_index].clone();
+                if node == formatting {
+                    break;
+                }
+                let mut node_formatting_index = self.formatting_index_of(&node);
+                if inner_loop > 3 {
+                    if let Some(index) = node_formatting_index.take() {
+                        self.formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
+                }
+                let Some(node_formatting_index) = node_formatting_index else {
+                    self.open_elements.remove(node_index);
+                    continue;
+                };
+
+                let Formatting::Element(_, node_token) = &self.formatting[node_formatting_index]
+                else {
+                    unreachable!()
+                };
+                let node_token = node_token.clone();
+                let mut new_node = Self::recreate(&node_token);
+                self.formatting[node_formatting_index] =
+                    Formatting::Element(new_node.clone(), node_token);
+                self.open_elements[node_index] = new_node.clone();
+
+                if last_node == furthest_block {
+                    bookmark = node_formatting_index + 1;
+                }
+                detach(&last_node);
+                new_node.append(&last_node);
+                last_node = new_node;
+            }
+
+            detach(&last_node);
+            let place = self.appropriate_place(Some(&common_ancestor));
+            self.insert_node(place, HtmlNode::Element(last_node.clone()));
+
+            // Entries may have been removed before the formatting element.
+            let formatting_index = self.formatting_index_of(&formatting).unwrap();
+            let Formatting::Element(_, formatting_token) = &self.formatting[formatting_index]
+            else {
+                unreachable!()
+            };
+            let formatting_token = formatting_token.clone();
+            let mut new_element = Self::recreate(&formatting_token);
+            for node in std::mem::take(&mut furthest_block.element().child_nodes) {
+                match node {
+                    HtmlNode::Element(child) => new_element.append(&child),
+                    node => new_element.element().child_nodes.push(node),
+                }
+            }
+            furthest_block.clone().append(&new_element);
+
+            self.formatting.remove(formatting_index);
+            if formatting_index < bookmark {
+                bookmark -= 1;
+            }
+            self.formatting.insert(
+                bookmark,
+                Formatting::Element(new_element.clone(), formatting_token),
+            );
+
+            self.open_elements.retain(|element| element != &formatting);
+            let furthest_block_index = self.open_index_of(&furthest_block).unwrap();
+            self.open_elements
+                .insert(furthest_block_index + 1, new_element);
+        }
+        true
+    }
+}
@@ -0,0 +1,757 @@
+use std::mem::{replace, take};
+
+use sleek_ast::{
+    ElementRef, ElementSpan, HtmlComment, HtmlDocType, HtmlDocument, HtmlNode, HtmlTag,
+    HtmlTextNode, HtmlToken, Namespace, Span,
+};
+use sleek_utils::{LineIndex, MutableCountRef, Node};
+
+use super::{HtmlParseResult, ParserResponse};
+use crate::html::{error::HtmlParseErrorType as ErrorType, Encoding, HtmlParseError, ParseOptions};
+
+mod body;
+mod foreign;
+mod formatting;
+mod modes;
+mod quirks;
+mod table;
+
+use formatting::Formatting;
+
+/// The insertion modes of tree construction. The mode decides what each token does to the tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    /// Inside an element whose content is all text, like `<script>` or `<title>`.
+    Text,
+    InTable,
+    /// Collecting text inside a table, to decide if it belongs in the table or before it.
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+/// The kinds of scope used to check if an element is open.
+#[derive(Clone, Copy)]
+enum Scope {
+    Default,
+    /// The default scope, also limited by lists.
+    ListItem,
+    /// The default scope, also limited by buttons.
+    Button,
+    /// Limited only by tables, and the elements that cannot be inside them.
+    Table,
+}
+
+impl Scope {
+    /// Checks if an element marks the edge of the scope.
+    fn is_boundary(&self, element: &ElementRef) -> bool {
+        let tag = element.tag_name();
+        if element.namespace() != Namespace::Html {
+            return !matches!(self, Scope::Table) && foreign::is_special_foreign(element);
+        }
+        let is_default_boundary = matches!(
+            tag,
+            HtmlTag::Applet
+                | HtmlTag::Caption
+                | HtmlTag::Html
+                | HtmlTag::Table
+                | HtmlTag::Td
+                | HtmlTag::Th
+                | HtmlTag::Marquee
+                | HtmlTag::Object
+                | HtmlTag::Template
+        );
+        match self {
+            Scope::Default => is_default_boundary,
+            Scope::ListItem => is_default_boundary || matches!(tag, HtmlTag::Ol | HtmlTag::Ul),
+            Scope::Button => is_default_boundary || tag == &HtmlTag::Button,
+            Scope::Table => matches!(tag, HtmlTag::Html | HtmlTag::Table | HtmlTag::Template),
+        }
+    }
+}
+
+/// Checks if an element is closed implicitly when its parent is closed, or when a sibling opens.
+fn has_implied_end_tag(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::Dd
+            | HtmlTag::Dt
+            | HtmlTag::Li
+            | HtmlTag::Optgroup
+            | HtmlTag::Option
+            | HtmlTag::P
+            | HtmlTag::Rb
+            | HtmlTag::Rp
+            | HtmlTag::Rt
+            | HtmlTag::Rtc
+    )
+}
+
+/// Checks if an element is an HTML element with a tag name. Foreign elements can have the same names, but not the same rules.
+fn is_html(element: &ElementRef, tag: &HtmlTag) -> bool {
+    element.namespace() == Namespace::Html && element.tag_name() == tag
+}
+
+/// Where a new node is added to the tree.
+enum Place {
+    Document,
+    /// At the end of an element.
+    Append(ElementRef),
+    /// Inside an element, before the child at an index.
+    Before(ElementRef, usize),
+}
+
+/// Remove the source spans of nodes and their descendants.
+fn clear_positions(nodes: &mut [HtmlNode]) {
+    for node in nodes {
+        match node {
+            HtmlNode::Element(element) => {
+                let element = element.element();
+                element.location = ElementSpan::empty();
+                for attribute in element.attributes.values_mut() {
+                    attribute.name_span = Span::over([0, 0], [0, 0]);
+                    attribute.value_span = None;
+                }
+                clear_positions(&mut element.child_nodes);
+            }
+            HtmlNode::Text(HtmlTextNode { span, .. })
+            | HtmlNode::Comment(HtmlComment { span, .. }) => {
+                *span = Span::over([0, 0], [0, 0]);
+            }
+            HtmlNode::DocType(_) => {}
+        }
+    }
+}
+
+fn is_heading(tag: &HtmlTag) -> bool {
+    matches!(
+        tag,
+        HtmlTag::H1 | HtmlTag::H2 | HtmlTag::H3 | HtmlTag::H4 | HtmlTag::H5 | HtmlTag::H6
+    )
+}
+
+/// Builds a document tree from a stream of tokens, following the tree construction rules of the HTML standard.
+/// Missing `<html>`, `<head>` and `<body>` elements are created, so every document has the same basic shape.
+pub struct TreeBuilder {
+    tree: HtmlDocument,
+    mode: InsertionMode,
+    /// The element a fragment is parsed inside of, if the tree is a fragment.
+    context: Option<HtmlTag>,
+    /// The mode to return to after the content of a text-only element.
+    original_mode: InsertionMode,
+    template_modes: Vec<InsertionMode>,
+    open_elements: Vec<ElementRef>,
+    /// The list of active formatting elements, used to reopen formatting that was closed implicitly.
+    formatting: Vec<Formatting>,
+    head: Option<ElementRef>,
+    /// The last form that was opened, if it has not been closed. Forms cannot be nested.
+    form: Option<ElementRef>,
+    frameset_ok: bool,
+    /// Whether content that does not belong in a table is moved before it.
+    foster_parenting: bool,
+    /// Text inside a table, waiting to be inserted in the table or before it.
+    pending_text: Vec<(String, Span)>,
+    /// Whether a newline directly after the last start tag should be dropped, as in `<textarea>`.
+    ignore_lf: bool,
+    /// The location of the last token, for nodes that are inserted without one.
+    location: [usize; 2],
+    response: ParserResponse,
+    errors: Vec<HtmlParseError>,
+    /// The number of errors found while parsing, shared with the tokenizer.
+    error_count: MutableCountRef<usize>,
+    /// Whether the current element is foreign, shared with the tokenizer to allow CDATA sections.
+    foreign_content: MutableCountRef<bool>,
+    /// The lines of the input read so far, shared with the tokenizer.
+    line_index: MutableCountRef<LineIndex>,
+    options: ParseOptions,
+}
+
+impl TreeBuilder {
+    pub fn new(
+        options: &ParseOptions,
+        error_count: MutableCountRef<usize>,
+        line_index: MutableCountRef<LineIndex>,
+        foreign_content: MutableCountRef<bool>,
+    ) -> Self {
+        let mut builder = Self {
+            tree: HtmlDocument::new(),
+            mode: InsertionMode::Initial,
+            context: None,
+            original_mode: InsertionMode::Initial,
+            template_modes: vec![],
+            open_elements: vec![],
+            formatting: vec![],
+            head: None,
+            form: None,
+            frameset_ok: true,
+            foster_parenting: false,
+            pending_text: vec![],
+            ignore_lf: false,
+            location: [1, 1],
+            response: ParserResponse::Continue,
+            errors: vec![],
+            error_count,
+            foreign_content,
+            line_index,
+            options: options.clone(),
+        };
+        if let Some(context) = &options.context {
+            builder.start_in(context.clone());
+        }
+        builder
+    }
+
+    /// Build a fragment of HTML, as if it was the content of an element.
+    fn start_in(&mut self, context: HtmlTag) {
+        self.insert_implied(HtmlTag::Html);
+        if context == HtmlTag::Template {
+            self.template_modes.push(InsertionMode::InTemplate);
+        }
+        self.context = Some(context);
+        self.reset_insertion_mode();
+    }
+
+    /// Add a token to the tree, and return how the tokenizer should treat the content after it.
+    pub fn receive(&mut self, token: HtmlToken) -> ParserResponse {
+        if self.stopped() {
+            return ParserResponse::Continue;
+        }
+        self.location = match &token {
+            HtmlToken::OpeningTag { span, .. }
+            | HtmlToken::ClosingTag { span, .. }
+            | HtmlToken::Text { span, .. }
+            | HtmlToken::Comment { span, .. }
+            | HtmlToken::DocType { span, .. } => span.start,
+            HtmlToken::EOF { location } => *location,
+        };
+
+        let token = match token {
+            // Drop the newline that directly follows the start tag of elements like <textarea>.
+            HtmlToken::Text { mut content, span } if take(&mut self.ignore_lf) => {
+                if content.starts_with('\n') {
+                    content.remove(0);
+                }
+                if content.is_empty() {
+                    return ParserResponse::Continue;
+                }
+                HtmlToken::Text { content, span }
+            }
+            token => {
+                self.ignore_lf = false;
+                token
+            }
+        };
+
+        // Elements that are not void cannot be self closing. Not a fatal error, the tag is treated as an opening tag.
+        if let HtmlToken::OpeningTag {
+            name,
+            span,
+            self_closing: true,
+            ..
+        } = &token
+        {
+            // Foreign elements can be self closing.
+            if !name.is_void() && !foreign::is_foreign_root(name) && !self.is_foreign(&token) {
+                self.error(
+                    ErrorType::SelfClosingNonVoidTag,
+                    span.start,
+                    span.start_offset,
+                );
+            }
+        }
+
+        self.process(token);
+        *self.foreign_content.borrow_mut() = self
+            .current()
+            .is_some_and(|current| current.namespace() != Namespace::Html);
+        replace(&mut self.response, ParserResponse::Continue)
+    }
+
+    /// Complete the tree and collect the errors encountered.
+    pub fn finish(&mut self, mut tokenizer_errors: Vec<HtmlParseError>) -> HtmlParseResult {
+        self.open_elements.clear();
+        // A fragment is made of the nodes inside the root element, without the root itself.
+        if self.context.is_some() {
+            let root = self.tree.children().next().cloned();
+            if let Some(root) = root {
+                let nodes = take(&mut root.element().child_nodes);
+                for node in &nodes {
+                    if let HtmlNode::Element(element) = node {
+                        element.element().__parent = None;
+                    }
+                }
+                self.tree.nodes = nodes;
+            }
+        }
+        if !self.options.keep_positions && !self.options.lossless {
+            clear_positions(&mut self.tree.nodes);
+        }
+        tokenizer_errors.append(&mut self.errors);
+        if let Some(max) = self.options.max_errors {
+            tokenizer_errors.truncate(max);
+        }
+        HtmlParseResult {
+            tree: take(&mut self.tree),
+            errors: tokenizer_errors,
+            encoding: Encoding::Utf8,
+        }
+    }
+
+    /// Process a token using the rules of the current insertion mode, or the rules for foreign content inside SVG and MathML.
+    fn process(&mut self, token: HtmlToken) {
+        match self.is_foreign(&token) {
+            true => self.foreign_content(token),
+            false => self.process_as(self.mode, token),
+        }
+    }
+
+    /// Process a token using the rules of an insertion mode, without switching to it.
+    fn process_as(&mut self, mode: InsertionMode, token: HtmlToken) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InTemplate => self.in_template(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::InFrameset => self.in_frameset(token),
+            InsertionMode::AfterFrameset => self.after_frameset(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+        }
+    }
+
+    /// Switch to a mode and process the token again in it.
+    fn reprocess(&mut self, mode: InsertionMode, token: HtmlToken) {
+        self.mode = mode;
+        self.process(token);
+    }
+
+    /// Checks if enough errors were found that strict parsing should stop.
+    fn stopped(&self) -> bool {
+        self.options
+            .stop_after()
+            .is_some_and(|max| *self.error_count.borrow() >= max)
+    }
+
+    /// Report an error at a location and its byte offset.
+    /// The offset is taken from a span, because the line index only keeps the lines being read.
+    fn error(&mut self, error_type: ErrorType, location: [usize; 2], offset: usize) {
+        *self.error_count.borrow_mut() += 1;
+        if self
+            .options
+            .max_errors
+            .is_some_and(|max| self.errors.len() >= max)
+        {
+            return;
+        }
+        self.errors.push(HtmlParseError {
+            error_type,
+            location,
+            offset,
+        });
+    }
+
+    /// Report an element that is not closed by an end tag, at the start of its last tag.
+    fn unclosed(&mut self, element: &ElementRef) {
+        let location = &element.element().location;
+        let span = location.close_tag.as_ref().unwrap_or(&location.open_tag);
+        let (start, offset) = (span.start, span.start_offset);
+        self.error(
+            ErrorType::UnclosedTag(element.tag_name().clone()),
+            start,
+            offset,
+        );
+    }
+
+    /// Report a token that is not allowed where it appears.
+    fn unexpected(&mut self, token: &HtmlToken) {
+        let (error_type, span) = match token {
+            HtmlToken::DocType { span, .. } => (ErrorType::UnexpectedDocType, span),
+            HtmlToken::OpeningTag { name, span, .. } => {
+                (ErrorType::UnexpectedOpenTag(name.clone()), span)
+            }
+            HtmlToken::ClosingTag { name, span } => {
+                (ErrorType::UnexpectedCloseTag(name.clone()), span)
+            }
+            HtmlToken::Text { content, span } => {
+                let ch = content
+                    .chars()
+                    .find(|ch| !ch.is_ascii_whitespace())
+                    .unwrap_or(' ');
+                (ErrorType::UnexpectedCharacter(ch), span)
+            }
+            HtmlToken::EOF { location } => {
+                // The end of the input is on the last line, which the line index keeps.
+                let offset = self
+                    .line_index
+                    .borrow()
+                    .offset(*location)
+                    .unwrap_or_default();
+                return self.error(ErrorType::UnexpectedEndOfInput, *location, offset);
+            }
+            HtmlToken::Comment { .. } => return,
+        };
+        self.error(error_type, span.start, span.start_offset);
+    }
+
+    /// The element that new nodes are added to.
+    fn current(&self) -> Option<&ElementRef> {
+        self.open_elements.last()
+    }
+
+    fn current_is(&self, tag: &HtmlTag) -> bool {
+        self.current().is_some_and(|element| is_html(element, tag))
+    }
+
+    /// Checks if an element with a tag name is open, and not hidden behind an element that limits the scope.
+    fn in_scope(&self, tag: &HtmlTag, scope: Scope) -> bool {
+        self.in_scope_where(|element| element.tag_name() == tag, scope)
+    }
+
+    /// Checks if an HTML element that matches a condition is open, and not hidden behind an element that limits the scope.
+    fn in_scope_where(&self, condition: impl Fn(&ElementRef) -> bool, scope: Scope) -> bool {
+        for element in self.open_elements.iter().rev() {
+            if element.namespace() == Namespace::Html && condition(element) {
+                return true;
+            }
+            if scope.is_boundary(element) {
+                return false;
+            }
+        }
+        false
+    }
+
+    fn is_open(&self, tag: &HtmlTag) -> bool {
+        self.open_elements
+            .iter()
+            .any(|element| is_html(element, tag))
+    }
+
+    /// Find where a new node should go: in the target element, or before the table it is in when foster parenting.
+    fn appropriate_place(&self, target: Option<&ElementRef>) -> Place {
+        let Some(mut target) = target.or(self.current()) else {
+            return Place::Document;
+        };
+        // Nodes that would be deeper than the limit are added to the element at the limit.
+        if let Some(max) = self.options.max_depth {
+            if self.open_elements.len() > max {
+                target = &self.open_elements[max - 1];
+            }
+        }
+        let fosters = self.foster_parenting
+            && target.namespace() == Namespace::Html
+            && matches!(
+                target.tag_name(),
+                HtmlTag::Table | HtmlTag::Tbody | HtmlTag::Tfoot | HtmlTag::Thead | HtmlTag::Tr
+            );
+        if !fosters {
+            return Place::Append(target.clone());
+        }
+        let last = |tag: HtmlTag| {
+            self.open_elements
+                .iter()
+                .rposition(|element| is_html(element, &tag))
+        };
+        let (template, table) = (last(HtmlTag::Template), last(HtmlTag::Table));
+        match (template, table) {
+            (Some(template), Some(table)) if template > table => {
+                Place::Append(self.open_elements[template].clone())
+            }
+            (Some(template), None) => Place::Append(self.open_elements[template].clone()),
+            (_, Some(table)) => {
+                let table_element = &self.open_elements[table];
+                match table_element.parent() {
+                    Some(parent) => {
+                        let index = parent.get_index_of(table_element).unwrap();
+                        Place::Before(parent, index)
+                    }
+                    None => Place::Append(self.open_elements[table - 1].clone()),
+                }
+            }
+            (None, None) => Place::Append(self.open_elements[0].clone()),
+        }
+    }
+
+    /// Add a node to the tree at a place.
+    fn insert_node(&mut self, place: Place, node: HtmlNode) {
+        let (parent, index) = match place {
+            Place::Document => return self.tree.nodes.push(node),
+            Place::Append(parent) => {
+                let index = parent.element().child_nodes.len();
+                (parent, index)
+            }
+            Place::Before(parent, index) => (parent, index),
+        };
+        if let HtmlNode::Element(element) = &node {
+            element.element().__parent = Some(parent.element());
+        }
+        parent.element().child_nodes.insert(index, node);
+    }
+
+    /// Add an element to the tree as a child of the current element, and make it the current element.
+    fn insert(&mut self, element: ElementRef) {
+        let place = self.appropriate_place(None);
+        self.insert_node(place, HtmlNode::Element(element.clone()));
+        self.open_elements.push(element);
+    }
+
+    /// Insert an element for an opening tag.
+    fn insert_element(&mut self, token: HtmlToken) -> ElementRef {
+        match token {
+            HtmlToken::OpeningTag {
+                name,
+                attributes,
+                span,
+                ..
+            } => {
+                let element = ElementRef::init(name, attributes, span);
+                self.insert(element.clone());
+                element
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Insert an element that was implied by the document, but not present in the source.
+    fn insert_implied(&mut self, name: HtmlTag) -> ElementRef {
+        let element = ElementRef::from(name);
+        self.insert(element.clone());
+        element
+    }
+
+    /// Insert an element that cannot have children, like `<meta>`.
+    fn insert_void(&mut self, token: HtmlToken) {
+        self.insert_element(token);
+        self.open_elements.pop();
+    }
+
+    /// Insert an element whose content is all text, and tell the tokenizer to treat it as such.
+    fn insert_text_element(&mut self, token: HtmlToken) {
+        let element = self.insert_element(token);
+        self.response = match element.tag_name() {
+            HtmlTag::Noscript => ParserResponse::SwitchToRawText,
+            tag => ParserResponse::from(tag),
+        };
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    /// Add text to the current element, joining it with the text node before it if there is one.
+    fn insert_text(&mut self, content: String, span: Span) {
+        let (parent, index) = match self.appropriate_place(None) {
+            Place::Document => return,
+            Place::Append(parent) => {
+                let index = parent.element().child_nodes.len();
+                (parent, index)
+            }
+            Place::Before(parent, index) => (parent, index),
+        };
+        let nodes = &mut parent.element().child_nodes;
+        match index.checked_sub(1).map(|previous| &mut nodes[previous]) {
+            Some(HtmlNode::Text(text_node)) => {
+                text_node.content.push_str(&content);
+                text_node.span.end = span.end;
+                text_node.span.end_offset = span.end_offset;
+            }
+            _ => nodes.insert(index, HtmlNode::Text(HtmlTextNode { content, span })),
+        }
+    }
+
+    fn insert_comment(&mut self, content: String, span: Span) {
+        if !self.options.keep_comments {
+            return;
+        }
+        let place = self.appropriate_place(None);
+        self.insert_node(place, HtmlNode::Comment(HtmlComment { content, span }));
+    }
+
+    /// Add a comment to the document itself, after the html element.
+    fn insert_document_comment(&mut self, content: String, span: Span) {
+        if !self.options.keep_comments {
+            return;
+        }
+        let node = HtmlNode::Comment(HtmlComment { content, span });
+        self.tree.nodes.push(node);
+    }
+
+    fn insert_doctype(&mut self, token: HtmlToken) {
+        if let HtmlToken::DocType {
+            name,
+            r#type,
+            public_identifier,
+            system_identifier,
+            force_quirks,
+            ..
+        } = token
+        {
+            let doctype = HtmlDocType {
+                name,
+                r#type,
+                public_identifier,
+                system_identifier,
+                force_quirks,
+            };
+            self.tree.mode = quirks::document_mode(&doctype);
+            self.tree.nodes.push(HtmlNode::DocType(doctype));
+        }
+    }
+
+    /// Pop elements until an element with a tag name has been popped, and return it.
+    fn pop_until(&mut self, tag: &HtmlTag) -> Option<ElementRef> {
+        while let Some(element) = self.open_elements.pop() {
+            if is_html(&element, tag) {
+                return Some(element);
+            }
+        }
+        None
+    }
+
+    /// Pop elements until an element with a tag name has been popped, and record the end tag that closed it.
+    fn close_element(&mut self, tag: &HtmlTag, span: Span) {
+        if let Some(element) = self.pop_until(tag) {
+            element.element().location.close_tag = Some(span);
+        }
+    }
+
+    /// Pop elements that can be closed implicitly, except for elements with a tag name.
+    fn generate_implied_end_tags(&mut self, except: Option<&HtmlTag>) {
+        while let Some(current) = self.current() {
+            let name = current.tag_name();
+            if current.namespace() != Namespace::Html
+                || !has_implied_end_tag(name)
+                || Some(name) == except
+            {
+                break;
+            }
+            self.open_elements.pop();
+        }
+    }
+
+    /// Close the open `<p>` element, and any elements that it implies the end of.
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some(&HtmlTag::P));
+        self.expect_current(&HtmlTag::P);
+        self.pop_until(&HtmlTag::P);
+    }
+
+    /// Close the open `<p>` element if there is one in button scope, before an element that cannot be inside it.
+    fn close_p_in_button_scope(&mut self) {
+        if self.in_scope(&HtmlTag::P, Scope::Button) {
+            self.close_p_element();
+        }
+    }
+
+    /// Report the current element as unclosed if it does not have a tag name, e.g. when an end tag closes other elements with it.
+    fn expect_current(&mut self, tag: &HtmlTag) {
+        if let Some(current) = self.current() {
+            if current.tag_name() != tag {
+                let current = current.clone();
+                self.unclosed(&current);
+            }
+        }
+    }
+
+    /// Go back to the mode that fits the elements that are open, e.g. after a `<template>` is closed.
+    fn reset_insertion_mode(&mut self) {
+        for (index, element) in self.open_elements.iter().enumerate().rev() {
+            let last = index == 0;
+            if !last && element.namespace() != Namespace::Html {
+                continue;
+            }
+            // The root of a fragment takes the rules of the context element.
+            let name = match &self.context {
+                Some(context) if last => context,
+                _ => element.tag_name(),
+            };
+            let mode = match name {
+                HtmlTag::Td | HtmlTag::Th if !last => Some(InsertionMode::InCell),
+                HtmlTag::Tr => Some(InsertionMode::InRow),
+                HtmlTag::Tbody | HtmlTag::Thead | HtmlTag::Tfoot => {
+                    Some(InsertionMode::InTableBody)
+                }
+                HtmlTag::Caption => Some(InsertionMode::InCaption),
+                HtmlTag::Colgroup => Some(InsertionMode::InColumnGroup),
+                HtmlTag::Table => Some(InsertionMode::InTable),
+                HtmlTag::Template => self.template_modes.last().copied(),
+                HtmlTag::Head if !last => Some(InsertionMode::InHead),
+                HtmlTag::Body => Some(InsertionMode::InBody),
+                HtmlTag::Frameset => Some(InsertionMode::InFrameset),
+                HtmlTag::Html if self.head.is_none() => Some(InsertionMode::BeforeHead),
+                HtmlTag::Html => Some(InsertionMode::AfterHead),
+                _ if last => Some(InsertionMode::InBody),
+                _ => None,
+            };
+            if let Some(mode) = mode {
+                self.mode = mode;
+                return;
+            }
+        }
+        self.mode = InsertionMode::InBody;
+    }
+
+    /// Stop building the tree.
+    fn stop(&mut self) {
+        self.open_elements.clear();
+    }
+}
+
+/// Splits text into its leading whitespace and the rest of the text. Either part can be empty.
+fn split_whitespace(mut content: String, span: Span) -> ((String, Span), (String, Span)) {
+    let index = content
+        .find(|ch: char| !ch.is_ascii_whitespace())
+        .unwrap_or(content.len());
+    let whitespace: String = content.drain(..index).collect();
+    let middle = advance(span.start, &whitespace);
+    // Whitespace is ASCII, so every character is a byte.
+    let middle_offset = span.start_offset + whitespace.len();
+    (
+        (
+            whitespace,
+            Span::over(span.start, middle).with_offsets(span.start_offset, middle_offset),
+        ),
+        (
+            content,
+            Span::over(middle, span.end).with_offsets(middle_offset, span.end_offset),
+        ),
+    )
+}
+
+/// Find the location after some text, starting from a location.
+fn advance(mut location: [usize; 2], text: &str) -> [usize; 2] {
+    for ch in text.chars() {
+        if ch == '\n' {
+            location = [location[0] + 1, 1];
+        } else {
+            location[1] += 1;
+        }
+    }
+    location
+}
@@ -0,0 +1,515 @@
+use sleek_ast::{DocumentMode, HtmlComment, HtmlNode, HtmlTag, HtmlToken};
+
+use super::{split_whitespace, InsertionMode, TreeBuilder};
+
+/// Checks if a token is text that is made up of only whitespace.
+fn is_whitespace(token: &HtmlToken) -> bool {
+    matches!(token, HtmlToken::Text { content, .. } if content.chars().all(|ch| ch.is_ascii_whitespace()))
+}
+
+impl TreeBuilder {
+    /// Drop the whitespace at the start of a text token, for modes that ignore it.
+    /// Returns nothing if the token was all whitespace.
+    fn skip_whitespace(token: HtmlToken) -> Option<HtmlToken> {
+        match token {
+            HtmlToken::Text { content, span } => {
+                let (_, (content, span)) = split_whitespace(content, span);
+                if content.is_empty() {
+                    None
+                } else {
+                    Some(HtmlToken::Text { content, span })
+                }
+            }
+            token => Some(token),
+        }
+    }
+
+    /// Insert the whitespace at the start of a text token, for modes that keep it.
+    /// Returns the rest of the token if there is any.
+    pub(super) fn insert_whitespace(&mut self, token: HtmlToken) -> Option<HtmlToken> {
+        match token {
+            HtmlToken::Text { content, span } => {
+                let ((whitespace, whitespace_span), (content, span)) =
+                    split_whitespace(content, span);
+                if !whitespace.is_empty() {
+                    self.insert_text(whitespace, whitespace_span);
+                }
+                if content.is_empty() {
+                    None
+                } else {
+                    Some(HtmlToken::Text { content, span })
+                }
+            }
+            token => Some(token),
+        }
+    }
+
+    pub(super) fn initial(&mut self, token: HtmlToken) {
+        let Some(token) = Self::skip_whitespace(token) else {
+            return;
+        };
+        match token {
+            HtmlToken::Comment { content, span } => self.insert_comment(content, span),
+            HtmlToken::DocType { .. } => {
+                self.insert_doctype(token);
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            // A document without a doctype is in quirks mode.
+            token => {
+                self.tree.mode = DocumentMode::Quirks;
+                self.reprocess(InsertionMode::BeforeHtml, token)
+            }
+        }
+    }
+
+    pub(super) fn before_html(&mut self, token: HtmlToken) {
+        let Some(token) = Self::skip_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => {
+                self.insert_element(token);
+                self.mode = InsertionMode::BeforeHead;
+            }
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Head | HtmlTag::Body | HtmlTag::Html | HtmlTag::Br,
+                ..
+            } => {
+                self.insert_implied(HtmlTag::Html);
+                self.reprocess(InsertionMode::BeforeHead, token);
+            }
+            HtmlToken::ClosingTag { .. } => self.unexpected(&token),
+            _ => {
+                self.insert_implied(HtmlTag::Html);
+                self.reprocess(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    pub(super) fn before_head(&mut self, token: HtmlToken) {
+        let Some(token) = Self::skip_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Head,
+                ..
+            } => {
+                self.head = Some(self.insert_element(token));
+                self.mode = InsertionMode::InHead;
+            }
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Head | HtmlTag::Body | HtmlTag::Html | HtmlTag::Br,
+                ..
+            } => {
+                self.head = Some(self.insert_implied(HtmlTag::Head));
+                self.reprocess(InsertionMode::InHead, token);
+            }
+            HtmlToken::ClosingTag { .. } => self.unexpected(&token),
+            _ => {
+                self.head = Some(self.insert_implied(HtmlTag::Head));
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    pub(super) fn in_head(&mut self, token: HtmlToken) {
+        let Some(token) = self.insert_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Html => self.in_body(token),
+                HtmlTag::Base
+                | HtmlTag::Basefont
+                | HtmlTag::Bgsound
+                | HtmlTag::Link
+                | HtmlTag::Meta => self.insert_void(token),
+                HtmlTag::Title | HtmlTag::Noframes | HtmlTag::Style | HtmlTag::Script => {
+                    self.insert_text_element(token)
+                }
+                HtmlTag::Noscript if self.options.scripting => self.insert_text_element(token),
+                HtmlTag::Noscript => {
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InHeadNoscript;
+                }
+                HtmlTag::Template => {
+                    self.insert_element(token);
+                    self.push_formatting_marker();
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_modes.push(InsertionMode::InTemplate);
+                }
+                HtmlTag::Head => self.unexpected(&token),
+                _ => self.after_in_head(token),
+            },
+            HtmlToken::ClosingTag { name, span } => match name {
+                HtmlTag::Head => {
+                    self.close_element(&HtmlTag::Head, span.clone());
+                    self.mode = InsertionMode::AfterHead;
+                }
+                HtmlTag::Template => {
+                    if self.is_open(&HtmlTag::Template) {
+                        self.generate_implied_end_tags(None);
+                        self.expect_current(&HtmlTag::Template);
+                        self.close_element(&HtmlTag::Template, span.clone());
+                        self.clear_formatting_to_marker();
+                        self.template_modes.pop();
+                        self.reset_insertion_mode();
+                    } else {
+                        self.unexpected(&token);
+                    }
+                }
+                HtmlTag::Body | HtmlTag::Html | HtmlTag::Br => self.after_in_head(token),
+                _ => self.unexpected(&token),
+            },
+            _ => self.after_in_head(token),
+        }
+    }
+
+    /// Close the head for content that does not belong in it.
+    fn after_in_head(&mut self, token: HtmlToken) {
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::AfterHead, token);
+    }
+
+    pub(super) fn in_head_noscript(&mut self, token: HtmlToken) {
+        if is_whitespace(&token) {
+            return self.in_head(token);
+        }
+        match &token {
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::Comment { .. } => self.in_head(token),
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Html => self.in_body(token),
+                HtmlTag::Basefont
+                | HtmlTag::Bgsound
+                | HtmlTag::Link
+                | HtmlTag::Meta
+                | HtmlTag::Noframes
+                | HtmlTag::Style => self.in_head(token),
+                HtmlTag::Head | HtmlTag::Noscript => self.unexpected(&token),
+                _ => self.after_in_head_noscript(token),
+            },
+            HtmlToken::ClosingTag { name, span } => match name {
+                HtmlTag::Noscript => {
+                    self.close_element(&HtmlTag::Noscript, span.clone());
+                    self.mode = InsertionMode::InHead;
+                }
+                HtmlTag::Br => self.after_in_head_noscript(token),
+                _ => self.unexpected(&token),
+            },
+            _ => self.after_in_head_noscript(token),
+        }
+    }
+
+    /// Close a `<noscript>` in the head for content that does not belong in it.
+    fn after_in_head_noscript(&mut self, token: HtmlToken) {
+        self.unexpected(&token);
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::InHead, token);
+    }
+
+    pub(super) fn after_head(&mut self, token: HtmlToken) {
+        let Some(token) = self.insert_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Html => self.in_body(token),
+                HtmlTag::Body => {
+                    self.insert_element(token);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                }
+                HtmlTag::Frameset => {
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InFrameset;
+                }
+                // Elements that belong in the head, but come after it.
+                HtmlTag::Base
+                | HtmlTag::Basefont
+                | HtmlTag::Bgsound
+                | HtmlTag::Link
+                | HtmlTag::Meta
+                | HtmlTag::Noframes
+                | HtmlTag::Script
+                | HtmlTag::Style
+                | HtmlTag::Template
+                | HtmlTag::Title => {
+                    self.unexpected(&token);
+                    let head = self.head.clone().unwrap();
+                    self.open_elements.push(head.clone());
+                    self.in_head(token);
+                    self.open_elements.retain(|element| element != &head);
+                }
+                HtmlTag::Head => self.unexpected(&token),
+                _ => self.after_after_head(token),
+            },
+            HtmlToken::ClosingTag { name, .. } => match name {
+                HtmlTag::Template => self.in_head(token),
+                HtmlTag::Body | HtmlTag::Html | HtmlTag::Br => self.after_after_head(token),
+                _ => self.unexpected(&token),
+            },
+            _ => self.after_after_head(token),
+        }
+    }
+
+    /// Open the implied body for content after the head.
+    fn after_after_head(&mut self, token: HtmlToken) {
+        self.insert_implied(HtmlTag::Body);
+        self.reprocess(InsertionMode::InBody, token);
+    }
+
+    pub(super) fn text(&mut self, token: HtmlToken) {
+        match token {
+            HtmlToken::Text { content, span } => self.insert_text(content, span),
+            HtmlToken::EOF { .. } => {
+                if let Some(current) = self.open_elements.pop() {
+                    self.unclosed(&current);
+                }
+                self.reprocess(self.original_mode, token);
+            }
+            HtmlToken::ClosingTag { name, span } => {
+                if let Some(current) = self.open_elements.pop() {
+                    if current.tag_name() == &name {
+                        current.element().location.close_tag = Some(span);
+                    }
+                }
+                self.mode = self.original_mode;
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn in_template(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::Text { .. } | HtmlToken::Comment { .. } | HtmlToken::DocType { .. } => {
+                self.in_body(token)
+            }
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Base
+                | HtmlTag::Basefont
+                | HtmlTag::Bgsound
+                | HtmlTag::Link
+                | HtmlTag::Meta
+                | HtmlTag::Noframes
+                | HtmlTag::Script
+                | HtmlTag::Style
+                | HtmlTag::Template
+                | HtmlTag::Title => self.in_head(token),
+                name => {
+                    let mode = match name {
+                        HtmlTag::Caption
+                        | HtmlTag::Colgroup
+                        | HtmlTag::Tbody
+                        | HtmlTag::Tfoot
+                        | HtmlTag::Thead => InsertionMode::InTable,
+                        HtmlTag::Col => InsertionMode::InColumnGroup,
+                        HtmlTag::Tr => InsertionMode::InTableBody,
+                        HtmlTag::Td | HtmlTag::Th => InsertionMode::InRow,
+                        _ => InsertionMode::InBody,
+                    };
+                    self.template_modes.pop();
+                    self.template_modes.push(mode);
+                    self.reprocess(mode, token);
+                }
+            },
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Template,
+                ..
+            } => self.in_head(token),
+            HtmlToken::ClosingTag { .. } => self.unexpected(&token),
+            HtmlToken::EOF { .. } => {
+                if !self.is_open(&HtmlTag::Template) {
+                    return self.stop();
+                }
+                self.unexpected(&token);
+                self.pop_until(&HtmlTag::Template);
+                self.clear_formatting_to_marker();
+                self.template_modes.pop();
+                self.reset_insertion_mode();
+                self.process(token);
+            }
+        }
+    }
+
+    pub(super) fn after_body(&mut self, token: HtmlToken) {
+        if is_whitespace(&token) {
+            return self.in_body(token);
+        }
+        match token {
+            // Comments after the body belong to the html element.
+            HtmlToken::Comment { content, span } => {
+                if let Some(html) = self.open_elements.first() {
+                    let node = HtmlNode::Comment(HtmlComment { content, span });
+                    html.element().child_nodes.push(node);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Html,
+                span,
+            } => {
+                if let Some(html) = self.open_elements.first() {
+                    html.element().location.close_tag = Some(span);
+                }
+                self.mode = InsertionMode::AfterAfterBody;
+            }
+            HtmlToken::EOF { .. } => self.stop(),
+            token => {
+                self.unexpected(&token);
+                self.reprocess(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    pub(super) fn in_frameset(&mut self, token: HtmlToken) {
+        let Some(token) = self.insert_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Html => self.in_body(token),
+                HtmlTag::Frameset => {
+                    self.insert_element(token);
+                }
+                HtmlTag::Frame => self.insert_void(token),
+                HtmlTag::Noframes => self.in_head(token),
+                _ => self.unexpected(&token),
+            },
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Frameset,
+                span,
+            } => {
+                if self.current_is(&HtmlTag::Html) {
+                    self.unexpected(&token);
+                } else {
+                    if let Some(frameset) = self.open_elements.pop() {
+                        frameset.element().location.close_tag = Some(span.clone());
+                    }
+                    if !self.current_is(&HtmlTag::Frameset) {
+                        self.mode = InsertionMode::AfterFrameset;
+                    }
+                }
+            }
+            HtmlToken::EOF { .. } => {
+                if !self.current_is(&HtmlTag::Html) {
+                    self.unexpected(&token);
+                }
+                self.stop();
+            }
+            _ => self.unexpected(&token),
+        }
+    }
+
+    pub(super) fn after_frameset(&mut self, token: HtmlToken) {
+        let Some(token) = self.insert_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Noframes,
+                ..
+            } => self.in_head(token),
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.mode = InsertionMode::AfterAfterFrameset,
+            HtmlToken::EOF { .. } => self.stop(),
+            _ => self.unexpected(&token),
+        }
+    }
+
+    pub(super) fn after_after_body(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_document_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. }
+            | HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            _ if is_whitespace(&token) => self.in_body(token),
+            HtmlToken::EOF { .. } => self.stop(),
+            _ => {
+                self.unexpected(&token);
+                self.reprocess(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    pub(super) fn after_after_frameset(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_document_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. }
+            | HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            _ if is_whitespace(&token) => self.in_body(token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Noframes,
+                ..
+            } => self.in_head(token),
+            HtmlToken::EOF { .. } => self.stop(),
+            _ => self.unexpected(&token),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use sleek_ast::{DocumentMode, HtmlDocType};
+
+/// Public identifiers of old doctypes that put a document in quirks mode, by their start.
+const QUIRKS_PUBLIC_PREFIXES: [&str; 55] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// Public identifiers that put a document in quirks mode.
+const QUIRKS_PUBLIC_IDENTIFIERS: [&str; 3] = [
+    "-//w3o//dtd w3 html strict 3.0//en//",
+    "-/w3c/dtd html 4.0 transitional/en",
+    "html",
+];
+
+/// HTML 4.01 doctypes put a document in quirks mode without a system identifier, and limited quirks mode with one.
+const HTML4_PUBLIC_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd html 4.01 frameset//",
+    "-//w3c//dtd html 4.01 transitional//",
+];
+
+const LIMITED_QUIRKS_PUBLIC_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd xhtml 1.0 frameset//",
+    "-//w3c//dtd xhtml 1.0 transitional//",
+];
+
+/// Decide the mode of a document from its doctype. Identifiers are compared without case.
+pub(super) fn document_mode(doctype: &HtmlDocType) -> DocumentMode {
+    let public = doctype
+        .public_identifier
+        .as_deref()
+        .map(str::to_ascii_lowercase);
+    let system = doctype
+        .system_identifier
+        .as_deref()
+        .map(str::to_ascii_lowercase);
+    let starts_with = |prefixes: &[&str]| {
+        public
+            .as_deref()
+            .is_some_and(|public| prefixes.iter().any(|prefix| public.starts_with(prefix)))
+    };
+
+    if doctype.force_quirks
+        || doctype.name != "html"
+        || public
+            .as_deref()
+            .is_some_and(|public| QUIRKS_PUBLIC_IDENTIFIERS.contains(&public))
+        || system.as_deref() == Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
+        || starts_with(&QUIRKS_PUBLIC_PREFIXES)
+        || (system.is_none() && starts_with(&HTML4_PUBLIC_PREFIXES))
+    {
+        DocumentMode::Quirks
+    } else if starts_with(&LIMITED_QUIRKS_PUBLIC_PREFIXES)
+        || (system.is_some() && starts_with(&HTML4_PUBLIC_PREFIXES))
+    {
+        DocumentMode::LimitedQuirks
+    } else {
+        DocumentMode::NoQuirks
+    }
+}
@@ -0,0 +1,490 @@
+use std::mem::take;
+
+use sleek_ast::{HtmlTag, HtmlToken, Namespace};
+
+use super::{is_html, InsertionMode, Scope, TreeBuilder};
+
+/// Checks if an element is a table section, like `<tbody>`.
+fn is_table_section(tag: &HtmlTag) -> bool {
+    matches!(tag, HtmlTag::Tbody | HtmlTag::Tfoot | HtmlTag::Thead)
+}
+
+/// Checks if an opening tag is `<input type=hidden>`.
+fn is_hidden_input(token: &HtmlToken) -> bool {
+    matches!(token, HtmlToken::OpeningTag { name: HtmlTag::Input, attributes, .. }
+        if attributes.iter().any(|attribute| attribute.key == "type"
+            && attribute.value.as_ref().is_some_and(|value| value.eq_ignore_ascii_case("hidden"))))
+}
+
+impl TreeBuilder {
+    pub(super) fn in_table(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::Text { .. }
+                if self.current().is_some_and(|current| {
+                    current.namespace() == Namespace::Html
+                        && matches!(
+                            current.tag_name(),
+                            HtmlTag::Table
+                                | HtmlTag::Tbody
+                                | HtmlTag::Template
+                                | HtmlTag::Tfoot
+                                | HtmlTag::Thead
+                                | HtmlTag::Tr
+                        )
+                }) =>
+            {
+                self.pending_text.clear();
+                self.original_mode = self.mode;
+                self.reprocess(InsertionMode::InTableText, token);
+            }
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Caption => {
+                    self.clear_to_table_context();
+                    self.push_formatting_marker();
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InCaption;
+                }
+                HtmlTag::Colgroup => {
+                    self.clear_to_table_context();
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                HtmlTag::Col => {
+                    self.clear_to_table_context();
+                    self.insert_implied(HtmlTag::Colgroup);
+                    self.reprocess(InsertionMode::InColumnGroup, token);
+                }
+                HtmlTag::Tbody | HtmlTag::Tfoot | HtmlTag::Thead => {
+                    self.clear_to_table_context();
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InTableBody;
+                }
+                HtmlTag::Td | HtmlTag::Th | HtmlTag::Tr => {
+                    self.clear_to_table_context();
+                    self.insert_implied(HtmlTag::Tbody);
+                    self.reprocess(InsertionMode::InTableBody, token);
+                }
+                // A table inside a table closes the first one.
+                HtmlTag::Table => {
+                    self.unexpected(&token);
+                    if self.in_scope(&HtmlTag::Table, Scope::Table) {
+                        self.pop_until(&HtmlTag::Table);
+                        self.reset_insertion_mode();
+                        self.process(token);
+                    }
+                }
+                HtmlTag::Style | HtmlTag::Script | HtmlTag::Template => self.in_head(token),
+                HtmlTag::Input if is_hidden_input(&token) => {
+                    self.unexpected(&token);
+                    self.insert_void(token);
+                }
+                HtmlTag::Form => {
+                    self.unexpected(&token);
+                    if self.form.is_none() && !self.is_open(&HtmlTag::Template) {
+                        let form = self.insert_element(token);
+                        self.form = Some(form);
+                        self.open_elements.pop();
+                    }
+                }
+                _ => self.foster_parent(token),
+            },
+            HtmlToken::ClosingTag { name, span } => match name {
+                HtmlTag::Table => {
+                    if !self.in_scope(&HtmlTag::Table, Scope::Table) {
+                        return self.unexpected(&token);
+                    }
+                    self.close_element(&HtmlTag::Table, span.clone());
+                    self.reset_insertion_mode();
+                }
+                HtmlTag::Body
+                | HtmlTag::Caption
+                | HtmlTag::Col
+                | HtmlTag::Colgroup
+                | HtmlTag::Html
+                | HtmlTag::Tbody
+                | HtmlTag::Td
+                | HtmlTag::Tfoot
+                | HtmlTag::Th
+                | HtmlTag::Thead
+                | HtmlTag::Tr => self.unexpected(&token),
+                HtmlTag::Template => self.in_head(token),
+                _ => self.foster_parent(token),
+            },
+            HtmlToken::EOF { .. } => self.in_body(token),
+            HtmlToken::Text { .. } => self.foster_parent(token),
+        }
+    }
+
+    /// Process a token that does not belong in a table with the rules of the body, moving what it inserts before the table.
+    fn foster_parent(&mut self, token: HtmlToken) {
+        self.unexpected(&token);
+        self.foster_parenting = true;
+        self.in_body(token);
+        self.foster_parenting = false;
+    }
+
+    pub(super) fn in_table_text(&mut self, token: HtmlToken) {
+        if let HtmlToken::Text { content, span } = token {
+            self.pending_text.push((content, span));
+            return;
+        }
+        let pending_text = take(&mut self.pending_text);
+        let is_whitespace = pending_text
+            .iter()
+            .all(|(content, _)| content.chars().all(|ch| ch.is_ascii_whitespace()));
+        for (content, span) in pending_text {
+            if is_whitespace {
+                self.insert_text(content, span);
+            } else {
+                self.foster_parent(HtmlToken::Text { content, span });
+            }
+        }
+        self.reprocess(self.original_mode, token);
+    }
+
+    pub(super) fn in_caption(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Caption,
+                span,
+            } => {
+                let span = span.clone();
+                if self.close_caption(&token) {
+                    if let Some(caption) = self.open_elements.pop() {
+                        caption.element().location.close_tag = Some(span);
+                    }
+                    self.clear_formatting_to_marker();
+                    self.mode = InsertionMode::InTable;
+                }
+            }
+            HtmlToken::OpeningTag {
+                name:
+                    HtmlTag::Caption
+                    | HtmlTag::Col
+                    | HtmlTag::Colgroup
+                    | HtmlTag::Tbody
+                    | HtmlTag::Td
+                    | HtmlTag::Tfoot
+                    | HtmlTag::Th
+                    | HtmlTag::Thead
+                    | HtmlTag::Tr,
+                ..
+            }
+            | HtmlToken::ClosingTag {
+                name: HtmlTag::Table,
+                ..
+            } => {
+                if self.close_caption(&token) {
+                    self.open_elements.pop();
+                    self.clear_formatting_to_marker();
+                    self.reprocess(InsertionMode::InTable, token);
+                }
+            }
+            HtmlToken::ClosingTag {
+                name:
+                    HtmlTag::Body
+                    | HtmlTag::Col
+                    | HtmlTag::Colgroup
+                    | HtmlTag::Html
+                    | HtmlTag::Tbody
+                    | HtmlTag::Td
+                    | HtmlTag::Tfoot
+                    | HtmlTag::Th
+                    | HtmlTag::Thead
+                    | HtmlTag::Tr,
+                ..
+            } => self.unexpected(&token),
+            _ => self.in_body(token),
+        }
+    }
+
+    /// Pop the elements inside the open caption, leaving it as the current element.
+    /// Returns false if there is no caption to close.
+    fn close_caption(&mut self, token: &HtmlToken) -> bool {
+        if !self.in_scope(&HtmlTag::Caption, Scope::Table) {
+            self.unexpected(token);
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        self.expect_current(&HtmlTag::Caption);
+        while !self.current_is(&HtmlTag::Caption) {
+            self.open_elements.pop();
+        }
+        true
+    }
+
+    pub(super) fn in_column_group(&mut self, token: HtmlToken) {
+        let Some(token) = self.insert_whitespace(token) else {
+            return;
+        };
+        match &token {
+            HtmlToken::Comment { .. } => {
+                if let HtmlToken::Comment { content, span } = token {
+                    self.insert_comment(content, span);
+                }
+            }
+            HtmlToken::DocType { .. } => self.unexpected(&token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Html,
+                ..
+            } => self.in_body(token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Col, ..
+            } => self.insert_void(token),
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Colgroup,
+                span,
+            } => {
+                if !self.current_is(&HtmlTag::Colgroup) {
+                    return self.unexpected(&token);
+                }
+                if let Some(colgroup) = self.open_elements.pop() {
+                    colgroup.element().location.close_tag = Some(span.clone());
+                }
+                self.mode = InsertionMode::InTable;
+            }
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Col, ..
+            } => self.unexpected(&token),
+            HtmlToken::OpeningTag {
+                name: HtmlTag::Template,
+                ..
+            }
+            | HtmlToken::ClosingTag {
+                name: HtmlTag::Template,
+                ..
+            } => self.in_head(token),
+            HtmlToken::EOF { .. } => self.in_body(token),
+            _ => {
+                if !self.current_is(&HtmlTag::Colgroup) {
+                    return self.unexpected(&token);
+                }
+                self.open_elements.pop();
+                self.reprocess(InsertionMode::InTable, token);
+            }
+        }
+    }
+
+    pub(super) fn in_table_body(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Tr => {
+                    self.clear_to_table_body_context();
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InRow;
+                }
+                HtmlTag::Th | HtmlTag::Td => {
+                    self.unexpected(&token);
+                    self.clear_to_table_body_context();
+                    self.insert_implied(HtmlTag::Tr);
+                    self.reprocess(InsertionMode::InRow, token);
+                }
+                HtmlTag::Caption
+                | HtmlTag::Col
+                | HtmlTag::Colgroup
+                | HtmlTag::Tbody
+                | HtmlTag::Tfoot
+                | HtmlTag::Thead => self.close_table_section(token),
+                _ => self.in_table(token),
+            },
+            HtmlToken::ClosingTag { name, span } => match name {
+                name if is_table_section(name) => {
+                    if !self.in_scope(name, Scope::Table) {
+                        return self.unexpected(&token);
+                    }
+                    self.clear_to_table_body_context();
+                    if let Some(section) = self.open_elements.pop() {
+                        section.element().location.close_tag = Some(span.clone());
+                    }
+                    self.mode = InsertionMode::InTable;
+                }
+                HtmlTag::Table => self.close_table_section(token),
+                HtmlTag::Body
+                | HtmlTag::Caption
+                | HtmlTag::Col
+                | HtmlTag::Colgroup
+                | HtmlTag::Html
+                | HtmlTag::Td
+                | HtmlTag::Th
+                | HtmlTag::Tr => self.unexpected(&token),
+                _ => self.in_table(token),
+            },
+            _ => self.in_table(token),
+        }
+    }
+
+    /// Close the open table section for a tag that cannot be inside it, and process the tag in the table.
+    fn close_table_section(&mut self, token: HtmlToken) {
+        if !self.in_scope_where(|element| is_table_section(element.tag_name()), Scope::Table) {
+            return self.unexpected(&token);
+        }
+        self.clear_to_table_body_context();
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::InTable, token);
+    }
+
+    pub(super) fn in_row(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::OpeningTag { name, .. } => match name {
+                HtmlTag::Th | HtmlTag::Td => {
+                    self.clear_to_table_row_context();
+                    self.insert_element(token);
+                    self.mode = InsertionMode::InCell;
+                    self.push_formatting_marker();
+                }
+                HtmlTag::Caption
+                | HtmlTag::Col
+                | HtmlTag::Colgroup
+                | HtmlTag::Tbody
+                | HtmlTag::Tfoot
+                | HtmlTag::Thead
+                | HtmlTag::Tr => self.close_row(token),
+                _ => self.in_table(token),
+            },
+            HtmlToken::ClosingTag { name, span } => match name {
+                HtmlTag::Tr => {
+                    if !self.in_scope(&HtmlTag::Tr, Scope::Table) {
+                        return self.unexpected(&token);
+                    }
+                    self.clear_to_table_row_context();
+                    if let Some(row) = self.open_elements.pop() {
+                        row.element().location.close_tag = Some(span.clone());
+                    }
+                    self.mode = InsertionMode::InTableBody;
+                }
+                HtmlTag::Table => self.close_row(token),
+                name if is_table_section(name) => {
+                    if !self.in_scope(name, Scope::Table) {
+                        return self.unexpected(&token);
+                    }
+                    self.close_row(token);
+                }
+                HtmlTag::Body
+                | HtmlTag::Caption
+                | HtmlTag::Col
+                | HtmlTag::Colgroup
+                | HtmlTag::Html
+                | HtmlTag::Td
+                | HtmlTag::Th => self.unexpected(&token),
+                _ => self.in_table(token),
+            },
+            _ => self.in_table(token),
+        }
+    }
+
+    /// Close the open row for a tag that cannot be inside it, and process the tag in the table body.
+    fn close_row(&mut self, token: HtmlToken) {
+        if !self.in_scope(&HtmlTag::Tr, Scope::Table) {
+            return self.unexpected(&token);
+        }
+        self.clear_to_table_row_context();
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::InTableBody, token);
+    }
+
+    pub(super) fn in_cell(&mut self, token: HtmlToken) {
+        match &token {
+            HtmlToken::ClosingTag {
+                name: name @ (HtmlTag::Td | HtmlTag::Th),
+                span,
+            } => {
+                if !self.in_scope(name, Scope::Table) {
+                    return self.unexpected(&token);
+                }
+                let (name, span) = (name.clone(), span.clone());
+                self.generate_implied_end_tags(None);
+                self.expect_current(&name);
+                self.close_element(&name, span);
+                self.clear_formatting_to_marker();
+                self.mode = InsertionMode::InRow;
+            }
+            HtmlToken::OpeningTag {
+                name:
+                    HtmlTag::Caption
+                    | HtmlTag::Col
+                    | HtmlTag::Colgroup
+                    | HtmlTag::Tbody
+                    | HtmlTag::Td
+                    | HtmlTag::Tfoot
+                    | HtmlTag::Th
+                    | HtmlTag::Thead
+                    | HtmlTag::Tr,
+                ..
+            } => {
+                if !self.in_scope_where(
+                    |element| matches!(element.tag_name(), HtmlTag::Td | HtmlTag::Th),
+                    Scope::Table,
+                ) {
+                    return self.unexpected(&token);
+                }
+                self.close_cell();
+                self.process(token);
+            }
+            HtmlToken::ClosingTag {
+                name:
+                    HtmlTag::Body | HtmlTag::Caption | HtmlTag::Col | HtmlTag::Colgroup | HtmlTag::Html,
+                ..
+            } => self.unexpected(&token),
+            HtmlToken::ClosingTag {
+                name:
+                    name @ (HtmlTag::Table
+                    | HtmlTag::Tbody
+                    | HtmlTag::Tfoot
+                    | HtmlTag::Thead
+                    | HtmlTag::Tr),
+                ..
+            } => {
+                if !self.in_scope(name, Scope::Table) {
+                    return self.unexpected(&token);
+                }
+                self.close_cell();
+                self.process(token);
+            }
+            _ => self.in_body(token),
+        }
+    }
+
+    /// Close the open cell, so that the next cell or row can start.
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.current_is(&HtmlTag::Td) && !self.current_is(&HtmlTag::Th) {
+            self.expect_current(&HtmlTag::Td);
+        }
+        while let Some(element) = self.open_elements.pop() {
+            if is_html(&element, &HtmlTag::Td) || is_html(&element, &HtmlTag::Th) {
+                break;
+            }
+        }
+        self.clear_formatting_to_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn clear_to_table_context(&mut self) {
+        self.clear_to(|tag| matches!(tag, HtmlTag::Table | HtmlTag::Template | HtmlTag::Html));
+    }
+
+    fn clear_to_table_body_context(&mut self) {
+        self.clear_to(|tag| {
+            is_table_section(tag) || matches!(tag, HtmlTag::Template | HtmlTag::Html)
+        });
+    }
+
+    fn clear_to_table_row_context(&mut self) {
+        self.clear_to(|tag| matches!(tag, HtmlTag::Tr | HtmlTag::Template | HtmlTag::Html));
+    }
+
+    /// Pop elements until the current element matches a condition.
+    fn clear_to(&mut self, condition: impl Fn(&HtmlTag) -> bool) {
+        while self.current().is_some_and(|current| {
+            current.namespace() != Namespace::Html || !condition(current.tag_name())
+        }) {
+            self.open_elements.pop();
+        }
+    }
+}
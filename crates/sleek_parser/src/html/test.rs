@@ -6,12 +6,16 @@ mod tests {
     use crate::{
         html::{
             tokenizer::{tokenize, TokenStore},
-            ParseMode,
+            HtmlParseErrorType, ParseMode,
         },
-        parse_html_input, HtmlParseError, HtmlParseResult,
+        parse_html_bytes, parse_html_file, parse_html_fragment, parse_html_input,
+        parse_html_reader, parse_html_strict, reparse_html, Encoding, HtmlFileError,
+        HtmlParseError, HtmlParseResult, HtmlTokenizer, ParseOptions, TextEdit, Whitespace,
     };
-    use sleek_ast::{HtmlTag, HtmlToken, Query};
-    use sleek_utils::{MatrixIterator, Node, QueueIterator};
+    use sleek_ast::{
+        DocumentMode, ElementRef, ElementSpan, HtmlNode, HtmlTag, HtmlToken, Namespace, Query,
+    };
+    use sleek_utils::{LineIndex, MatrixIterator, Node, QueueIterator};
 
     pub struct TokenizerResult {
         errors: Vec<HtmlParseError>,
@@ -23,6 +27,8 @@ mod tests {
             TokenStore::new(),
             QueueIterator::new(MatrixIterator::new(input.chars(), '\n')),
         );
+        // The indentation of the inputs is not counted in the tokens.
+        token_store.configure(&ParseOptions::new().whitespace(Whitespace::Remove));
         tokenize(&mut token_store, &mut iterator);
         TokenizerResult {
             errors: take(&mut token_store.errors),
@@ -113,6 +119,97 @@ mod tests {
         assert_eq!(res.tokens.len(), 3, "Tokenized {:?}", res.tokens);
     }
     #[test]
+    fn it_keeps_the_first_of_duplicate_attributes() {
+        let res = tokenize_html("<a href=x\n  HREF='y' href>");
+        let HtmlToken::OpeningTag { attributes, .. } = &res.tokens[0] else {
+            panic!("expected an opening tag, got {:?}", res.tokens[0]);
+        };
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].value.as_deref(), Some("x"));
+        let errors: Vec<_> = res
+            .errors
+            .iter()
+            .map(|error| (error.error_type.name(), error.location))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("duplicate-attribute", [2, 3]),
+                ("duplicate-attribute", [2, 12])
+            ]
+        );
+    }
+    #[test]
+    fn it_reads_tokens_one_at_a_time() {
+        let source = "<!DOCTYPE html>\n<p class=a class=b>Hi\0</p><script>a<b</script>";
+        let items: Vec<_> = HtmlTokenizer::new(source, ParseOptions::new())
+            .map(|item| match item {
+                Ok(HtmlToken::EOF { .. }) => "EOF".to_string(),
+                Ok(
+                    HtmlToken::DocType { span, .. }
+                    | HtmlToken::OpeningTag { span, .. }
+                    | HtmlToken::ClosingTag { span, .. }
+                    | HtmlToken::Text { span, .. }
+                    | HtmlToken::Comment { span, .. },
+                ) => source[span.range()].to_string(),
+                Err(error) => error.error_type.name().to_string(),
+            })
+            .collect();
+        assert_eq!(
+            items,
+            [
+                "<!DOCTYPE html>",
+                "\n",
+                "duplicate-attribute",
+                "<p class=a class=b>",
+                "Hi",
+                "unexpected-null-character",
+                "</p>",
+                "<script>",
+                "a<b",
+                "</script>",
+                "EOF"
+            ]
+        );
+        let tokens: Vec<_> = HtmlTokenizer::new(source, ParseOptions::new())
+            .filter_map(Result::ok)
+            .collect();
+        let mut token_store = TokenStore::new();
+        let line_index = token_store.line_index();
+        let chars = source
+            .chars()
+            .inspect(move |&ch| line_index.borrow_mut().push(ch));
+        let mut iterator = QueueIterator::new(MatrixIterator::new(chars, '\n'));
+        tokenize(&mut token_store, &mut iterator);
+        assert_eq!(tokens, token_store.tokens);
+    }
+    #[test]
+    fn it_ends_at_the_end_of_input_inside_an_end_tag() {
+        let cases = [
+            ("</", Some("</"), "eof-before-tag-name"),
+            ("</h", None, "eof-in-tag"),
+            ("</h a=1", None, "eof-in-tag"),
+            ("</h<!--\r\n", None, "eof-in-tag"),
+        ];
+        for (input, text, error) in cases {
+            let res = tokenize_html(input);
+            let errors: Vec<_> = res.errors.iter().map(|e| e.error_type.name()).collect();
+            assert_eq!(errors, [error], "Errors for {input:?}");
+            match text {
+                Some(text) => {
+                    assert_eq!(res.tokens.len(), 2, "Tokenized {:?}", res.tokens);
+                    assert!(
+                        matches!(&res.tokens[0], HtmlToken::Text { content, .. } if content == text)
+                    );
+                }
+                None => assert_eq!(res.tokens.len(), 1, "Tokenized {:?}", res.tokens),
+            }
+            assert!(res.tokens.last().unwrap().is_eof());
+            let result = parse_html_input(input, ParseOptions::new());
+            assert_eq!(result.errors.len(), 1, "Errors for {input:?}");
+        }
+    }
+    #[test]
     fn it_rejects_fragment_tag() {
         let res = tokenize_html("<></>");
         assert_eq!(res.errors.len(), 2, "Errors: {:?}", res.errors);
@@ -149,6 +246,33 @@ mod tests {
         assert_eq!(res.tokens.len(), 2, "Tokenized {:?}", res.tokens);
     }
     #[test]
+    fn it_ends_comments_like_the_standard() {
+        let cases = [
+            ("<!-->x-->y", "", "x-->y", "abrupt-closing-of-empty-comment"),
+            ("<!--->x", "", "x", "abrupt-closing-of-empty-comment"),
+            ("<!-- a --!>x", " a ", "x", "incorrectly-closed-comment"),
+            ("<!-- <!-- a -->x", " <!-- a ", "x", "nested-comment"),
+        ];
+        for (input, comment, text, error) in cases {
+            let res = tokenize_html(input);
+            let errors: Vec<_> = res.errors.iter().map(|e| e.error_type.name()).collect();
+            assert_eq!(errors, [error], "Errors for {input:?}");
+            assert!(
+                matches!(&res.tokens[0], HtmlToken::Comment { content, .. } if content == comment),
+                "Tokenized {:?}",
+                res.tokens
+            );
+            assert!(
+                matches!(&res.tokens[1], HtmlToken::Text { content, .. } if content == text),
+                "Tokenized {:?}",
+                res.tokens
+            );
+        }
+        let res = tokenize_html("<!--<!-->");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert!(matches!(&res.tokens[0], HtmlToken::Comment { content, .. } if content == "<!"));
+    }
+    #[test]
     fn it_tokenizes_element_with_comment() {
         let res = tokenize_html(
             "
@@ -218,14 +342,15 @@ mod tests {
     #[test]
     fn it_parses_html() {
         let input = "<html lang=en>This is valid html.</html>";
-        let HtmlParseResult { tree, errors } = parse_html_input(input, ParseMode::Synchronous);
+        let HtmlParseResult { tree, errors, .. } =
+            parse_html_input(input, ParseOptions::new().mode(ParseMode::Synchronous));
         assert_eq!(tree.query_selector("html"), tree.children().next().cloned());
         assert_eq!(errors.len(), 0);
     }
 
     #[test]
     fn it_parses_html_with_children() {
-        let HtmlParseResult { tree, errors } = parse_html_input(
+        let HtmlParseResult { tree, errors, .. } = parse_html_input(
             "<html lang=\"en\">
                     <head>
                         <title>Document</title>
@@ -237,7 +362,7 @@ mod tests {
                         </section>
                     </body>
                 </html>",
-            ParseMode::Synchronous,
+            ParseOptions::new().mode(ParseMode::Synchronous),
         );
 
         assert_eq!(tree.nodes.len(), 1);
@@ -253,7 +378,7 @@ mod tests {
     fn it_parses_file() {
         let input = std::fs::read_to_string("src/html/test.html").unwrap();
         let time = Instant::now();
-        let res = parse_html_input(&input, ParseMode::Synchronous);
+        let res = parse_html_input(&input, ParseOptions::new().mode(ParseMode::Synchronous));
         println!("{}", res.tree.query_selector_all("div[class]").len());
         println!("{:?}", time.elapsed());
         assert_eq!(res.errors.len(), 0)
@@ -263,7 +388,7 @@ mod tests {
     fn it_parses_elements_speculatively() {
         let res = parse_html_input(
             "<div><input/><div><span></span></div></div>",
-            ParseMode::Speculative,
+            ParseOptions::new().mode(ParseMode::Speculative),
         );
         println!("{:#?}", res.tree);
 
@@ -279,10 +404,15 @@ mod tests {
                     <p>This is inner text.</p>
                 </body>
             </html>",
-            ParseMode::Speculative,
+            ParseOptions::new().mode(ParseMode::Speculative),
         );
         assert_eq!(
-            res.tree.children().next().unwrap().get_text_content(),
+            res.tree
+                .children()
+                .next()
+                .unwrap()
+                .get_text_content()
+                .trim(),
             "This is inner text."
         );
 
@@ -296,8 +426,1003 @@ mod tests {
         <button type=button>
         <p> Hello world! </p>
         </div>",
-            ParseMode::Speculative,
+            ParseOptions::new().mode(ParseMode::Speculative),
         );
-        assert_eq!(res.errors.len(), 2, "Errors encountered: {:?}", res.errors)
+        assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+        assert!(matches!(
+            &res.errors[0].error_type,
+            HtmlParseErrorType::UnclosedTag(HtmlTag::Button)
+        ));
+    }
+
+    #[test]
+    fn it_tokenizes_style_as_raw_text() {
+        let res = tokenize_html("<style>p > a { content: '</p>'; }</style>");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert_eq!(res.tokens.len(), 4, "Tokenized {:?}", res.tokens);
+        assert!(matches!(
+            &res.tokens[1],
+            HtmlToken::Text { content, .. } if content == "p > a { content: '</p>'; }"
+        ));
+        assert!(matches!(
+            &res.tokens[2],
+            HtmlToken::ClosingTag {
+                name: HtmlTag::Style,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn it_tokenizes_script_data() {
+        let res = tokenize_html("<script>if (a<b) { x = '</div>' }</SCRIPT ><p>");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert_eq!(res.tokens.len(), 5, "Tokenized {:?}", res.tokens);
+        assert!(matches!(
+            &res.tokens[1],
+            HtmlToken::Text { content, .. } if content == "if (a<b) { x = '</div>' }"
+        ));
+        assert!(matches!(
+            &res.tokens[3],
+            HtmlToken::OpeningTag {
+                name: HtmlTag::P,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn it_tokenizes_escaped_script_data() {
+        let res = tokenize_html("<script><!--<script></script>--></script>");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert_eq!(res.tokens.len(), 4, "Tokenized {:?}", res.tokens);
+        assert!(matches!(
+            &res.tokens[1],
+            HtmlToken::Text { content, .. } if content == "<!--<script></script>-->"
+        ));
+    }
+
+    #[test]
+    fn it_parses_scripts_speculatively() {
+        let res = parse_html_input(
+            "<div><script>document.write('<b>')</script><style>b{}</style></div>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+        let script = res.tree.query_selector("script").unwrap();
+        assert_eq!(script.element().child_nodes.len(), 1);
+        assert_eq!(script.get_text_content(), "document.write('<b>')");
+        assert_eq!(
+            res.tree.query_selector("style").unwrap().get_text_content(),
+            "b{}"
+        );
+    }
+
+    #[test]
+    fn it_parses_inline_style_in_site() {
+        let input = std::fs::read_to_string("../../site/index.html").unwrap();
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(&input, ParseOptions::new().mode(mode));
+            let style = res.tree.query_selector("style").unwrap();
+            assert_eq!(style.element().child_nodes.len(), 1);
+            assert!(style.get_text_content().contains(".outline *"));
+        }
+    }
+
+    #[test]
+    fn it_decodes_character_references_in_text() {
+        let res = tokenize_html("a &amp; b &lt;c&gt; &#x27;d&#39; &nbsp;&NotNestedGreaterGreater;");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert!(matches!(
+            &res.tokens[0],
+            HtmlToken::Text { content, .. } if content == "a & b <c> 'd' \u{a0}\u{2aa2}\u{338}"
+        ));
+    }
+
+    #[test]
+    fn it_decodes_legacy_character_references() {
+        let res = tokenize_html("&copy 2023 &notin; &notit; &bogus; & x");
+        assert_eq!(res.errors.len(), 3, "Errors: {:?}", res.errors);
+        assert!(matches!(
+            &res.tokens[0],
+            HtmlToken::Text { content, .. } if content == "\u{a9} 2023 \u{2209} \u{ac}it; &bogus; & x"
+        ));
+    }
+
+    #[test]
+    fn it_decodes_character_references_in_attributes() {
+        let res = tokenize_html("<a href=\"?a=1&copy=2&amp;b=&lt\" title=&quot;x&quot;>");
+        assert_eq!(res.errors.len(), 1, "Errors: {:?}", res.errors);
+        assert!(matches!(
+            &res.tokens[0],
+            HtmlToken::OpeningTag { attributes, .. }
+            if attributes[0].value.as_deref() == Some("?a=1&copy=2&b=<")
+                && attributes[1].value.as_deref() == Some("\"x\"")
+        ));
+    }
+
+    #[test]
+    fn it_reports_invalid_numeric_character_references() {
+        let res = tokenize_html("&#0;&#x110000;&#xD800;&#128;&#;&#65");
+        assert_eq!(res.errors.len(), 6, "Errors: {:?}", res.errors);
+        assert!(matches!(
+            &res.tokens[0],
+            HtmlToken::Text { content, .. } if content == "\u{fffd}\u{fffd}\u{fffd}\u{20ac}&#;A"
+        ));
+    }
+
+    #[test]
+    fn it_tokenizes_rcdata() {
+        let res = tokenize_html("<textarea><b>hi</b> &amp; &lt;/textarea></textarea>");
+        assert_eq!(res.errors.len(), 0, "Errors: {:?}", res.errors);
+        assert_eq!(res.tokens.len(), 4, "Tokenized {:?}", res.tokens);
+        assert!(matches!(
+            &res.tokens[1],
+            HtmlToken::Text { content, .. } if content == "<b>hi</b> & </textarea>"
+        ));
+    }
+
+    #[test]
+    fn it_parses_rcdata_elements_as_text() {
+        let input = "<div><title>A &lt; B</title><textarea><b>hi</b></textarea></div>";
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(input, ParseOptions::new().mode(mode));
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            let title = res.tree.query_selector("title").unwrap();
+            assert_eq!(title.get_text_content(), "A < B");
+            let textarea = res.tree.query_selector("textarea").unwrap();
+            assert_eq!(textarea.element().child_nodes.len(), 1);
+            assert_eq!(textarea.get_text_content(), "<b>hi</b>");
+            assert!(res.tree.query_selector("b").is_none());
+        }
+    }
+
+    #[test]
+    fn it_inserts_implied_document_elements() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<title>Page</title><p>Hello",
+                ParseOptions::new().mode(mode),
+            );
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            assert_eq!(res.tree.nodes.len(), 1);
+            let html = res.tree.children().next().unwrap();
+            assert_eq!(html.tag_name(), &HtmlTag::Html);
+            let children: Vec<_> = html
+                .children()
+                .map(|child| child.tag_name().clone())
+                .collect();
+            assert_eq!(children, vec![HtmlTag::Head, HtmlTag::Body]);
+            assert!(res.tree.query_selector("head > title").is_some());
+            assert_eq!(
+                res.tree
+                    .query_selector("body > p")
+                    .unwrap()
+                    .get_text_content(),
+                "Hello"
+            );
+        }
+    }
+
+    #[test]
+    fn it_moves_head_content_after_the_head() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<html><head></head><meta charset=utf-8><body></body></html>",
+                ParseOptions::new().mode(mode),
+            );
+            assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+            assert!(res.tree.query_selector("head > meta").is_some());
+            assert!(res.tree.query_selector("body > meta").is_none());
+        }
+    }
+
+    #[test]
+    fn it_merges_repeated_body_attributes() {
+        let res = parse_html_input(
+            "<body class=a><p>text</p><body id=main class=b>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+        assert!(matches!(
+            &res.errors[0].error_type,
+            HtmlParseErrorType::UnexpectedOpenTag(HtmlTag::Body)
+        ));
+        assert_eq!(res.tree.query_selector_all("body").len(), 1);
+        assert!(res.tree.query_selector("body#main.a").is_some());
+    }
+
+    #[test]
+    fn it_closes_list_items_implicitly() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<ul><li>a<li>b</ul><dl><dt>c<dd>d<dt>e</dl>",
+                ParseOptions::new().mode(mode),
+            );
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            let items = res.tree.query_selector_all("ul > li");
+            assert_eq!(items.len(), 2);
+            assert_eq!(items[0].get_text_content(), "a");
+            assert_eq!(items[1].get_text_content(), "b");
+            assert_eq!(res.tree.query_selector_all("dl > dt").len(), 2);
+            assert_eq!(res.tree.query_selector_all("dl > dd").len(), 1);
+            assert!(res.tree.query_selector("li li").is_none());
+        }
+    }
+
+    #[test]
+    fn it_closes_paragraphs_implicitly() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<p>one<p>two<div>three</div><h1>four<h2>five</h1>",
+                ParseOptions::new().mode(mode),
+            );
+            // The nested heading, then the end tag that closes it with the wrong name.
+            assert_eq!(res.errors.len(), 2, "Errors encountered: {:?}", res.errors);
+            assert!(matches!(
+                &res.errors[0].error_type,
+                HtmlParseErrorType::UnexpectedOpenTag(HtmlTag::H2)
+            ));
+            assert_eq!(res.tree.query_selector_all("body > p").len(), 2);
+            assert!(res.tree.query_selector("p div").is_none());
+            assert_eq!(res.tree.query_selector_all("body > h1, body > h2").len(), 2);
+        }
+    }
+
+    #[test]
+    fn it_inserts_paragraph_for_stray_end_tag() {
+        let res = parse_html_input(
+            "<div></p></div>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+        assert!(matches!(
+            &res.errors[0].error_type,
+            HtmlParseErrorType::UnexpectedCloseTag(HtmlTag::P)
+        ));
+        assert!(res.tree.query_selector("div > p").is_some());
+    }
+
+    #[test]
+    fn it_adopts_misnested_formatting_elements() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input("<b><i>text</b> more</i>", ParseOptions::new().mode(mode));
+            assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+            assert!(matches!(
+                &res.errors[0].error_type,
+                HtmlParseErrorType::UnexpectedCloseTag(HtmlTag::B)
+            ));
+            assert_eq!(
+                res.tree
+                    .query_selector("body > b > i")
+                    .unwrap()
+                    .get_text_content(),
+                "text"
+            );
+            assert_eq!(
+                res.tree
+                    .query_selector("body > i")
+                    .unwrap()
+                    .get_text_content(),
+                " more"
+            );
+        }
+    }
+
+    #[test]
+    fn it_moves_blocks_out_of_formatting_elements() {
+        let res = parse_html_input(
+            "<b>1<p>2</b>3</p>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+        let body = res.tree.query_selector("body").unwrap();
+        let children: Vec<_> = body
+            .children()
+            .map(|child| child.tag_name().clone())
+            .collect();
+        assert_eq!(children, vec![HtmlTag::B, HtmlTag::P]);
+        assert_eq!(
+            res.tree
+                .query_selector("body > b")
+                .unwrap()
+                .get_text_content(),
+            "1"
+        );
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.get_text_content(), "23");
+        assert_eq!(
+            res.tree.query_selector("p > b").unwrap().get_text_content(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn it_reconstructs_active_formatting_elements() {
+        let res = parse_html_input(
+            "<p><b>bold<p>still bold",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        // The paragraph is closed while <b> is open, and <b> is still open at the end.
+        assert_eq!(res.errors.len(), 2, "Errors encountered: {:?}", res.errors);
+        let bold = res.tree.query_selector_all("p > b");
+        assert_eq!(bold.len(), 2);
+        assert_eq!(bold[1].get_text_content(), "still bold");
+
+        let res = parse_html_input(
+            "<a href=1>one<a href=2>two</a>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 1, "Errors encountered: {:?}", res.errors);
+        assert_eq!(res.tree.query_selector_all("body > a").len(), 2);
+        assert!(res.tree.query_selector("a a").is_none());
+    }
+
+    #[test]
+    fn it_implies_table_sections_and_closes_cells() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<table><tr><td>1<td>2<tr><th>3</table>",
+                ParseOptions::new().mode(mode),
+            );
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            assert_eq!(res.tree.query_selector_all("table > tbody > tr").len(), 2);
+            let cells = res.tree.query_selector_all("tr > td");
+            assert_eq!(cells.len(), 2);
+            assert_eq!(cells[1].get_text_content(), "2");
+            assert!(res.tree.query_selector("td td").is_none());
+            assert!(res.tree.query_selector("tr > th").is_some());
+        }
+    }
+
+    #[test]
+    fn it_parses_table_parts() {
+        let res = parse_html_input(
+            "<table><caption>c</caption><colgroup><col></colgroup><thead><tr><th>h</thead><tr><td>d</table>",
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+        let table = res.tree.query_selector("table").unwrap();
+        let children: Vec<_> = table
+            .children()
+            .map(|child| child.tag_name().clone())
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                HtmlTag::Caption,
+                HtmlTag::Colgroup,
+                HtmlTag::Thead,
+                HtmlTag::Tbody
+            ]
+        );
+        assert!(res.tree.query_selector("colgroup > col").is_some());
+        assert_eq!(
+            res.tree
+                .query_selector("tbody td")
+                .unwrap()
+                .get_text_content(),
+            "d"
+        );
+    }
+
+    #[test]
+    fn it_foster_parents_content_in_tables() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<table>text<b>bold</b><tr><td>cell</td></tr></table>",
+                ParseOptions::new().mode(mode),
+            );
+            assert!(!res.errors.is_empty());
+            let body = res.tree.query_selector("body").unwrap();
+            let children: Vec<_> = body
+                .children()
+                .map(|child| child.tag_name().clone())
+                .collect();
+            assert_eq!(children, vec![HtmlTag::B, HtmlTag::Table]);
+            assert_eq!(body.get_text_content(), "textboldcell");
+            assert_eq!(
+                res.tree.query_selector("td").unwrap().get_text_content(),
+                "cell"
+            );
+        }
+    }
+
+    #[test]
+    fn it_parses_fragments_in_context() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_fragment(
+                "<tr><td>1</td></tr><tr><td>2",
+                HtmlTag::Tbody,
+                ParseOptions::new().mode(mode),
+            );
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            assert_eq!(res.tree.nodes.len(), 2);
+            let rows: Vec<_> = res.tree.children().collect();
+            assert!(rows.iter().all(|row| row.tag_name() == &HtmlTag::Tr));
+            assert!(rows[1].parent().is_none());
+            assert_eq!(rows[1].get_text_content(), "2");
+            assert!(res.tree.query_selector("tbody").is_none());
+        }
+    }
+
+    #[test]
+    fn it_parses_fragments_without_document_elements() {
+        let res = parse_html_fragment(
+            "<li>one<li>two",
+            HtmlTag::Ul,
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+        assert_eq!(res.tree.query_selector_all("li").len(), 2);
+        assert!(res.tree.query_selector("body").is_none());
+
+        let res = parse_html_fragment(
+            "a <b>&amp;</b>",
+            HtmlTag::Textarea,
+            ParseOptions::new().mode(ParseMode::Speculative),
+        );
+        assert_eq!(res.tree.nodes.len(), 1);
+        assert!(matches!(&res.tree.nodes[0], HtmlNode::Text(text) if text.content == "a <b>&</b>"));
+    }
+
+    #[test]
+    fn it_parses_from_a_reader() {
+        let input = "<ul><li>caf\u{e9}</li><li>\u{1F600}</li></ul>".repeat(2000);
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_reader(input.as_bytes(), ParseOptions::new().mode(mode)).unwrap();
+            assert_eq!(res.errors.len(), 0, "Errors encountered: {:?}", res.errors);
+            let items = res.tree.query_selector_all("li");
+            assert_eq!(items.len(), 4000);
+            assert_eq!(items[3999].get_text_content(), "\u{1F600}");
+        }
+    }
+
+    #[test]
+    fn it_decodes_bytes_in_the_declared_encoding() {
+        let input = b"<meta charset=windows-1251><p>\xcf\xf0\xe8\xe2\xe5\xf2</p>";
+        let res = parse_html_bytes(
+            input,
+            ParseOptions::new()
+                .mode(ParseMode::Synchronous)
+                .fallback_encoding(Encoding::Windows1252),
+        );
+        assert_eq!(res.encoding, Encoding::Windows1251);
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(
+            paragraph.get_text_content(),
+            "\u{41f}\u{440}\u{438}\u{432}\u{435}\u{442}"
+        );
+    }
+
+    #[test]
+    fn it_decodes_bytes_with_the_fallback_encoding() {
+        let res = parse_html_bytes(
+            b"<p>caf\xe9",
+            ParseOptions::new()
+                .mode(ParseMode::Speculative)
+                .fallback_encoding(Encoding::Windows1252),
+        );
+        assert_eq!(res.encoding, Encoding::Windows1252);
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.get_text_content(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn it_decodes_utf16_bytes_with_a_byte_order_mark() {
+        let mut input = vec![0xFF, 0xFE];
+        for unit in "<p>\u{1F600}</p>".encode_utf16() {
+            input.extend_from_slice(&unit.to_le_bytes());
+        }
+        let res = parse_html_bytes(
+            &input,
+            ParseOptions::new()
+                .mode(ParseMode::Synchronous)
+                .fallback_encoding(Encoding::Windows1252),
+        );
+        assert_eq!(res.encoding, Encoding::Utf16Le);
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.get_text_content(), "\u{1F600}");
+    }
+
+    #[test]
+    fn it_parses_files_with_any_extension() {
+        let directory = std::env::temp_dir();
+        for name in ["sleek-template.tpl", "sleek-template"] {
+            let path = directory.join(name);
+            std::fs::write(&path, b"<p>caf\xe9</p>").unwrap();
+            let res = parse_html_file(&path, ParseOptions::new()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let paragraph = res.tree.query_selector("p").unwrap();
+            assert_eq!(paragraph.get_text_content(), "caf\u{e9}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_parses_files_with_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let path = std::env::temp_dir().join(OsStr::from_bytes(b"sleek-\xff.html"));
+        std::fs::write(&path, "<p>text</p>").unwrap();
+        let res = parse_html_file(&path, ParseOptions::new().mode(ParseMode::Synchronous));
+        std::fs::remove_file(&path).unwrap();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn it_distinguishes_missing_files_from_unsupported_content() {
+        let directory = std::env::temp_dir();
+        let missing = parse_html_file(directory.join("sleek-missing.html"), ParseOptions::new());
+        assert!(matches!(missing, Err(HtmlFileError::Io(_))));
+
+        let path = directory.join("sleek-image.png");
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR").unwrap();
+        let binary = parse_html_file(&path, ParseOptions::new().sniff_content(true));
+        let unchecked = parse_html_file(&path, ParseOptions::new());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(binary, Err(HtmlFileError::UnsupportedContent)));
+        assert!(unchecked.is_ok());
+    }
+
+    #[test]
+    fn it_parses_noscript_as_text_with_scripting() {
+        let input = "<head><noscript><link rel=icon></noscript></head>";
+        let res = parse_html_input(input, ParseOptions::new().scripting(true));
+        let noscript = res.tree.query_selector("noscript").unwrap();
+        assert_eq!(noscript.get_text_content(), "<link rel=icon>");
+        assert!(res.tree.query_selector("link").is_none());
+
+        let res = parse_html_input(input, ParseOptions::new());
+        assert!(res.tree.query_selector("noscript link").is_some());
+    }
+
+    #[test]
+    fn it_preserves_whitespace_text_by_default() {
+        let input = "<p><span>a</span> <span>b</span></p><pre>\n\n  x\n</pre>";
+        let res = parse_html_input(input, ParseOptions::new());
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.get_text_content(), "a b");
+        // The first line break of a `<pre>` is dropped by the tree construction rules.
+        let pre = res.tree.query_selector("pre").unwrap();
+        assert_eq!(pre.get_text_content(), "\n  x\n");
+
+        let res = parse_html_input(input, ParseOptions::new().whitespace(Whitespace::Remove));
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.get_text_content(), "ab");
+    }
+
+    #[test]
+    fn it_stops_collecting_errors_at_the_limit() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(
+                "<body></b></i></u><p>text",
+                ParseOptions::new().mode(mode).max_errors(2),
+            );
+            assert_eq!(res.errors.len(), 2, "Errors encountered: {:?}", res.errors);
+            assert!(res.tree.query_selector("p").is_some());
+        }
+    }
+
+    #[test]
+    fn it_limits_the_nesting_depth() {
+        fn depth(element: &ElementRef) -> usize {
+            let children = &element.element().child_nodes;
+            1 + children
+                .iter()
+                .filter_map(|node| match node {
+                    HtmlNode::Element(child) => Some(depth(child)),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0)
+        }
+        fn children(element: &ElementRef) -> Vec<String> {
+            let element = element.element();
+            element
+                .child_nodes
+                .iter()
+                .map(|node| match node {
+                    HtmlNode::Element(child) => child.tag_name().to_string(),
+                    HtmlNode::Text(text) => text.content.clone(),
+                    _ => String::new(),
+                })
+                .collect()
+        }
+        let input = "<div>".repeat(10);
+        let res = parse_html_input(&input, ParseOptions::new().max_nesting_depth(4));
+        assert_eq!(res.tree.query_selector_all("div").len(), 10);
+        let html = res.tree.query_selector("html").unwrap();
+        assert_eq!(depth(&html), 5);
+        let limit = res.tree.query_selector("body > div > div").unwrap();
+        assert_eq!(children(&limit), vec!["div"; 8]);
+
+        let input = "<div>hello<span>x</span></div>";
+        let res = parse_html_input(input, ParseOptions::new().max_nesting_depth(3));
+        let div = res.tree.query_selector("div").unwrap();
+        assert_eq!(children(&div), ["hello", "span", "x"]);
+
+        let input = "<ul><li>a</li></ul>";
+        let res = parse_html_input(input, ParseOptions::new().max_nesting_depth(4));
+        let item = res.tree.query_selector("li").unwrap();
+        assert_eq!(children(&item), ["a"]);
+
+        for max in [0, 1] {
+            let res = parse_html_input("<p>x", ParseOptions::new().max_nesting_depth(max));
+            assert_eq!(res.tree.nodes.len(), 1);
+            let body = res.tree.query_selector("body").unwrap();
+            assert_eq!(children(&body), ["p", "x"]);
+        }
+    }
+
+    #[test]
+    fn it_drops_comments_and_positions_when_asked() {
+        let options = ParseOptions::new()
+            .keep_comments(false)
+            .keep_positions(false);
+        let res = parse_html_input("<!--a--><p>b<!--c-->", options);
+        assert_eq!(res.tree.nodes.len(), 1);
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.element().child_nodes.len(), 1);
+        assert_eq!(paragraph.element().location, ElementSpan::empty());
+
+        // The input is not indexed, so errors have locations but no offsets.
+        let res = parse_html_input("a\n<p/>", ParseOptions::new().keep_positions(false));
+        let error = res.errors.last().unwrap();
+        assert_eq!(
+            error.error_type.name(),
+            "non-void-html-element-start-tag-with-trailing-solidus"
+        );
+        assert_eq!((error.location, error.offset), ([2, 1], 0));
+    }
+
+    #[test]
+    fn it_parses_valid_input_strictly() {
+        let res = parse_html_strict("<title>Page</title><p>Hello</p>", ParseOptions::new());
+        assert!(res.is_ok(), "Error encountered: {:?}", res.err());
+    }
+
+    #[test]
+    fn it_fails_strict_parsing_at_the_threshold() {
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let input = "<body></b><div></i></div><p>text</u>";
+            let error = parse_html_strict(input, ParseOptions::new().mode(mode)).unwrap_err();
+            assert!(matches!(
+                error.error_type,
+                HtmlParseErrorType::UnexpectedCloseTag(HtmlTag::B)
+            ));
+
+            let options = ParseOptions::new().mode(mode).error_threshold(2);
+            let error = parse_html_strict(input, options).unwrap_err();
+            assert!(matches!(
+                error.error_type,
+                HtmlParseErrorType::UnexpectedCloseTag(HtmlTag::I)
+            ));
+        }
+    }
+
+    #[test]
+    fn it_names_errors_after_the_standard() {
+        let cases = [
+            ("<div", "eof-in-tag"),
+            ("<?xml?>", "unexpected-question-mark-instead-of-tag-name"),
+            ("</>", "missing-end-tag-name"),
+            ("a <1", "invalid-first-character-of-tag-name"),
+            ("<!x>", "incorrectly-opened-comment"),
+            ("<p></p class=a>", "end-tag-with-attributes"),
+            ("<p a=>", "missing-attribute-value"),
+            ("<p a=\"b\"c>", "missing-whitespace-between-attributes"),
+            ("<p a\"b>", "unexpected-character-in-attribute-name"),
+            ("<p =a>", "unexpected-equals-sign-before-attribute-name"),
+            (
+                "<p a=b<c>",
+                "unexpected-character-in-unquoted-attribute-value",
+            ),
+            ("<script><!-- a", "eof-in-script-html-comment-like-text"),
+            ("a\u{1}b", "control-character-in-input-stream"),
+            ("a\u{fdd0}b", "noncharacter-in-input-stream"),
+            ("a\u{1fffe}b", "noncharacter-in-input-stream"),
+        ];
+        for (input, name) in cases {
+            let res = parse_html_input(input, ParseOptions::new());
+            let names: Vec<_> = res.errors.iter().map(|e| e.error_type.name()).collect();
+            assert!(names.contains(&name), "{input}: {names:?}");
+        }
+    }
+
+    #[test]
+    fn it_locates_nodes_and_errors_by_byte_offset() {
+        let source = "<p>caf\u{e9}</p>\n<div title=\"\u{1F600}\">\u{1F600}</b></div>";
+        for mode in [ParseMode::Speculative, ParseMode::Synchronous] {
+            let res = parse_html_input(source, ParseOptions::new().mode(mode));
+            let paragraph = res.tree.query_selector("p").unwrap();
+            let location = &paragraph.element().location;
+            assert_eq!(&source[location.open_tag.range()], "<p>");
+            assert_eq!(
+                &source[location.close_tag.as_ref().unwrap().range()],
+                "</p>"
+            );
+            let HtmlNode::Text(text) = &paragraph.element().child_nodes[0] else {
+                panic!("Expected text in the paragraph");
+            };
+            assert_eq!(&source[text.span.range()], "caf\u{e9}");
+
+            let div = res.tree.query_selector("div").unwrap();
+            assert_eq!(
+                &source[div.element().location.open_tag.range()],
+                "<div title=\"\u{1F600}\">"
+            );
+            assert_eq!(res.errors.len(), 1);
+            assert_eq!(&source[res.errors[0].offset..][..4], "</b>");
+
+            let index = LineIndex::new(source);
+            assert_eq!(
+                index.position(res.errors[0].offset),
+                Some(res.errors[0].location)
+            );
+            assert_eq!(res.errors[0].location, [2, 17]);
+            assert_eq!(index.utf16_column(res.errors[0].location), Some(19));
+        }
+    }
+
+    #[test]
+    fn it_finds_offsets_of_errors_read_many_lines_before() {
+        let source = format!("<div><span a=1 a=2>\n{}</div>", "line\n".repeat(500));
+        let res = parse_html_reader(source.as_bytes(), ParseOptions::new()).unwrap();
+        let errors: Vec<_> = res
+            .errors
+            .iter()
+            .map(|error| (error.error_type.name(), error.location, error.offset))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("duplicate-attribute", [1, 16], 15),
+                ("unclosed-element", [1, 6], 5)
+            ]
+        );
+        let index = LineIndex::new(&source);
+        for error in &res.errors {
+            assert_eq!(index.position(error.offset), Some(error.location));
+        }
+    }
+
+    #[test]
+    fn it_writes_an_unchanged_document_back_exactly() {
+        let sources = [
+            "<HTML>\n  <Body CLASS='main'   id=top>\n<p>a &amp; b&nbsp;</P>\n</body>\n</html>\n",
+            "<!-- note -->\n<ul>\n  <li>One\n  <li>Two\n</ul></x>  <b><p>misnested</b></p>",
+            "<table><tr><td>cell</td>text</tr></table>\n<img src=\"a.png\"/>",
+            "  leading text<script>if (a < b) {}</script>trailing  \n",
+        ];
+        for source in sources {
+            let res = parse_html_input(source, ParseOptions::new().lossless(true));
+            assert_eq!(res.tree.to_source().as_deref(), Some(source));
+        }
+        let res = parse_html_input("<p>", ParseOptions::new());
+        assert!(res.tree.to_source().is_none());
+    }
+
+    #[test]
+    fn it_writes_edits_back_with_minimal_changes() {
+        let source =
+            "<DIV class='box'  data-x=1 hidden>\n  <p>Hi &amp; bye</p>\n  <span>old</span>\n</DIV>";
+        let res = parse_html_input(source, ParseOptions::new().lossless(true));
+        let mut div = res.tree.query_selector("div").unwrap();
+        div.set_attribute("class", "box wide");
+        div.set_attribute("title", "new");
+        div.remove_attribute("hidden");
+        let span = div.query_selector("span").unwrap();
+        div.remove(&span);
+        let mut em = ElementRef::new("em");
+        em.set_attribute("lang", "\"en\"");
+        div.append(&em);
+        let paragraph = div.query_selector("p").unwrap();
+        if let HtmlNode::Text(text) = &mut paragraph.element().child_nodes[0] {
+            text.content = "Hi < bye".to_string();
+        }
+        assert_eq!(
+            res.tree.to_source().unwrap(),
+            "<DIV class='box wide'  data-x=1 title=\"new\">\n  <p>Hi &lt; bye</p>\n  \n<em lang=\"&quot;en&quot;\"></em></DIV>"
+        );
+    }
+
+    #[test]
+    fn it_keeps_attributes_in_source_order_with_their_spans() {
+        let source = "<a id=\"top\" href=x\n  download class='c'>";
+        let res = parse_html_input(source, ParseOptions::new().lossless(true));
+        let mut anchor = res.tree.query_selector("a").unwrap();
+        let spans: Vec<_> = anchor
+            .element()
+            .attributes
+            .iter()
+            .map(|(name, attribute)| {
+                let value = attribute.value_span.as_ref();
+                (
+                    name.as_str(),
+                    &source[attribute.name_span.range()],
+                    value.map(|span| &source[span.range()]),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("id", "id", Some("\"top\"")),
+                ("href", "href", Some("x")),
+                ("download", "download", None),
+                ("class", "class", Some("'c'")),
+            ]
+        );
+        let download = &anchor
+            .element()
+            .attributes
+            .get("download")
+            .unwrap()
+            .name_span;
+        assert_eq!((download.start, download.end), ([2, 3], [2, 11]));
+
+        let mut em = ElementRef::new("em");
+        em.set_attribute("title", "t");
+        em.set_attribute("id", "e");
+        anchor.append(&em);
+        assert_eq!(
+            res.tree.to_source().unwrap(),
+            "<a id=\"top\" href=x\n  download class='c'><em title=\"t\" id=\"e\"></em>"
+        );
+    }
+
+    /// The nodes of a tree with their spans, one per line, for comparing trees.
+    fn outline(nodes: &[HtmlNode], depth: usize, lines: &mut Vec<String>) {
+        for node in nodes {
+            let indent = "  ".repeat(depth);
+            match node {
+                HtmlNode::Element(element) => {
+                    let element = element.element();
+                    let mut attributes: Vec<_> = element
+                        .attributes
+                        .iter()
+                        .map(|(name, value)| format!("{name}={:?}", value.data))
+                        .collect();
+                    attributes.sort();
+                    lines.push(format!(
+                        "{indent}<{}> {attributes:?} {:?}",
+                        element.name, element.location
+                    ));
+                    outline(&element.child_nodes, depth + 1, lines);
+                }
+                HtmlNode::Text(text) => lines.push(format!("{indent}{text:?}")),
+                HtmlNode::Comment(comment) => lines.push(format!("{indent}{comment:?}")),
+                HtmlNode::DocType(doctype) => lines.push(format!("{indent}{doctype:?}")),
+            }
+        }
+    }
+
+    #[test]
+    fn it_reparses_the_element_around_an_edit() {
+        let source = "<div id=a><p>One</p>\n<p>Two</p></div>\n<span>after</span>";
+        let cases = [
+            (source, 24..27, "Three\nlines", Some(HtmlTag::P)),
+            (source, 21..21, "<p>New</p>", Some(HtmlTag::Div)),
+            // The end tag closes the div early, which parsing the paragraph alone cannot do.
+            (source, 25..25, "</div>", None),
+            // Start tags that close an element around them, without an error.
+            ("<ul><li>ab</li></ul>", 9..9, "<li>c", Some(HtmlTag::Ul)),
+            ("<p>ab</p>", 4..4, "<div>x</div>", None),
+            ("<div><a href=1>ab</a></div>", 16..16, "<a>x</a>", None),
+        ];
+        for (source, range, text, parent) in cases {
+            let edit = TextEdit::new(range, text);
+            let tree = parse_html_input(source, ParseOptions::new()).tree;
+            let result = reparse_html(tree, source, &edit, ParseOptions::new());
+            assert_eq!(result.parent.as_ref().map(|p| p.tag_name().clone()), parent);
+
+            let edited = edit.apply(source);
+            let full = parse_html_input(&edited, ParseOptions::new());
+            let (mut expected, mut actual) = (vec![], vec![]);
+            outline(&full.tree.nodes, 0, &mut expected);
+            outline(&result.tree.nodes, 0, &mut actual);
+            assert_eq!(actual, expected, "{edited}");
+            if let Some(span) = result.tree.query_selector("span") {
+                assert_eq!(&edited[span.element().location.open_tag.range()], "<span>");
+            }
+        }
+    }
+
+    #[test]
+    fn it_decides_the_document_mode_from_the_doctype() {
+        let doctypes = [
+            ("<!DOCTYPE html>", DocumentMode::NoQuirks),
+            ("", DocumentMode::Quirks),
+            (
+                r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">"#,
+                DocumentMode::Quirks,
+            ),
+            (
+                r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">"#,
+                DocumentMode::LimitedQuirks,
+            ),
+            (
+                r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" 'http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd'>"#,
+                DocumentMode::NoQuirks,
+            ),
+            (
+                r#"<!DOCTYPE html SYSTEM "about:legacy-compat">"#,
+                DocumentMode::NoQuirks,
+            ),
+            ("<!DOCTYPE svg>", DocumentMode::Quirks),
+        ];
+        for (doctype, mode) in doctypes {
+            let res = parse_html_input(&format!("{doctype}<p>"), ParseOptions::new());
+            assert_eq!(res.tree.mode, mode, "{doctype}");
+        }
+
+        let res = parse_html_input(
+            r#"<!doctype html public "-//W3C//DTD XHTML 1.0 Strict//EN" 'strict.dtd'>"#,
+            ParseOptions::new(),
+        );
+        let Some(HtmlNode::DocType(doctype)) = res.tree.nodes.first() else {
+            panic!("expected a doctype");
+        };
+        assert_eq!(
+            doctype.public_identifier.as_deref(),
+            Some("-//W3C//DTD XHTML 1.0 Strict//EN")
+        );
+        assert_eq!(doctype.system_identifier.as_deref(), Some("strict.dtd"));
+        assert!(res.errors.is_empty());
+
+        // In quirks mode, a table can be inside a paragraph.
+        let res = parse_html_input("<p><table></table>", ParseOptions::new());
+        let table = res.tree.query_selector("table").unwrap();
+        assert_eq!(table.parent().unwrap().tag_name(), &HtmlTag::P);
+    }
+
+    #[test]
+    fn it_parses_svg_and_mathml_as_foreign_content() {
+        let input = "<svg viewbox='0 0 1 1'><clippath/><foreignobject><p>a</p></foreignobject>\
+            <title>t</title><![CDATA[<b>&amp;]]></svg><math><mi><b>x</b></mi></math><svg><p>b";
+        let res = parse_html_input(input, ParseOptions::new());
+        let svg = res.tree.query_selector("svg").unwrap();
+        assert_eq!(svg.namespace(), Namespace::Svg);
+        assert!(svg.element().attributes.contains_key("viewBox"));
+        let names: Vec<_> = svg
+            .children()
+            .map(|child| child.tag_name().to_string())
+            .collect();
+        assert_eq!(names, ["clipPath", "foreignObject", "title"]);
+        assert!(svg
+            .children()
+            .all(|child| child.namespace() == Namespace::Svg));
+        assert_eq!(svg.get_text_content(), "at<b>&amp;");
+
+        // The content of `<foreignObject>` and `<mi>` is HTML again.
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert_eq!(paragraph.namespace(), Namespace::Html);
+        let bold = res.tree.query_selector("b").unwrap();
+        assert_eq!(bold.namespace(), Namespace::Html);
+        assert_eq!(bold.parent().unwrap().namespace(), Namespace::MathMl);
+
+        // An SVG `<title>` is not the title of the document.
+        assert!(res
+            .tree
+            .query_selector("head")
+            .unwrap()
+            .children()
+            .next()
+            .is_none());
+
+        // HTML elements like `<p>` close the foreign elements around them.
+        let body = res.tree.query_selector("body").unwrap();
+        let last = body.children().last().unwrap();
+        assert_eq!(last.tag_name(), &HtmlTag::P);
+        assert_eq!(last.get_text_content(), "b");
+
+        // Outside of foreign content, CDATA sections are comments.
+        let res = parse_html_input("<p><![CDATA[x]]></p>", ParseOptions::new());
+        let paragraph = res.tree.query_selector("p").unwrap();
+        assert!(matches!(
+            &paragraph.element().child_nodes[..],
+            [HtmlNode::Comment(_)]
+        ));
     }
 }
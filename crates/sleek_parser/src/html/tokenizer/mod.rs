@@ -1,5 +1,8 @@
+mod entities;
 mod state;
 mod store;
+mod stream;
 
 pub use state::tokenize;
 pub use store::TokenStore;
+pub use stream::HtmlTokenizer;
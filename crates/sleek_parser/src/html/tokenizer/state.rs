@@ -1,10 +1,11 @@
-use std::str::Chars;
-
 use sleek_utils::{HigherOrderIterator, QueueMatrix};
 
 use sleek_ast::{AttributeQuoteType as QuoteType, DocTypeIdentifier, HtmlToken};
 
-use super::store::{Event, TokenStore};
+use super::{
+    entities,
+    store::{Event, TokenStore},
+};
 use crate::html::HtmlParseErrorType as ErrorType;
 
 #[derive(Debug)]
@@ -17,14 +18,62 @@ pub enum State {
     Comment,
     AttributeValue,
     Doctype,
+    /// Text inside elements like `<style>`, which ends only at the matching end tag.
+    RawText,
+    /// Text inside `<script>`, which can be escaped with `<!--` to hide end tags.
+    ScriptData,
+    /// Text inside `<title>` and `<textarea>`, which is raw text that can contain character references.
+    RcData,
+    /// Text inside a `<![CDATA[` section in SVG or MathML.
+    CData,
+}
+
+/// The escape states that script data can be in.
+#[derive(PartialEq)]
+enum Escape {
+    None,
+    /// Inside a `<!--` in a script.
+    Escaped,
+    /// Inside a `<script>` nested in an escaped script. End tags here do not close the script.
+    DoubleEscaped,
+}
+
+/// The states of the standard for reading a comment after its `<!--`.
+#[derive(Clone, Copy)]
+enum CommentState {
+    Start,
+    StartDash,
+    Text,
+    /// After a `<`, which could start a nested `<!--`.
+    LessThan,
+    LessThanBang,
+    LessThanBangDash,
+    LessThanBangDashDash,
+    EndDash,
+    End,
+    /// After `--!`, which ends the comment if a `>` follows.
+    EndBang,
 }
 
 /// Tokenize an input string.
-pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'_>>) {
-    // Starting state.
-    let mut state = State::Data;
+pub fn tokenize(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+) {
+    // Starting state. Usually data, unless a fragment starts inside an element like <textarea>.
+    let mut state = token_store.next_state();
 
     loop {
+        token_store.take_input_errors();
+        // Strict parsing gives up without reaching the end of the input.
+        if token_store.stopped() {
+            return;
+        }
+        // Reading tokens one at a time stops between states, whose progress is kept in the store.
+        if token_store.pausing() {
+            token_store.pause_in(state);
+            return;
+        }
         match state {
             // Parse regular html text, without any formatting.
             State::Data => match iterator.next() {
@@ -42,6 +91,13 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     }
                     token_store.error(ErrorType::InvalidCharacter, iterator);
                 }
+                Some('&') => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator)
+                    }
+                    let text = character_reference(token_store, iterator, false);
+                    token_store.push_str(&text);
+                }
                 Some(ch) => {
                     // Collect the starting point of the text node.
                     if token_store.empty() {
@@ -51,6 +107,7 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                 }
                 None => {
                     if !token_store.empty() {
+                        token_store.set_end_of_input(iterator);
                         token_store.emit(Event::Text, iterator);
                     }
                     break;
@@ -71,20 +128,22 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                                 Some(ch) => {
                                     token_store.push('-');
                                     token_store.push(ch);
-                                    token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                                    token_store
+                                        .error(ErrorType::IncorrectlyOpenedComment, iterator);
                                     state = State::Comment
                                 }
                                 None => {
                                     token_store.push('-');
                                     token_store.emit(Event::Comment, iterator);
-                                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                                    token_store
+                                        .error(ErrorType::IncorrectlyOpenedComment, iterator);
                                     break;
                                 }
                             },
                             // Default to comment.
                             None => {
                                 token_store.emit(Event::Comment, iterator);
-                                token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                                token_store.error(ErrorType::IncorrectlyOpenedComment, iterator);
                                 break;
                             }
                             // Check for !doctype
@@ -93,16 +152,32 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                                 if value.to_ascii_lowercase() == "octype" {
                                     state = State::Doctype;
                                 } else {
-                                    println!("{value}");
                                     token_store.push(ch);
                                     token_store.push_str(value.as_str());
-                                    token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                                    token_store
+                                        .error(ErrorType::IncorrectlyOpenedComment, iterator);
                                     state = State::Comment;
                                 }
                             }
+                            // CDATA sections are only allowed in SVG and MathML. In HTML, they are comments.
+                            Some('[') => {
+                                let value: String = iterator.collect_next(6);
+                                if value == "CDATA[" && token_store.in_foreign_content() {
+                                    state = State::CData;
+                                } else {
+                                    token_store.push('[');
+                                    value.chars().for_each(|ch| iterator.push(ch));
+                                    let error_type = match value == "CDATA[" {
+                                        true => ErrorType::CdataInHtmlContent,
+                                        false => ErrorType::IncorrectlyOpenedComment,
+                                    };
+                                    token_store.error(error_type, iterator);
+                                    state = State::BogusComment;
+                                }
+                            }
                             Some(ch) => {
                                 token_store.push(ch);
-                                token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                                token_store.error(ErrorType::IncorrectlyOpenedComment, iterator);
                                 state = State::BogusComment;
                             }
                         }
@@ -122,18 +197,18 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                             // tag is self-closing.
                             Some('>') => {
                                 token_store.emit(Event::OpenerTag(true), iterator);
-                                state = State::Data;
+                                state = token_store.next_state();
                             }
                             // Parse error. Scan character again as attribute.
                             Some(ch) => {
-                                token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                                token_store.error(ErrorType::UnexpectedSolidusInTag, iterator);
                                 iterator.push(ch);
                                 state = State::AttributeName;
                             }
                             // Tag was unclosed.
                             None => {
                                 token_store.clear();
-                                token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                                token_store.error(ErrorType::EofInTag, iterator);
                                 state = State::Data;
                             }
                         }
@@ -144,18 +219,18 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     if token_store.empty() {
                         token_store.push('<');
                         iterator.push('>');
-                        token_store.error(ErrorType::UnexpectedCharacter('>'), iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         state = State::Data;
                     } else {
                         // Push an opening tag with no attributes.
                         token_store.emit(Event::OpenerTag(false), iterator);
-                        state = State::Data;
+                        state = token_store.next_state();
                     }
                 }
                 Some(ch) if ch.is_ascii_alphanumeric() || ch == '-' => {
                     // Tags cannot start with numeric values. Reparse the tag as plain text.
                     if token_store.empty() && (ch.is_numeric() || ch == '-') {
-                        token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         token_store.push('<');
                         token_store.push(ch);
                         state = State::Data;
@@ -168,7 +243,7 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     if token_store.empty() {
                         token_store.push('<');
                         iterator.push(ch);
-                        token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         state = State::Data;
                     } else {
                         // Parsing an attribute. Revisit the current character as an attribute name.
@@ -179,9 +254,13 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                 Some(ch) => {
                     if token_store.empty() {
                         // Emit as text.
-                        iterator.push('<');
+                        token_store.push('<');
                         iterator.push(ch);
-                        token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        let error_type = match ch {
+                            '?' => ErrorType::UnexpectedQuestionMarkInsteadOfTagName,
+                            _ => ErrorType::InvalidFirstCharacterOfTagName,
+                        };
+                        token_store.error(error_type, iterator);
                         state = State::Data;
                     } else {
                         // Add to tag name.
@@ -189,9 +268,14 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     }
                 }
                 None => {
-                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                    let error_type = match token_store.empty() {
+                        true => ErrorType::EofBeforeTagName,
+                        false => ErrorType::EofInTag,
+                    };
+                    token_store.error(error_type, iterator);
                     // Emit as text.
                     token_store.push('<');
+                    token_store.set_end_of_input(iterator);
                     token_store.emit(Event::Text, iterator);
                     break;
                 }
@@ -206,18 +290,25 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                         Some(ch) if ch.is_whitespace() => {
                             // Skip over succeeding whitespaces.
                             iterator.next_until(|ch| !ch.is_whitespace());
-                            while !ended {
-                                match iterator.next() {
-                                    Some(ch) => {
-                                        // Another attribute encountered. Reparse as attribute name.
-                                        iterator.push(ch);
-                                        break;
-                                    }
-                                    // Input ended without tag close.
-                                    None => ended = true,
+                            match iterator.next() {
+                                // The value of the name before the whitespace, like in `a = b`.
+                                Some('=') if !token_store.cache.1.is_empty() => has_value = true,
+                                // Another attribute encountered. Reparse as attribute name.
+                                Some(ch) => {
+                                    iterator.push(ch);
+                                    break;
                                 }
+                                // Input ended without tag close.
+                                None => ended = true,
                             }
-                            break;
+                        }
+                        // An attribute name can start with `=`.
+                        Some('=') if token_store.cache.1.is_empty() => {
+                            token_store.error(
+                                ErrorType::UnexpectedEqualsSignBeforeAttributeName,
+                                iterator,
+                            );
+                            token_store.push_attr_name('=', iterator);
                         }
                         // Expect attribute value.
                         Some('=') => has_value = true,
@@ -227,8 +318,14 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                             state = State::OpeningTag;
                             break;
                         }
+                        // Quotes and `<` are kept in the name.
+                        Some(ch @ ('"' | '\'' | '<')) => {
+                            token_store
+                                .error(ErrorType::UnexpectedCharacterInAttributeName, iterator);
+                            token_store.push_attr_name(ch, iterator);
+                        }
                         // Push character into name.
-                        Some(ch) => token_store.push_attr_name(ch),
+                        Some(ch) => token_store.push_attr_name(ch.to_ascii_lowercase(), iterator),
                         // Input ends abruptly.
                         None => ended = true,
                     }
@@ -238,7 +335,7 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     state = State::AttributeValue;
                 } else if ended {
                     // Input ended unexpectedly.
-                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                    token_store.error(ErrorType::EofInTag, iterator);
                     token_store.clear();
                     break;
                 } else {
@@ -251,19 +348,25 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
             }
             State::AttributeValue => {
                 let mut quote_type = QuoteType::None;
+                // Whitespace can come before the value, like in `a = b`.
+                iterator.next_while(|ch| ch.is_whitespace());
                 // Check for quote type.
-                match iterator.next() {
+                let first = iterator.next();
+                if first.is_some() {
+                    token_store.start_attr_value(iterator);
+                }
+                match first {
                     Some('\'') => quote_type = QuoteType::Single,
                     Some('"') => quote_type = QuoteType::Double,
                     Some(ch) => {
-                        if ch == '<' {
-                            token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        if ch == '>' {
+                            token_store.error(ErrorType::MissingAttributeValue, iterator);
                         }
                         iterator.push(ch);
-                        iterator.inner_mut().left();
                     }
                     None => {
-                        token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                        token_store.error(ErrorType::EofInTag, iterator);
+                        token_store.clear();
                         break;
                     }
                 }
@@ -279,14 +382,41 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                             iterator.push(ch);
                             break;
                         }
+                        Some(ch @ ('"' | '\'' | '<' | '=' | '`'))
+                            if quote_type == QuoteType::None =>
+                        {
+                            token_store.error(
+                                ErrorType::UnexpectedCharacterInUnquotedAttributeValue,
+                                iterator,
+                            );
+                            token_store.push_attr_value(ch);
+                        }
+                        Some('&') => {
+                            for ch in character_reference(token_store, iterator, true).chars() {
+                                token_store.push_attr_value(ch);
+                            }
+                        }
                         Some(ch) => token_store.push_attr_value(ch),
                         None => ended = true,
                     }
                 }
                 if ended {
-                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
-                } else {
-                    token_store.collect_attribute(quote_type);
+                    token_store.error(ErrorType::EofInTag, iterator);
+                    token_store.clear();
+                    break;
+                }
+                let quoted = quote_type != QuoteType::None;
+                token_store.end_attr_value(quoted, iterator);
+                token_store.collect_attribute(quote_type);
+                // A quoted value is followed by whitespace or the end of the tag, not another attribute like in `a="b"c`.
+                if quoted {
+                    if let Some(ch) = iterator.next() {
+                        if !(ch.is_whitespace() || ch == '/' || ch == '>') {
+                            token_store
+                                .error(ErrorType::MissingWhitespaceBetweenAttributes, iterator);
+                        }
+                        iterator.push(ch);
+                    }
                 }
 
                 state = State::AttributeName;
@@ -296,14 +426,27 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                 Some(ch) if ch.is_whitespace() => {
                     // tagnames must directly follow the </
                     if token_store.empty() {
-                        token_store.error(ErrorType::ExpectedTagName, iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         state = State::Data;
+                    } else {
+                        // Closing tags have no attributes. Skip to the end of the tag.
+                        let rest: String = iterator.collect_until(|ch| ch == &'>');
+                        let rest = rest.trim();
+                        // An end tag cut off by the end of the input is only reported as such.
+                        if let Some(ch) = iterator.next() {
+                            iterator.push(ch);
+                            if rest == "/" {
+                                token_store.error(ErrorType::EndTagWithTrailingSolidus, iterator);
+                            } else if !rest.is_empty() {
+                                token_store.error(ErrorType::EndTagWithAttributes, iterator);
+                            }
+                        }
                     }
                 }
                 Some(ch) if ch.is_ascii_alphanumeric() || ch == '-' => {
                     // closing tags cannot start with numbers. Reparse the tag as a bogus comment.
                     if token_store.empty() && ch.is_numeric() {
-                        token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         iterator.push(ch);
                         // Start bogus comment here.
                         token_store.set_start(iterator);
@@ -326,7 +469,7 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     if token_store.empty() {
                         // Emit as text.
                         iterator.push(ch);
-                        token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                        token_store.error(ErrorType::InvalidFirstCharacterOfTagName, iterator);
                         state = State::BogusComment
                     } else {
                         // Add to tag name.
@@ -334,56 +477,71 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                     }
                 }
                 None => {
-                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
+                    if token_store.empty() {
+                        token_store.error(ErrorType::EofBeforeTagName, iterator);
+                        // Emit the `</` as text.
+                        token_store.push_str("</");
+                        token_store.set_end_of_input(iterator);
+                        token_store.emit(Event::Text, iterator);
+                    } else {
+                        // The unfinished tag is dropped.
+                        token_store.error(ErrorType::EofInTag, iterator);
+                        token_store.clear();
+                    }
+                    break;
                 }
             },
             // A comment has been opened with <!--
             State::Comment => {
-                let mut is_closed = false;
-                loop {
-                    match iterator.next() {
-                        // Attempt to close, First -
-                        Some('-') => match iterator.next() {
-                            // Second -
-                            Some('-') => match iterator.next() {
-                                // Hurray. Comment closed.
-                                Some('>') => {
-                                    is_closed = true;
-                                    break;
-                                }
-                                // Any character, not a >
-                                Some(ch) => {
-                                    token_store.push_str("--");
-                                    token_store.push(ch);
-                                }
-                                None => {
-                                    token_store.push_str("--");
-                                    break;
+                comment(token_store, iterator);
+                state = State::Data;
+            }
+            // The content of a CDATA section is text, up to the first `]]>`.
+            State::CData => match iterator.next() {
+                Some(']') => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator);
+                    }
+                    let [line, column] = iterator.inner().locus();
+                    let mut brackets = 1;
+                    loop {
+                        match iterator.next() {
+                            Some(']') => brackets += 1,
+                            Some('>') if brackets >= 2 => {
+                                // Only the last two brackets end the section.
+                                (2..brackets).for_each(|_| token_store.push(']'));
+                                if !token_store.empty() {
+                                    token_store.set_end([line, column + brackets - 2]);
+                                    token_store.emit(Event::Text, iterator);
                                 }
-                            },
-                            // Any character, not a -
-                            Some(ch) => {
-                                token_store.push('-');
-                                token_store.push(ch);
+                                state = State::Data;
+                                break;
                             }
-                            // Comment unclosed. Retrieve - and break
-                            None => {
-                                token_store.push('-');
+                            next => {
+                                (0..brackets).for_each(|_| token_store.push(']'));
+                                if let Some(ch) = next {
+                                    iterator.push(ch);
+                                }
                                 break;
                             }
-                        },
-                        // Push any character to store.
-                        Some(ch) => token_store.push(ch),
-                        None => break,
+                        }
                     }
                 }
-
-                if !is_closed {
-                    token_store.error(ErrorType::UnclosedComment, iterator);
+                Some(ch) => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator);
+                    }
+                    token_store.push(ch);
                 }
-                token_store.emit(Event::Comment, iterator);
-                state = State::Data;
-            }
+                None => {
+                    token_store.error(ErrorType::EofInCdata, iterator);
+                    if !token_store.empty() {
+                        token_store.set_end_of_input(iterator);
+                        token_store.emit(Event::Text, iterator);
+                    }
+                    break;
+                }
+            },
             // Result of a parse error. Attempt to parse tag as comment.
             State::BogusComment => {
                 loop {
@@ -396,80 +554,731 @@ pub fn tokenize(token_store: &mut TokenStore, iterator: &mut QueueMatrix<Chars<'
                 token_store.emit(Event::Comment, iterator);
                 state = State::Data;
             }
-            State::Doctype => {
-                let mut _force_quirks = false;
+            // Parse the text content of raw text elements.
+            State::RawText | State::RcData => match iterator.next() {
+                Some('<') => {
+                    if raw_text_end_tag(token_store, iterator) {
+                        state = State::ClosingTag;
+                    }
+                }
+                Some('&') if matches!(state, State::RcData) => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator)
+                    }
+                    let text = character_reference(token_store, iterator, false);
+                    token_store.push_str(&text);
+                }
+                Some('\0') => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator)
+                    }
+                    token_store.error(ErrorType::InvalidCharacter, iterator);
+                    token_store.push('\u{fffd}');
+                }
+                Some(ch) => {
+                    if token_store.empty() {
+                        token_store.set_start(iterator)
+                    }
+                    token_store.push(ch)
+                }
+                None => {
+                    if !token_store.empty() {
+                        token_store.set_end_of_input(iterator);
+                        token_store.emit(Event::Text, iterator);
+                    }
+                    break;
+                }
+            },
+            // Parse the content of a script element.
+            State::ScriptData => {
+                let mut escape = Escape::None;
+                // Number of consecutive dashes, for finding the end of an escape.
+                let mut dashes = 0;
                 let mut ended = false;
-                let mut r#type = None;
-
-                // Expect a whitespace character.
-                match iterator.next() {
-                    Some(ch) => {
-                        // Parse error. parse anyway.
-                        if ch.is_whitespace() {
-                            // One whitespace found, Ignore rest.
-                            iterator.next_while(|ch| ch.is_whitespace());
-                        } else {
-                            iterator.push(ch);
-                            token_store.error(ErrorType::UnexpectedCharacter(ch), iterator);
+                loop {
+                    match iterator.next() {
+                        Some('<') => {
+                            dashes = 0;
+                            let tag_start = iterator.inner().locus();
+                            match iterator.next() {
+                                // Possible end tag.
+                                Some('/') if escape != Escape::DoubleEscaped => {
+                                    iterator.push('/');
+                                    if raw_text_end_tag_at(token_store, iterator, tag_start) {
+                                        state = State::ClosingTag;
+                                        break;
+                                    }
+                                }
+                                // A nested </script> ends the double escape.
+                                Some('/') => {
+                                    push_text_at(token_store, '<', tag_start);
+                                    token_store.push('/');
+                                    if script_tag_follows(token_store, iterator) {
+                                        escape = Escape::Escaped;
+                                    }
+                                }
+                                // <!-- starts an escape.
+                                Some('!') if escape == Escape::None => {
+                                    push_text_at(token_store, '<', tag_start);
+                                    token_store.push('!');
+                                    if let Some(ch) = iterator.next() {
+                                        if ch == '-' {
+                                            token_store.push(ch);
+                                            match iterator.next() {
+                                                Some('-') => {
+                                                    token_store.push('-');
+                                                    escape = Escape::Escaped;
+                                                    dashes = 2;
+                                                }
+                                                Some(ch) => iterator.push(ch),
+                                                None => {}
+                                            }
+                                        } else {
+                                            iterator.push(ch);
+                                        }
+                                    }
+                                }
+                                // A nested <script> inside an escape starts a double escape.
+                                Some(ch)
+                                    if ch.is_ascii_alphabetic() && escape == Escape::Escaped =>
+                                {
+                                    push_text_at(token_store, '<', tag_start);
+                                    iterator.push(ch);
+                                    if script_tag_follows(token_store, iterator) {
+                                        escape = Escape::DoubleEscaped;
+                                    }
+                                }
+                                Some(ch) => {
+                                    push_text_at(token_store, '<', tag_start);
+                                    iterator.push(ch);
+                                }
+                                None => push_text_at(token_store, '<', tag_start),
+                            }
+                        }
+                        Some('-') => {
+                            if token_store.empty() {
+                                token_store.set_start(iterator)
+                            }
+                            token_store.push('-');
+                            dashes += 1;
+                        }
+                        Some('>') => {
+                            // --> ends any escape.
+                            if dashes >= 2 {
+                                escape = Escape::None;
+                            }
+                            dashes = 0;
+                            if token_store.empty() {
+                                token_store.set_start(iterator)
+                            }
+                            token_store.push('>');
+                        }
+                        Some(ch) => {
+                            dashes = 0;
+                            if token_store.empty() {
+                                token_store.set_start(iterator)
+                            }
+                            if ch == '\0' {
+                                token_store.error(ErrorType::InvalidCharacter, iterator);
+                                token_store.push('\u{fffd}');
+                            } else {
+                                token_store.push(ch);
+                            }
+                        }
+                        None => {
+                            if escape != Escape::None {
+                                token_store
+                                    .error(ErrorType::EofInScriptHtmlCommentLikeText, iterator);
+                            }
+                            if !token_store.empty() {
+                                token_store.set_end_of_input(iterator);
+                                token_store.emit(Event::Text, iterator);
+                            }
+                            ended = true;
+                            break;
                         }
                     }
-                    None => {
-                        ended = true;
-                    }
                 }
+                if ended {
+                    break;
+                }
+            }
+            State::Doctype => {
+                let event = doctype(token_store, iterator);
+                token_store.emit(event, iterator);
+                state = State::Data;
+            }
+        }
+    }
 
-                let name: String = iterator.collect_until(|ch| ch.is_whitespace() || ch == &'>');
+    token_store.take_input_errors();
+    token_store.tokens.push(HtmlToken::EOF {
+        location: iterator.inner().locus(),
+    });
+}
 
-                // Skip whitespace.
-                iterator.next_while(|ch| ch.is_whitespace());
+/// Consume a comment after its `<!--`, up to and including the `-->` that ends it, and emit it.
+/// A comment that already has content, like one that was opened incorrectly, continues from its text.
+fn comment(token_store: &mut TokenStore, iterator: &mut QueueMatrix<impl Iterator<Item = char>>) {
+    use CommentState::*;
 
+    let mut state = match token_store.empty() {
+        true => Start,
+        false => Text,
+    };
+    loop {
+        let Some(ch) = iterator.next() else {
+            token_store.error(ErrorType::UnclosedComment, iterator);
+            break;
+        };
+        state = match (state, ch) {
+            // `<!-->` and `<!--->` end an empty comment.
+            (Start | StartDash, '>') => {
+                token_store.error(ErrorType::AbruptClosingOfEmptyComment, iterator);
+                break;
+            }
+            (Start, '-') => StartDash,
+            (StartDash, '-') => End,
+            (StartDash, ch) => {
+                token_store.push('-');
+                iterator.push(ch);
+                Text
+            }
+            (Text | LessThan, '<') => {
+                token_store.push('<');
+                LessThan
+            }
+            (LessThan, '!') => {
+                token_store.push('!');
+                LessThanBang
+            }
+            (LessThanBang, '-') => LessThanBangDash,
+            (LessThanBangDash, '-') => LessThanBangDashDash,
+            // A `<!--` inside the comment, unless it is the end of the comment as in `<!--<!-->`.
+            (LessThanBangDashDash, ch) => {
+                if ch != '>' {
+                    token_store.error(ErrorType::NestedComment, iterator);
+                }
+                iterator.push(ch);
+                End
+            }
+            (LessThanBangDash, ch) => {
+                iterator.push(ch);
+                EndDash
+            }
+            (Text, '-') => EndDash,
+            (Text, '\0') => {
+                token_store.error(ErrorType::InvalidCharacter, iterator);
+                token_store.push('\u{fffd}');
+                Text
+            }
+            (Text, ch) => {
+                token_store.push(ch);
+                Text
+            }
+            (EndDash, '-') => End,
+            (EndDash, ch) => {
+                token_store.push('-');
+                iterator.push(ch);
+                Text
+            }
+            (End, '>') => break,
+            (End, '!') => EndBang,
+            (End, '-') => {
+                token_store.push('-');
+                End
+            }
+            (End, ch) => {
+                token_store.push_str("--");
+                iterator.push(ch);
+                Text
+            }
+            (EndBang, '-') => {
+                token_store.push_str("--!");
+                EndDash
+            }
+            (EndBang, '>') => {
+                token_store.error(ErrorType::IncorrectlyClosedComment, iterator);
+                break;
+            }
+            (EndBang, ch) => {
+                token_store.push_str("--!");
+                iterator.push(ch);
+                Text
+            }
+            // Anything else is read again as text.
+            (Start | LessThan | LessThanBang, ch) => {
+                iterator.push(ch);
+                Text
+            }
+        };
+    }
+    token_store.emit(Event::Comment, iterator);
+}
+
+/// Consume a doctype after its `<!DOCTYPE`, up to and including its `>`.
+fn doctype(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+) -> Event {
+    let mut name = String::new();
+    let mut r#type = None;
+    let mut public_identifier = None;
+    let mut system_identifier = None;
+
+    let force_quirks = 'doctype: {
+        match iterator.next() {
+            Some(ch) if ch.is_whitespace() => iterator.next_while(|ch| ch.is_whitespace()),
+            Some(ch) => {
+                if ch != '>' {
+                    token_store.error(ErrorType::MissingWhitespaceBeforeDoctypeName, iterator);
+                }
+                iterator.push(ch);
+            }
+            None => {}
+        }
+        loop {
+            match iterator.next() {
+                Some(ch) if ch.is_whitespace() => break,
+                Some('>') => {
+                    if name.is_empty() {
+                        token_store.error(ErrorType::MissingDoctypeName, iterator);
+                    }
+                    break 'doctype name.is_empty();
+                }
+                Some('\0') => {
+                    token_store.error(ErrorType::InvalidCharacter, iterator);
+                    name.push('\u{fffd}');
+                }
+                Some(ch) => name.push(ch.to_ascii_lowercase()),
+                None => {
+                    token_store.error(ErrorType::EofInDoctype, iterator);
+                    break 'doctype true;
+                }
+            }
+        }
+
+        // A PUBLIC or SYSTEM keyword can follow the name.
+        iterator.next_while(|ch| ch.is_whitespace());
+        let keyword: String = match iterator.next() {
+            Some('>') => break 'doctype false,
+            Some(ch) => {
+                let rest: String = iterator.collect_next(5);
+                format!("{ch}{rest}")
+            }
+            None => {
+                token_store.error(ErrorType::EofInDoctype, iterator);
+                break 'doctype true;
+            }
+        };
+        let public = match keyword.to_ascii_lowercase().as_str() {
+            "public" => true,
+            "system" => false,
+            _ => {
+                // Read the rest again, in case the doctype ends inside it.
+                keyword.chars().skip(1).for_each(|ch| iterator.push(ch));
+                token_store.error(ErrorType::IndecipherableDocType, iterator);
+                iterator.find(|ch| ch == &'>');
+                break 'doctype true;
+            }
+        };
+        r#type = Some(match public {
+            true => DocTypeIdentifier::Public,
+            false => DocTypeIdentifier::System,
+        });
+        let Some(quote) = identifier_quote(token_store, iterator, public) else {
+            break 'doctype true;
+        };
+
+        let quote = if public {
+            if doctype_identifier(token_store, iterator, quote, true, &mut public_identifier) {
+                break 'doctype true;
+            }
+            // A system identifier can follow the public one.
+            let mut whitespace = false;
+            loop {
                 match iterator.next() {
-                    Some('>') => {
-                        _force_quirks = true;
-                        token_store.error(ErrorType::UnexpectedCharacter('>'), iterator);
-                    }
-                    Some('\0') => {
-                        token_store.error(ErrorType::InvalidCharacter, iterator);
-                        token_store.push('\u{fffd}');
-                    }
-                    // Try to parse identifier.
-                    Some(ch @ ('p' | 'P' | 's' | 'S')) => {
-                        let mut identifier_string: String = iterator.collect_next(5);
-                        identifier_string.insert(0, ch);
-                        if identifier_string.to_ascii_lowercase() == "system" {
-                            r#type = Some(DocTypeIdentifier::System);
-                        } else if identifier_string.to_ascii_lowercase() == "public" {
-                            r#type = Some(DocTypeIdentifier::Public);
-                        } else {
-                            token_store.error(ErrorType::IndecipherableDocType, iterator);
-                            iterator.find(|ch| ch == &'>');
+                    Some(ch) if ch.is_whitespace() => whitespace = true,
+                    Some('>') => break 'doctype false,
+                    Some(quote @ ('"' | '\'')) => {
+                        if !whitespace {
+                            token_store.error(
+                                ErrorType::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+                                iterator,
+                            );
                         }
+                        break quote;
                     }
                     Some(_) => {
-                        token_store.error(ErrorType::IndecipherableDocType, iterator);
+                        token_store.error(
+                            ErrorType::MissingQuoteBeforeDoctypeSystemIdentifier,
+                            iterator,
+                        );
                         iterator.find(|ch| ch == &'>');
+                        break 'doctype true;
+                    }
+                    None => {
+                        token_store.error(ErrorType::EofInDoctype, iterator);
+                        break 'doctype true;
                     }
-                    None => ended = true,
                 }
-                if ended {
-                    _force_quirks = true;
-                    token_store.error(ErrorType::UnexpectedEndOfInput, iterator);
-                    break;
+            }
+        } else {
+            quote
+        };
+        if doctype_identifier(token_store, iterator, quote, false, &mut system_identifier) {
+            break 'doctype true;
+        }
+        iterator.next_while(|ch| ch.is_whitespace());
+        match iterator.next() {
+            Some('>') => false,
+            // Anything else is ignored, without forcing quirks.
+            Some(_) => {
+                token_store.error(
+                    ErrorType::UnexpectedCharacterAfterDoctypeSystemIdentifier,
+                    iterator,
+                );
+                iterator.find(|ch| ch == &'>');
+                false
+            }
+            None => {
+                token_store.error(ErrorType::EofInDoctype, iterator);
+                true
+            }
+        }
+    };
+
+    Event::DocType {
+        name,
+        r#type,
+        public_identifier,
+        system_identifier,
+        force_quirks,
+    }
+}
+
+/// Find the quote that opens an identifier after its PUBLIC or SYSTEM keyword.
+/// Returns `None` if the doctype ended, or has something else than an identifier.
+fn identifier_quote(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+    public: bool,
+) -> Option<char> {
+    let (missing_whitespace, missing_identifier, missing_quote) = match public {
+        true => (
+            ErrorType::MissingWhitespaceAfterDoctypePublicKeyword,
+            ErrorType::MissingDoctypePublicIdentifier,
+            ErrorType::MissingQuoteBeforeDoctypePublicIdentifier,
+        ),
+        false => (
+            ErrorType::MissingWhitespaceAfterDoctypeSystemKeyword,
+            ErrorType::MissingDoctypeSystemIdentifier,
+            ErrorType::MissingQuoteBeforeDoctypeSystemIdentifier,
+        ),
+    };
+    let mut whitespace = false;
+    loop {
+        match iterator.next() {
+            Some(ch) if ch.is_whitespace() => whitespace = true,
+            Some(quote @ ('"' | '\'')) => {
+                if !whitespace {
+                    token_store.error(missing_whitespace, iterator);
                 }
-                token_store.emit(
-                    Event::DocType {
-                        name,
-                        r#type,
-                        force_quirks: _force_quirks,
+                return Some(quote);
+            }
+            Some('>') => {
+                token_store.error(missing_identifier, iterator);
+                return None;
+            }
+            Some(_) => {
+                token_store.error(missing_quote, iterator);
+                iterator.find(|ch| ch == &'>');
+                return None;
+            }
+            None => {
+                token_store.error(ErrorType::EofInDoctype, iterator);
+                return None;
+            }
+        }
+    }
+}
+
+/// Read a doctype identifier up to its closing quote.
+/// Returns true if the doctype ended before the quote, which forces quirks mode.
+fn doctype_identifier(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+    quote: char,
+    public: bool,
+    value: &mut Option<String>,
+) -> bool {
+    let mut identifier = String::new();
+    let ended = loop {
+        match iterator.next() {
+            Some(ch) if ch == quote => break false,
+            Some('>') => {
+                token_store.error(
+                    match public {
+                        true => ErrorType::AbruptDoctypePublicIdentifier,
+                        false => ErrorType::AbruptDoctypeSystemIdentifier,
                     },
                     iterator,
                 );
-                state = State::Data;
+                break true;
+            }
+            Some('\0') => {
+                token_store.error(ErrorType::InvalidCharacter, iterator);
+                identifier.push('\u{fffd}');
+            }
+            Some(ch) => identifier.push(ch),
+            None => {
+                token_store.error(ErrorType::EofInDoctype, iterator);
+                break true;
+            }
+        }
+    };
+    *value = Some(identifier);
+    ended
+}
+
+/// Consume a character reference after a `&`, and return the text it stands for.
+/// If the characters do not make up a valid reference, they are returned as they are.
+fn character_reference(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+    in_attribute: bool,
+) -> String {
+    // Errors point at the `&` that starts the reference.
+    let (reference, offset) = token_store.last_read(iterator);
+    match iterator.next() {
+        Some('#') => numeric_character_reference(token_store, iterator, (reference, offset)),
+        Some(ch) if ch.is_ascii_alphanumeric() => {
+            // Collect the longest run that could be a name, then find the longest name that matches it.
+            let mut name = String::from(ch);
+            let mut next = iterator.next();
+            while let Some(ch) = next {
+                if name.len() == entities::LONGEST_NAME || !ch.is_ascii_alphanumeric() {
+                    break;
+                }
+                name.push(ch);
+                next = iterator.next();
+            }
+            if next == Some(';') {
+                name.push(';');
+                next = iterator.next();
+            }
+            let mut matched = None;
+            for length in (1..=name.len()).rev() {
+                if let Some(value) = entities::lookup(&name[..length]) {
+                    matched = Some((length, value));
+                    break;
+                }
+            }
+            match matched {
+                Some((length, value)) => {
+                    // Return the unused characters to the iterator.
+                    let rest = &name[length..];
+                    rest.chars().for_each(|ch| iterator.push(ch));
+                    if let Some(ch) = next {
+                        iterator.push(ch);
+                    }
+                    if name[..length].ends_with(';') {
+                        return value.to_string();
+                    }
+                    // Legacy references in attributes are left alone if they run into other text, e.g. ?a=1&copy=2
+                    let following = rest.chars().next().or(next);
+                    if in_attribute
+                        && following.is_some_and(|ch| ch == '=' || ch.is_ascii_alphanumeric())
+                    {
+                        let mut text = String::from('&');
+                        text.push_str(&name[..length]);
+                        return text;
+                    }
+                    token_store.error_at(
+                        ErrorType::MissingSemicolonAfterCharacterReference,
+                        reference,
+                        offset,
+                    );
+                    value.to_string()
+                }
+                None => {
+                    if let Some(ch) = next {
+                        iterator.push(ch);
+                    }
+                    if name.ends_with(';') {
+                        token_store.error_at(
+                            ErrorType::UnknownNamedCharacterReference,
+                            reference,
+                            offset,
+                        );
+                    }
+                    let mut text = String::from('&');
+                    text.push_str(&name);
+                    text
+                }
             }
         }
+        // Not a character reference.
+        Some(ch) => {
+            iterator.push(ch);
+            String::from('&')
+        }
+        None => String::from('&'),
     }
+}
 
-    token_store.tokens.push(HtmlToken::EOF {
-        location: iterator.inner().locus(),
+/// Consume a decimal or hexadecimal character reference after a `&#`.
+fn numeric_character_reference(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+    (reference, offset): ([usize; 2], usize),
+) -> String {
+    let mut prefix = String::from("&#");
+    let mut radix = 10;
+    match iterator.next() {
+        Some(ch @ ('x' | 'X')) => {
+            prefix.push(ch);
+            radix = 16;
+        }
+        Some(ch) => iterator.push(ch),
+        None => {}
+    }
+    let digits: String = iterator.collect_until(|ch| !ch.is_digit(radix));
+    if digits.is_empty() {
+        token_store.error_at(
+            ErrorType::AbsenceOfDigitsInNumericCharacterReference,
+            reference,
+            offset,
+        );
+        return prefix;
+    }
+    match iterator.next() {
+        Some(';') => {}
+        Some(ch) => {
+            iterator.push(ch);
+            token_store.error_at(
+                ErrorType::MissingSemicolonAfterCharacterReference,
+                reference,
+                offset,
+            );
+        }
+        None => token_store.error_at(
+            ErrorType::MissingSemicolonAfterCharacterReference,
+            reference,
+            offset,
+        ),
+    }
+    // Saturate above the unicode range, so that long inputs cannot overflow.
+    let code = digits.chars().fold(0u32, |code, ch| {
+        (code * radix + ch.to_digit(radix).unwrap()).min(0x110000)
     });
+    let error = match code {
+        0 => Some(ErrorType::NullCharacterReference),
+        0x110000.. => Some(ErrorType::CharacterReferenceOutsideUnicodeRange),
+        0xD800..=0xDFFF => Some(ErrorType::SurrogateCharacterReference),
+        0xFDD0..=0xFDEF => Some(ErrorType::NoncharacterCharacterReference),
+        _ if code & 0xFFFE == 0xFFFE => Some(ErrorType::NoncharacterCharacterReference),
+        0x0D | 0x80..=0x9F => Some(ErrorType::ControlCharacterReference),
+        0x01..=0x1F | 0x7F if !matches!(code, 0x09 | 0x0A | 0x0C) => {
+            Some(ErrorType::ControlCharacterReference)
+        }
+        _ => None,
+    };
+    if let Some(error) = error {
+        token_store.error_at(error, reference, offset);
+    }
+    let ch = match code {
+        0 | 0xD800..=0xDFFF | 0x110000.. => '\u{fffd}',
+        // References to C1 controls are treated as windows-1252 bytes.
+        0x80..=0x9F => WINDOWS_1252_C1[(code - 0x80) as usize],
+        _ => char::from_u32(code).unwrap_or('\u{fffd}'),
+    };
+    String::from(ch)
+}
+
+/// The windows-1252 characters for bytes 0x80 to 0x9F. Unmapped bytes stay as C1 controls.
+const WINDOWS_1252_C1: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Push a character into the text that was read at a location before the current one.
+fn push_text_at(token_store: &mut TokenStore, ch: char, location: [usize; 2]) {
+    if token_store.empty() {
+        token_store.set_start_at(location);
+    }
+    token_store.push(ch);
+}
+
+/// Checks if the `<` just read in raw text starts the end tag of the raw text element.
+/// If it does, the text before it is emitted and the store is left holding the tag name.
+/// Otherwise everything read is added to the text.
+fn raw_text_end_tag(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+) -> bool {
+    let tag_start = iterator.inner().locus();
+    raw_text_end_tag_at(token_store, iterator, tag_start)
+}
+
+fn raw_text_end_tag_at(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+    tag_start: [usize; 2],
+) -> bool {
+    match iterator.next() {
+        Some('/') => {}
+        Some(ch) => {
+            push_text_at(token_store, '<', tag_start);
+            iterator.push(ch);
+            return false;
+        }
+        None => {
+            push_text_at(token_store, '<', tag_start);
+            return false;
+        }
+    }
+    let name: String = iterator.collect_until(|ch| !ch.is_ascii_alphanumeric());
+    match iterator.next() {
+        Some(ch)
+            if token_store.is_appropriate_end_tag(&name)
+                && (ch.is_whitespace() || ch == '/' || ch == '>') =>
+        {
+            iterator.push(ch);
+            if !token_store.empty() {
+                token_store.set_end(tag_start);
+                token_store.emit(Event::Text, iterator);
+            }
+            token_store.set_start_at(tag_start);
+            token_store.push_str(&name.to_ascii_lowercase());
+            true
+        }
+        next => {
+            push_text_at(token_store, '<', tag_start);
+            token_store.push('/');
+            token_store.push_str(&name);
+            if let Some(ch) = next {
+                iterator.push(ch);
+            }
+            false
+        }
+    }
+}
+
+/// Collects a tag name in script data as text, and checks if it is a complete `script` tag name.
+fn script_tag_follows(
+    token_store: &mut TokenStore,
+    iterator: &mut QueueMatrix<impl Iterator<Item = char>>,
+) -> bool {
+    let name: String = iterator.collect_until(|ch| !ch.is_ascii_alphabetic());
+    token_store.push_str(&name);
+    match iterator.next() {
+        Some(ch) => {
+            iterator.push(ch);
+            name.eq_ignore_ascii_case("script") && (ch.is_whitespace() || ch == '/' || ch == '>')
+        }
+        None => false,
+    }
 }
@@ -1,15 +1,20 @@
-use std::{mem::take, str::Chars};
+use std::{cell::RefCell, mem::take, rc::Rc};
 
 use sleek_ast::{
     AttributeQuoteType as QuoteType, DocTypeIdentifier, HtmlAttribute, HtmlTag, HtmlToken, Span,
 };
-use sleek_utils::{HigherOrderIterator, MatrixIterator, QueueIterator, QueueMatrix};
+use sleek_utils::{HigherOrderIterator, LineIndex, MutableCountRef, QueueMatrix};
 
+use super::state::State;
 use crate::{
-    html::{error::HtmlParseErrorType as ErrorType, parser::ParserResponse},
-    HtmlParseError,
+    html::{error::HtmlParseErrorType as ErrorType, parser::ParserResponse, Whitespace},
+    HtmlParseError, ParseOptions,
 };
 
+/// The lines kept in the line index while tokenizing.
+/// The tokenizer reads a few characters ahead of where it is, and each could be a line break.
+const KEPT_LINES: usize = 64;
+
 pub enum Event {
     Text,
     Close,
@@ -18,6 +23,8 @@ pub enum Event {
     DocType {
         name: String,
         r#type: Option<DocTypeIdentifier>,
+        public_identifier: Option<String>,
+        system_identifier: Option<String>,
         force_quirks: bool,
     },
 }
@@ -29,6 +36,34 @@ pub struct TokenStore {
     attrib_store: Vec<HtmlAttribute>,
     pub cache: (String, String, Option<String>),
     loc: [usize; 2],
+    /// The byte offset of `loc`, found while its line is in the line index.
+    loc_offset: usize,
+    end: Option<[usize; 2]>,
+    /// The start and end of the attribute name being collected.
+    attribute_name: Span,
+    /// The start and end of the attribute value being collected, including its quotes.
+    attribute_value: Option<Span>,
+    /// The name of the last opening tag emitted, used to find the end of raw text.
+    last_start_tag: String,
+    /// The state requested by the parser after the last emitted token.
+    switch: Option<State>,
+    /// Whether tokenizing pauses once a token or an error is found, so tokens can be read one at a time.
+    pause: bool,
+    /// The state to continue in after a pause.
+    paused: Option<State>,
+    keep_whitespace: bool,
+    max_errors: Option<usize>,
+    /// The number of errors found while parsing, shared with the tree builder.
+    error_count: MutableCountRef<usize>,
+    stop_after: Option<usize>,
+    /// The lines of the input read so far, for finding the byte offsets of locations.
+    line_index: MutableCountRef<LineIndex>,
+    /// Whether the input is indexed, which is only needed when positions are kept.
+    index_lines: bool,
+    /// Errors for characters of the input that the standard does not allow, found by the [`InputReader`].
+    input_errors: MutableCountRef<Vec<HtmlParseError>>,
+    /// Whether the tree builder is inside an SVG or MathML element, where CDATA sections are allowed.
+    foreign_content: MutableCountRef<bool>,
     listener: Option<Box<dyn Fn(HtmlToken) -> ParserResponse>>,
 }
 
@@ -41,12 +76,43 @@ impl TokenStore {
         self.cache.0.push(ch);
     }
     pub fn push_str(&mut self, st: &str) {
+        if !st.is_empty() {
+            self.has_data = true
+        };
         self.cache.0.push_str(st);
     }
-    /// Push a character into an attribute name.
-    pub fn push_attr_name(&mut self, ch: char) {
+    /// Push a character into an attribute name, and mark where the name starts and ends.
+    pub fn push_attr_name(&mut self, ch: char, iterator: &QueueMatrix<impl Iterator<Item = char>>) {
+        // The iterator is past the character, which is never a line break.
+        let [line, column] = iterator.inner().locus();
+        if self.cache.1.is_empty() {
+            self.attribute_name = self.span([line, column - 1], [line, column]);
+        } else {
+            self.attribute_name.end = [line, column];
+            self.attribute_name.end_offset = self.offset([line, column]);
+        }
         self.cache.1.push(ch);
     }
+    /// Mark the first character of an attribute value, which was just read.
+    pub fn start_attr_value(&mut self, iterator: &QueueMatrix<impl Iterator<Item = char>>) {
+        let start = self.before(iterator.inner().locus());
+        self.attribute_value = Some(self.span(start, start));
+    }
+    /// Mark the end of an attribute value at the character just read.
+    /// A closing quote is part of the value, the character after an unquoted value is not.
+    pub fn end_attr_value(
+        &mut self,
+        quoted: bool,
+        iterator: &QueueMatrix<impl Iterator<Item = char>>,
+    ) {
+        let locus = iterator.inner().locus();
+        let end = if quoted { locus } else { self.before(locus) };
+        let end_offset = self.offset(end);
+        if let Some(value) = &mut self.attribute_value {
+            value.end = end;
+            value.end_offset = end_offset;
+        }
+    }
     /// Push a character into the attribute value, if it exists, or create a new attribute value to push into if it doesn't
     pub fn push_attr_value(&mut self, ch: char) {
         match &mut self.cache.2 {
@@ -58,11 +124,29 @@ impl TokenStore {
             }
         }
     }
+    /// Add the attribute in the cache to the tag. If the tag already has an attribute with the name,
+    /// the first one is kept, as browsers do, and the duplicate is reported at its name.
     pub fn collect_attribute(&mut self, quote_type: QuoteType) {
+        let key = take(&mut self.cache.1);
+        let value = self.cache.2.take();
+        let value_span = self.attribute_value.take();
+        if self
+            .attrib_store
+            .iter()
+            .any(|attribute| attribute.key == key)
+        {
+            let name = &self.attribute_name;
+            self.error_at(ErrorType::DuplicateAttribute, name.start, name.start_offset);
+            return;
+        }
+        // An empty unquoted value, like in `<a href=>`, is no value.
+        let value_span = value_span.filter(|_| value.is_some() || quote_type != QuoteType::None);
         self.attrib_store.push(HtmlAttribute {
-            key: take(&mut self.cache.1),
-            value: self.cache.2.take(),
+            key,
+            value,
             quote_type,
+            name_span: self.attribute_name.clone(),
+            value_span,
         })
     }
     /// Set a listener for emission events.
@@ -70,22 +154,28 @@ impl TokenStore {
         self.listener = Some(f);
     }
     /// Push a token to the token list.
-    pub fn emit(&mut self, event: Event, iterator: &mut QueueMatrix<Chars<'_>>) {
+    pub fn emit(&mut self, event: Event, iterator: &mut QueueMatrix<impl Iterator<Item = char>>) {
         let content = take(&mut self.cache.0);
         self.has_data = false;
-        let mut span = Span::over(self.loc, iterator.inner().locus());
+        let end = self.end.take().unwrap_or_else(|| iterator.inner().locus());
+        let mut span = Span::over(self.loc, end).with_offsets(self.loc_offset, self.offset(end));
 
         let token = match event {
             Event::Text => {
-                // Ignore empty text nodes.
-                if content.find(|ch: char| !ch.is_whitespace()).is_none() {
+                // Ignore empty text nodes. Only ascii whitespace counts, so decoded references like &nbsp; are kept.
+                if content.is_empty()
+                    || (!self.keep_whitespace
+                        && content.find(|ch: char| !ch.is_ascii_whitespace()).is_none())
+                {
                     return;
                 }
                 span.end[1] -= 1;
+                span.end_offset = self.offset(span.end);
                 HtmlToken::Text { content, span }
             }
             Event::OpenerTag(self_closing) => {
                 let attributes = take(&mut self.attrib_store);
+                self.last_start_tag = content.clone();
                 HtmlToken::OpeningTag {
                     name: HtmlTag::new(content),
                     attributes,
@@ -101,39 +191,193 @@ impl TokenStore {
             Event::DocType {
                 name,
                 r#type,
+                public_identifier,
+                system_identifier,
                 force_quirks,
             } => HtmlToken::DocType {
                 name,
                 r#type,
+                public_identifier,
+                system_identifier,
                 force_quirks,
+                span,
             },
         };
 
-        match &self.listener {
-            Some(listener) => match listener(token) {
-                ParserResponse::SwitchToStyleSheet => todo!(),
-                ParserResponse::SwitchToScript => todo!(),
-                ParserResponse::Continue => {}
-            },
-            None => self.tokens.push(token),
+        let response = match &self.listener {
+            Some(listener) => listener(token),
+            None => {
+                // Without a parser to respond, switch states based on the tag alone.
+                let response = match &token {
+                    HtmlToken::OpeningTag { name, .. } => ParserResponse::from(name),
+                    _ => ParserResponse::Continue,
+                };
+                self.tokens.push(token);
+                response
+            }
+        };
+        self.respond(response);
+    }
+    /// Switch states for the content after the last token, as requested by the parser.
+    fn respond(&mut self, response: ParserResponse) {
+        self.switch = match response {
+            ParserResponse::SwitchToStyleSheet | ParserResponse::SwitchToRawText => {
+                Some(State::RawText)
+            }
+            ParserResponse::SwitchToScript => Some(State::ScriptData),
+            ParserResponse::SwitchToRcData => Some(State::RcData),
+            ParserResponse::Continue => None,
+        };
+    }
+    /// Apply the options that affect tokenizing.
+    pub fn configure(&mut self, options: &ParseOptions) {
+        self.keep_whitespace = options.whitespace == Whitespace::Preserve;
+        self.max_errors = options.max_errors;
+        self.stop_after = options.stop_after();
+        self.index_lines = options.keep_positions || options.lossless;
+        if let Some(context) = &options.context {
+            self.start_in(context);
+            // With scripting, the content of <noscript> is not parsed.
+            if context == &HtmlTag::Noscript && options.scripting {
+                self.respond(ParserResponse::SwitchToRawText);
+            }
+        }
+    }
+    /// Start tokenizing as if inside an element, for fragments whose context is an element like `<textarea>`.
+    fn start_in(&mut self, context: &HtmlTag) {
+        self.last_start_tag = context.to_string();
+        self.respond(ParserResponse::from(context));
+    }
+    /// Returns a handle to the number of errors found, so the tree builder can add its own.
+    pub fn error_count(&self) -> MutableCountRef<usize> {
+        self.error_count.clone()
+    }
+    /// Returns a handle to the index of the input lines.
+    pub fn line_index(&self) -> MutableCountRef<LineIndex> {
+        self.line_index.clone()
+    }
+    /// Returns a reader that every character of the input must be passed to before it is tokenized.
+    pub fn reader(&self) -> InputReader {
+        InputReader {
+            line_index: self.index_lines.then(|| self.line_index.clone()),
+            errors: self.input_errors.clone(),
+            location: [1, 1],
+            offset: 0,
+        }
+    }
+    /// Adds the errors the reader found in the input read so far.
+    pub fn take_input_errors(&mut self) {
+        let errors = take(&mut *self.input_errors.borrow_mut());
+        for error in errors {
+            self.error_at(error.error_type, error.location, error.offset);
         }
     }
-    /// Adds an error.
+    /// Returns a handle to whether the tree builder is in foreign content, which the tree builder updates after every token.
+    pub fn foreign_content(&self) -> MutableCountRef<bool> {
+        self.foreign_content.clone()
+    }
+    /// Checks if the tree builder is inside an SVG or MathML element.
+    pub fn in_foreign_content(&self) -> bool {
+        *self.foreign_content.borrow()
+    }
+    /// The byte offset of a location. Locations before the start of a line are treated as the start of the line.
+    pub fn offset(&self, [line, column]: [usize; 2]) -> usize {
+        self.line_index
+            .borrow()
+            .offset([line, column.max(1)])
+            .unwrap_or_default()
+    }
+    /// Checks if enough errors were found that strict parsing should stop.
+    pub fn stopped(&self) -> bool {
+        self.stop_after
+            .is_some_and(|max| *self.error_count.borrow() >= max)
+    }
+    /// Pause tokenizing whenever tokens or errors are found, until they are taken from the store.
+    pub fn pause_on_output(&mut self) {
+        self.pause = true;
+    }
+    /// Checks if there are tokens or errors to take before tokenizing continues.
+    pub fn pausing(&self) -> bool {
+        self.pause && (!self.tokens.is_empty() || !self.errors.is_empty())
+    }
+    /// Keep the state to continue in for the next call to [`tokenize`](super::tokenize).
+    pub fn pause_in(&mut self, state: State) {
+        self.paused = Some(state);
+    }
+    /// Returns the state the tokenizer should continue in after emitting a tag, or after a pause.
+    pub fn next_state(&mut self) -> State {
+        self.paused
+            .take()
+            .or_else(|| self.switch.take())
+            .unwrap_or(State::Data)
+    }
+    /// Checks if a tag name closes the raw text started by the last opening tag.
+    pub fn is_appropriate_end_tag(&self, name: &str) -> bool {
+        !self.last_start_tag.is_empty() && name.eq_ignore_ascii_case(&self.last_start_tag)
+    }
+    /// Adds an error at the character read last.
     pub fn error(
         &mut self,
         error_type: ErrorType,
-        iterator: &QueueIterator<MatrixIterator<Chars<'_>>>,
+        iterator: &QueueMatrix<impl Iterator<Item = char>>,
     ) {
-        let location = iterator.inner().locus();
+        let (location, offset) = self.last_read(iterator);
+        self.error_at(error_type, location, offset);
+    }
+    /// The location of the character read last, with its byte offset.
+    pub fn last_read(
+        &self,
+        iterator: &QueueMatrix<impl Iterator<Item = char>>,
+    ) -> ([usize; 2], usize) {
+        let location = self.before(iterator.inner().locus());
+        (location, self.offset(location))
+    }
+    /// Adds an error at a location that was read earlier.
+    pub fn error_at(&mut self, error_type: ErrorType, location: [usize; 2], offset: usize) {
+        *self.error_count.borrow_mut() += 1;
+        if self.max_errors.is_some_and(|max| self.errors.len() >= max) {
+            return;
+        }
         self.errors.push(HtmlParseError {
             error_type,
             location,
+            offset,
         });
     }
     /// Sets the position of the iterator to the start of something.
-    pub fn set_start(&mut self, iterator: &QueueIterator<MatrixIterator<Chars<'_>>>) {
-        self.loc = iterator.inner().locus();
-        self.loc[1] -= 1;
+    pub fn set_start(&mut self, iterator: &QueueMatrix<impl Iterator<Item = char>>) {
+        self.set_start_at(iterator.inner().locus());
+    }
+    /// Sets the start of something to the character before a location that was read earlier.
+    pub fn set_start_at(&mut self, location: [usize; 2]) {
+        self.loc = self.before(location);
+        self.loc_offset = self.offset(self.loc);
+    }
+    /// The location of the character before a location.
+    fn before(&self, location: [usize; 2]) -> [usize; 2] {
+        match location {
+            // The character was the line break at the end of the line before.
+            [line, 1] if line > 1 => self
+                .offset(location)
+                .checked_sub(1)
+                .and_then(|offset| self.line_index.borrow().position(offset))
+                .unwrap_or([line - 1, 0]),
+            [line, column] => [line, column - 1],
+        }
+    }
+    /// A span between two locations, with their byte offsets.
+    fn span(&self, start: [usize; 2], end: [usize; 2]) -> Span {
+        Span::over(start, end).with_offsets(self.offset(start), self.offset(end))
+    }
+    /// Marks where the cached data ends, for when the tokenizer has read past it before emitting.
+    pub fn set_end(&mut self, location: [usize; 2]) {
+        self.end = Some(location);
+    }
+    /// Marks the cached text as ending at the end of the input.
+    /// Text usually ends at a character that was read after it, which the end of the input is not.
+    pub fn set_end_of_input(&mut self, iterator: &QueueMatrix<impl Iterator<Item = char>>) {
+        let [line, column] = iterator.inner().locus();
+        self.end = Some([line, column + 1]);
     }
     /// Checks if the store contains data in its cache.
     pub fn empty(&self) -> bool {
@@ -144,6 +388,7 @@ impl TokenStore {
         self.cache.0.clear();
         self.cache.1.clear();
         self.cache.2 = None;
+        self.attribute_value = None;
     }
 }
 
@@ -156,8 +401,62 @@ impl TokenStore {
             attrib_store: vec![],
             has_data: false,
             loc: [0, 0],
+            loc_offset: 0,
+            end: None,
+            attribute_name: Span::over([0, 0], [0, 0]),
+            attribute_value: None,
+            last_start_tag: String::new(),
+            switch: None,
+            pause: false,
+            paused: None,
+            keep_whitespace: true,
+            max_errors: None,
+            error_count: Rc::new(RefCell::new(0)),
+            stop_after: None,
+            line_index: Rc::new(RefCell::new(LineIndex::streaming(KEPT_LINES))),
+            index_lines: true,
+            input_errors: Rc::new(RefCell::new(vec![])),
+            foreign_content: Rc::new(RefCell::new(false)),
             cache: (String::new(), String::new(), None),
             listener: None,
         }
     }
 }
+
+/// Reads the characters of the input before the tokenizer does,
+/// to index their lines and to find the characters the standard does not allow in the input, like controls.
+pub struct InputReader {
+    line_index: Option<MutableCountRef<LineIndex>>,
+    errors: MutableCountRef<Vec<HtmlParseError>>,
+    location: [usize; 2],
+    offset: usize,
+}
+
+impl InputReader {
+    /// Read the next character of the input.
+    pub fn read(&mut self, ch: char) {
+        if let Some(line_index) = &self.line_index {
+            line_index.borrow_mut().push(ch);
+        }
+        let error_type = match ch {
+            // Null characters are reported where they are tokenized.
+            '\0' | '\t' | '\n' | '\x0C' | '\r' => None,
+            '\x01'..='\x1F' | '\x7F'..='\u{9F}' => Some(ErrorType::ControlCharacterInInputStream),
+            '\u{FDD0}'..='\u{FDEF}' => Some(ErrorType::NoncharacterInInputStream),
+            _ if ch as u32 & 0xFFFE == 0xFFFE => Some(ErrorType::NoncharacterInInputStream),
+            _ => None,
+        };
+        if let Some(error_type) = error_type {
+            self.errors.borrow_mut().push(HtmlParseError {
+                error_type,
+                location: self.location,
+                offset: self.offset,
+            });
+        }
+        self.offset += ch.len_utf8();
+        match ch {
+            '\n' => self.location = [self.location[0] + 1, 1],
+            _ => self.location[1] += 1,
+        }
+    }
+}
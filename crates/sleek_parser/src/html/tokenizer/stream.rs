@@ -0,0 +1,92 @@
+use std::{collections::VecDeque, mem::take};
+
+use sleek_ast::HtmlToken;
+use sleek_utils::{MatrixIterator, QueueIterator, QueueMatrix};
+
+use super::{tokenize, TokenStore};
+use crate::html::{HtmlParseError, ParseOptions};
+
+/// Reads HTML one token at a time, without building a tree, e.g. for syntax highlighting.
+/// Errors are returned in the order of the source. An error inside a token, like a duplicate attribute, comes before the token.
+/// The last token is [`EOF`](HtmlToken::EOF).
+///
+/// Without a tree builder, the content of elements like `<script>` and `<title>` is read as text by their tag name alone,
+/// and CDATA sections are read as comments.
+/// # Examples
+/// ```
+/// use sleek_ast::HtmlToken;
+/// use sleek_parser::{HtmlTokenizer, ParseOptions};
+///
+/// let source = "<!DOCTYPE html><p>Hi";
+/// let mut tokenizer = HtmlTokenizer::new(source, ParseOptions::new());
+/// let Some(Ok(HtmlToken::DocType { span, .. })) = tokenizer.next() else {
+///     panic!("expected a doctype");
+/// };
+/// assert_eq!(&source[span.range()], "<!DOCTYPE html>");
+/// assert_eq!(tokenizer.count(), 3);
+/// ```
+pub struct HtmlTokenizer<'a> {
+    token_store: TokenStore,
+    iterator: QueueMatrix<Box<dyn Iterator<Item = char> + 'a>>,
+    /// Tokens and errors that were found but not returned yet.
+    pending: VecDeque<Result<HtmlToken, HtmlParseError>>,
+    finished: bool,
+}
+
+impl<'a> HtmlTokenizer<'a> {
+    /// Tokenize an HTML string.
+    /// Only the options that affect tokenizing are used, like the [whitespace](ParseOptions::whitespace) handling,
+    /// and the [fragment context](ParseOptions::fragment_context) to start in.
+    pub fn new(input: &'a str, options: ParseOptions) -> Self {
+        let mut token_store = TokenStore::new();
+        token_store.configure(&options);
+        token_store.pause_on_output();
+        let mut reader = token_store.reader();
+        let chars: Box<dyn Iterator<Item = char> + 'a> =
+            Box::new(input.chars().inspect(move |&ch| reader.read(ch)));
+        HtmlTokenizer {
+            token_store,
+            iterator: QueueIterator::new(MatrixIterator::new(chars, '\n')),
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+impl Iterator for HtmlTokenizer<'_> {
+    type Item = Result<HtmlToken, HtmlParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.finished {
+            tokenize(&mut self.token_store, &mut self.iterator);
+            let mut found: Vec<_> = take(&mut self.token_store.errors)
+                .into_iter()
+                .map(Err)
+                .chain(take(&mut self.token_store.tokens).into_iter().map(Ok))
+                .collect();
+            // Tokenizing stops without output at the end of the input, or when strict parsing gives up.
+            self.finished = found.is_empty()
+                || found
+                    .iter()
+                    .any(|item| matches!(item, Ok(token) if token.is_eof()));
+            found.sort_by_key(location);
+            self.pending.extend(found);
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// The location where a token starts, or of an error.
+fn location(item: &Result<HtmlToken, HtmlParseError>) -> [usize; 2] {
+    match item {
+        Ok(
+            HtmlToken::DocType { span, .. }
+            | HtmlToken::OpeningTag { span, .. }
+            | HtmlToken::ClosingTag { span, .. }
+            | HtmlToken::Text { span, .. }
+            | HtmlToken::Comment { span, .. },
+        ) => span.start,
+        Ok(HtmlToken::EOF { location }) => *location,
+        Err(error) => error.location,
+    }
+}
@@ -1,4 +1,8 @@
-#![feature(io_error_more)]
 mod html;
 
-pub use html::{parse_html_file, parse_html_input, HtmlParseError, HtmlParseResult};
+pub use html::{
+    parse_html_bytes, parse_html_file, parse_html_fragment, parse_html_input, parse_html_reader,
+    parse_html_strict, reparse_html, sniff_encoding, DiagnosticStyle, Encoding, HtmlFileError,
+    HtmlParseError, HtmlParseResult, HtmlReparseResult, HtmlTokenizer, ParseMode, ParseOptions,
+    Severity, TextEdit, Whitespace,
+};
@@ -0,0 +1,166 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, Read},
+    rc::Rc,
+};
+
+use super::MutableCountRef;
+
+/// The number of bytes read from the source at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Decodes characters from the start of some bytes, and returns the number of bytes used.
+/// Bytes that are not used are passed again with the next chunk.
+pub type Decode = Box<dyn Fn(&[u8], &mut VecDeque<char>) -> usize>;
+
+/// An iterator over the characters of a byte stream, read in chunks so that the full input is never in memory.
+/// The bytes are UTF-8 unless another [decoder](CharReader::with_decoder) is given.
+/// Characters split across chunks are joined, and invalid sequences are replaced with U+FFFD.
+///
+/// Reading stops at the first I/O error, which is kept so it can be checked after iteration.
+/// # Examples
+/// ```
+/// use sleek_utils::CharReader;
+///
+/// let reader = CharReader::new("héllo".as_bytes());
+/// let error = reader.error();
+///
+/// assert_eq!(reader.collect::<String>(), "héllo");
+/// assert!(error.borrow().is_none());
+/// ```
+pub struct CharReader<R: Read> {
+    source: R,
+    buffer: Box<[u8]>,
+    /// Bytes at the end of the last chunk that start a character, but do not complete it.
+    leftover: Vec<u8>,
+    decoded: VecDeque<char>,
+    decode: Decode,
+    done: bool,
+    error: MutableCountRef<Option<io::Error>>,
+}
+
+impl<R: Read> CharReader<R> {
+    pub fn new(source: R) -> Self {
+        Self::with_decoder(source, Box::new(decode_utf8))
+    }
+    /// Creates a reader for bytes in another encoding than UTF-8.
+    pub fn with_decoder(source: R, decode: Decode) -> Self {
+        CharReader {
+            source,
+            buffer: vec![0; CHUNK_SIZE].into_boxed_slice(),
+            leftover: vec![],
+            decoded: VecDeque::new(),
+            decode,
+            done: false,
+            error: Rc::new(RefCell::new(None)),
+        }
+    }
+    /// Returns a handle to the I/O error that stopped reading, if there is one.
+    pub fn error(&self) -> MutableCountRef<Option<io::Error>> {
+        self.error.clone()
+    }
+    /// Read and decode the next chunk of the source.
+    fn fill(&mut self) {
+        let count = loop {
+            match self.source.read(&mut self.buffer) {
+                Ok(count) => break count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    *self.error.borrow_mut() = Some(error);
+                    break 0;
+                }
+            }
+        };
+        if count == 0 {
+            self.done = true;
+            // The input ended in the middle of a character.
+            if !self.leftover.is_empty() {
+                self.leftover.clear();
+                self.decoded.push_back(char::REPLACEMENT_CHARACTER);
+            }
+            return;
+        }
+        self.leftover.extend_from_slice(&self.buffer[..count]);
+        self.decode();
+    }
+    /// Decode as much of the leftover bytes as possible.
+    fn decode(&mut self) {
+        let used = (self.decode)(&self.leftover, &mut self.decoded);
+        self.leftover.drain(..used);
+    }
+}
+
+/// Decodes UTF-8, replacing invalid sequences with U+FFFD.
+fn decode_utf8(mut bytes: &[u8], decoded: &mut VecDeque<char>) -> usize {
+    let length = bytes.len();
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                decoded.extend(text.chars());
+                return length;
+            }
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                // The bytes up to the error are always valid UTF-8.
+                if let Ok(text) = std::str::from_utf8(valid) {
+                    decoded.extend(text.chars());
+                }
+                match error.error_len() {
+                    Some(error_length) => {
+                        decoded.push_back(char::REPLACEMENT_CHARACTER);
+                        bytes = &rest[error_length..];
+                    }
+                    // The rest may be completed by the next chunk.
+                    None => return length - rest.len(),
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for CharReader<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.decoded.is_empty() && !self.done {
+            self.fill();
+        }
+        self.decoded.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use crate::CharReader;
+
+    /// A source that returns one byte at a time, so every multi-byte character is split.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.0.split_first() {
+                Some((byte, rest)) => {
+                    buf[0] = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn it_decodes_characters_across_chunks() {
+        let reader = CharReader::new(Trickle("a€𝄞é".as_bytes()));
+        assert_eq!(reader.collect::<String>(), "a€𝄞é");
+    }
+
+    #[test]
+    fn it_replaces_invalid_sequences() {
+        let reader = CharReader::new(Trickle(b"a\xffb\xe2\x82"));
+        assert_eq!(reader.collect::<String>(), "a\u{FFFD}b\u{FFFD}");
+    }
+}
@@ -1,14 +1,18 @@
 #![allow(incomplete_features)]
 #![feature(return_position_impl_trait_in_trait)]
 
+mod char_reader;
 mod high_order_iterator;
+mod line_index;
 mod matrix_iterator;
 mod node;
 mod queue_iterator;
 
 use std::{cell::RefCell, rc::Rc};
 
+pub use char_reader::{CharReader, Decode};
 pub use high_order_iterator::HigherOrderIterator;
+pub use line_index::LineIndex;
 pub use matrix_iterator::MatrixIterator;
 pub use node::Node;
 pub use queue_iterator::QueueIterator;
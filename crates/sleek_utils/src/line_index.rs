@@ -0,0 +1,323 @@
+use std::collections::VecDeque;
+
+/// The number of characters in the stretch of a line that a checkpoint covers.
+const STRETCH: usize = 32;
+/// The number of checkpoints a streaming index keeps for a line, so long lines are read in constant memory too.
+const KEPT_CHECKPOINTS: usize = 1024;
+
+/// A line of the indexed text.
+#[derive(Debug)]
+struct Line {
+    /// The byte offset of the start of the line.
+    start: usize,
+    /// The number of characters in the line, including the line break.
+    length: usize,
+    /// The totals of the wide characters in the line.
+    totals: Totals,
+    /// The checkpoints of the stretches of the line that have characters of more than one byte.
+    checkpoints: VecDeque<Checkpoint>,
+    /// The totals of the wide characters before the checkpoints, once the first ones are dropped.
+    dropped: Totals,
+    /// The first column that can be converted, which is after the dropped checkpoints.
+    first_column: usize,
+}
+
+/// The totals of the characters of more than one byte.
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    /// The bytes more than one per character.
+    extra: usize,
+    /// The characters that are four bytes, which are two UTF-16 code units.
+    surrogates: usize,
+}
+
+impl Totals {
+    fn add(&mut self, length: usize) {
+        self.extra += length - 1;
+        self.surrogates += usize::from(length == 4);
+    }
+}
+
+/// The lengths of the characters in a stretch of a line, with the totals of the line before it,
+/// so positions are found by counting from the closest checkpoint before them.
+#[derive(Debug)]
+struct Checkpoint {
+    /// The column of the first character in the stretch.
+    column: usize,
+    /// The totals of the characters before the stretch.
+    totals: Totals,
+    /// The length in bytes of each character in the stretch less one, in two bits each.
+    lengths: u64,
+}
+
+impl Checkpoint {
+    /// The length in bytes of a character in the stretch.
+    fn length(&self, index: usize) -> usize {
+        1 + (self.lengths >> (2 * index) & 0b11) as usize
+    }
+    /// The totals of the characters before a column in or after the stretch.
+    fn totals(&self, column: usize) -> Totals {
+        let mut totals = self.totals;
+        (0..(column - self.column).min(STRETCH)).for_each(|index| totals.add(self.length(index)));
+        totals
+    }
+}
+
+impl Line {
+    fn new(start: usize) -> Self {
+        Line {
+            start,
+            length: 0,
+            totals: Totals::default(),
+            checkpoints: VecDeque::new(),
+            dropped: Totals::default(),
+            first_column: 1,
+        }
+    }
+    /// The totals of the wide characters before a column.
+    fn totals(&self, column: usize) -> Totals {
+        match self.checkpoints.back() {
+            // Usually the position is after the last stretch, e.g. at the end of the line being read.
+            Some(last) if last.column + STRETCH <= column => self.totals,
+            _ => match self
+                .checkpoints
+                .partition_point(|checkpoint| checkpoint.column < column)
+            {
+                0 => self.dropped,
+                before => self.checkpoints[before - 1].totals(column),
+            },
+        }
+    }
+    /// The column at a count of units from the start of the line, if it is not inside a character.
+    /// `at` gives the units before a column from the totals before it, and `units` the units of a character of a length.
+    fn column(
+        &self,
+        count: usize,
+        at: impl Fn(usize, Totals) -> usize,
+        units: impl Fn(usize) -> usize,
+    ) -> Option<usize> {
+        let before = self
+            .checkpoints
+            .partition_point(|checkpoint| at(checkpoint.column, checkpoint.totals) <= count);
+        let (mut column, mut counted) = match before.checked_sub(1) {
+            Some(last) => {
+                let checkpoint = &self.checkpoints[last];
+                (checkpoint.column, at(checkpoint.column, checkpoint.totals))
+            }
+            None => (self.first_column, at(self.first_column, self.dropped)),
+        };
+        if let Some(last) = before.checked_sub(1) {
+            let checkpoint = &self.checkpoints[last];
+            for index in 0..STRETCH {
+                if counted >= count {
+                    break;
+                }
+                counted += units(checkpoint.length(index));
+                column += 1;
+            }
+        }
+        // The characters after the stretch, up to the next checkpoint, are one unit each.
+        let column = column + count.checked_sub(counted)?;
+        (column >= self.first_column && column <= self.length + 1).then_some(column)
+    }
+}
+
+/// Converts between positions in a text: byte offsets, and `[line, column]` pairs with columns counted in characters or in UTF-16 code units.
+/// Lines and columns start at 1, like [`MatrixIterator::locus`](crate::MatrixIterator::locus).
+///
+/// The index can be built from the whole text, or a character at a time while the text is read.
+/// Positions can be converted as soon as the characters before them are pushed.
+/// # Examples
+/// ```
+/// use sleek_utils::LineIndex;
+///
+/// let index = LineIndex::new("a\n€𝄞b");
+///
+/// // The `b` is after a 3 byte and a 4 byte character, which is 2 UTF-16 code units.
+/// assert_eq!(index.offset([2, 3]), Some(9));
+/// assert_eq!(index.position(9), Some([2, 3]));
+/// assert_eq!(index.utf16_column([2, 3]), Some(4));
+/// assert_eq!(index.from_utf16([2, 4]), Some([2, 3]));
+/// ```
+#[derive(Debug)]
+pub struct LineIndex {
+    lines: VecDeque<Line>,
+    /// The number of the first line in the index.
+    first: usize,
+    /// The number of lines to keep as the text is read, or `None` to keep every line.
+    kept: Option<usize>,
+}
+
+impl Default for LineIndex {
+    fn default() -> Self {
+        LineIndex {
+            lines: VecDeque::from([Line::new(0)]),
+            first: 1,
+            kept: None,
+        }
+    }
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut index = Self::default();
+        text.chars().for_each(|ch| index.push(ch));
+        index
+    }
+    /// An index that only keeps the last lines read, at least one, and the end of a long line,
+    /// so a text of any size is read in constant memory.
+    /// Positions before them cannot be converted.
+    pub fn streaming(lines: usize) -> Self {
+        LineIndex {
+            kept: Some(lines.max(1)),
+            ..Self::default()
+        }
+    }
+    /// Add the next character of the text.
+    pub fn push(&mut self, ch: char) {
+        let streaming = self.kept.is_some();
+        let line = self.lines.back_mut().expect("there is always a line");
+        line.length += 1;
+        let length = ch.len_utf8();
+        if length > 1 {
+            let column = line.length;
+            let stretch = column - (column - 1) % STRETCH;
+            if line
+                .checkpoints
+                .back()
+                .is_none_or(|last| last.column != stretch)
+            {
+                line.checkpoints.push_back(Checkpoint {
+                    column: stretch,
+                    totals: line.totals,
+                    lengths: 0,
+                });
+                if streaming && line.checkpoints.len() > KEPT_CHECKPOINTS {
+                    let dropped = line
+                        .checkpoints
+                        .pop_front()
+                        .expect("a checkpoint was pushed");
+                    line.first_column = dropped.column + STRETCH;
+                    line.dropped = dropped.totals(line.first_column);
+                }
+            }
+            let checkpoint = line
+                .checkpoints
+                .back_mut()
+                .expect("a checkpoint was pushed");
+            checkpoint.lengths |= ((length - 1) as u64) << (2 * (column - stretch));
+            line.totals.add(length);
+        }
+        if ch == '\n' {
+            let start = self.len();
+            self.lines.push_back(Line::new(start));
+            if self.kept.is_some_and(|kept| self.lines.len() > kept) {
+                self.lines.pop_front();
+                self.first += 1;
+            }
+        }
+    }
+    /// The number of bytes pushed.
+    pub fn len(&self) -> usize {
+        let line = self.lines.back().expect("there is always a line");
+        line.start + line.length + line.totals.extra
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The byte offset of a position, which can be the position after the last character.
+    pub fn offset(&self, [line, column]: [usize; 2]) -> Option<usize> {
+        let line = self.line(line, column)?;
+        Some(line.start + column - 1 + line.totals(column).extra)
+    }
+    /// The position of a byte offset, if it is at the start of a character.
+    pub fn position(&self, offset: usize) -> Option<[usize; 2]> {
+        let index = self.lines.partition_point(|line| line.start <= offset);
+        let line = &self.lines[index.checked_sub(1)?];
+        let column = line.column(
+            offset - line.start,
+            |column, totals| column - 1 + totals.extra,
+            |length| length,
+        )?;
+        Some([self.first + index - 1, column])
+    }
+    /// The column of a position in UTF-16 code units, as used by the Language Server Protocol.
+    pub fn utf16_column(&self, [line, column]: [usize; 2]) -> Option<usize> {
+        let line = self.line(line, column)?;
+        Some(column + line.totals(column).surrogates)
+    }
+    /// The position of a column in UTF-16 code units, if it is not inside a surrogate pair.
+    pub fn from_utf16(&self, [number, utf16_column]: [usize; 2]) -> Option<[usize; 2]> {
+        let line = self.lines.get(number.checked_sub(self.first)?)?;
+        let column = line.column(
+            utf16_column,
+            |column, totals| column + totals.surrogates,
+            |length| 1 + usize::from(length == 4),
+        )?;
+        Some([number, column])
+    }
+    fn line(&self, number: usize, column: usize) -> Option<&Line> {
+        let line = self.lines.get(number.checked_sub(self.first)?)?;
+        (column >= line.first_column && column <= line.length + 1).then_some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LineIndex;
+
+    use super::KEPT_CHECKPOINTS;
+
+    #[test]
+    fn it_converts_every_position() {
+        // The last line is long enough for a few checkpoints.
+        let text = &format!("ab\nç𝄞\n\nx{}y", "aé€𝄞".repeat(40));
+        let index = LineIndex::new(text);
+        assert_eq!(index.len(), text.len());
+        for (offset, _) in text.char_indices().chain([(text.len(), ' ')]) {
+            let position = index.position(offset).unwrap();
+            assert_eq!(index.offset(position), Some(offset));
+            let utf16 = index.utf16_column(position).unwrap();
+            assert_eq!(index.from_utf16([position[0], utf16]), Some(position));
+        }
+    }
+
+    #[test]
+    fn it_rejects_positions_inside_characters() {
+        let index = LineIndex::new("𝄞a");
+        assert_eq!(index.position(2), None);
+        assert_eq!(index.from_utf16([1, 2]), None);
+        assert_eq!(index.from_utf16([1, 3]), Some([1, 2]));
+        assert_eq!(index.offset([1, 4]), None);
+        assert_eq!(index.offset([2, 1]), None);
+    }
+
+    #[test]
+    fn it_keeps_the_last_two_lines_when_streaming() {
+        let mut index = LineIndex::streaming(2);
+        "a\nbé\ncd".chars().for_each(|ch| index.push(ch));
+        assert_eq!(index.len(), 8);
+        assert_eq!(index.offset([1, 1]), None);
+        assert_eq!(index.offset([2, 3]), Some(5));
+        assert_eq!(index.position(5), Some([2, 3]));
+        assert_eq!(index.position(7), Some([3, 2]));
+        assert_eq!(index.position(0), None);
+    }
+
+    #[test]
+    fn it_reads_a_long_line_in_constant_memory_when_streaming() {
+        let mut index = LineIndex::streaming(2);
+        let length = 3_000_000;
+        (0..length).for_each(|_| index.push('é'));
+        index.push('𝄞');
+        assert!(index.lines[0].checkpoints.len() <= KEPT_CHECKPOINTS);
+        assert_eq!(index.len(), 2 * length + 4);
+        assert_eq!(index.offset([1, length]), Some(2 * length - 2));
+        assert_eq!(index.position(2 * length), Some([1, length + 1]));
+        assert_eq!(index.position(2 * length + 4), Some([1, length + 2]));
+        assert_eq!(index.utf16_column([1, length + 2]), Some(length + 3));
+        assert_eq!(index.from_utf16([1, length + 1]), Some([1, length + 1]));
+        assert_eq!(index.offset([1, 1]), None);
+        assert_eq!(index.position(0), None);
+    }
+}